@@ -0,0 +1,156 @@
+//! Records the violations that have been accepted, so that only violations
+//! that aren't in the baseline fail a run. This lets ns-rules be adopted in a
+//! codebase that already breaks its rules, while stopping things getting worse.
+
+use crate::{
+    compare::{self, Edge},
+    Report,
+};
+use miette::Diagnostic;
+use serde_json::json;
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, Diagnostic)]
+#[diagnostic(
+    code(baseline_error),
+    help(
+        "the baseline, at {:?}, must be written by --baseline-update or be a \
+         report from --format json",
+        self.path
+    )
+)]
+#[error("there was a problem with the baseline")]
+pub(crate) struct Error {
+    path: PathBuf,
+    source: Problem,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum Problem {
+    #[error(transparent)]
+    ReadFailure(#[from] compare::Problem),
+    #[error("the file could not be written")]
+    WriteFailure { source: io::Error },
+}
+
+#[derive(Debug)]
+pub(crate) struct Baseline {
+    /// The accepted violations, by edge, so that moving code around a file
+    /// doesn't make them new again.
+    accepted: BTreeSet<Edge>,
+}
+
+impl Baseline {
+    /// Reads the baseline at `path`. A baseline that doesn't exist yet is
+    /// empty if it is to be `created`, and is otherwise an error.
+    pub(crate) fn read(path: &Path, created: bool) -> Result<Self, Error> {
+        if created && !path.exists() {
+            return Ok(Self {
+                accepted: BTreeSet::new(),
+            });
+        }
+
+        let accepted = compare::read_edges(path).map_err(|source| Error {
+            path: path.into(),
+            source: source.into(),
+        })?;
+
+        Ok(Self { accepted })
+    }
+
+    /// Removes the accepted violations from `report`.
+    pub(crate) fn apply(&self, report: &mut Report) {
+        report.accept(&self.accepted);
+    }
+
+    /// Adds the violations in `report` that aren't yet accepted to the
+    /// baseline at `path`, returning how many were added. Every accepted
+    /// violation is kept, even if it's since been fixed, so that nothing is
+    /// accepted, or forgotten, other than what is in the report.
+    pub(crate) fn update(
+        mut self,
+        path: &Path,
+        report: &Report,
+    ) -> Result<usize, Error> {
+        let added = self.merge(report.violation_edges());
+        let json = format!("{:#}\n", self.to_json());
+        fs::write(path, json).map_err(|source| Error {
+            path: path.into(),
+            source: Problem::WriteFailure { source },
+        })?;
+
+        Ok(added)
+    }
+
+    fn merge(&mut self, violations: impl Iterator<Item = Edge>) -> usize {
+        let before = self.accepted.len();
+        self.accepted.extend(violations);
+        self.accepted.len() - before
+    }
+
+    /// The baseline in the form of a JSON report, so that it can be read as a
+    /// previous report by --compare.
+    fn to_json(&self) -> serde_json::Value {
+        let violations = self
+            .accepted
+            .iter()
+            .map(|(namespace, reference)| {
+                json!({ "namespace": namespace, "reference": reference })
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "violations": violations })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn edges(edges: &[(&str, &str)]) -> BTreeSet<Edge> {
+        edges
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn updates_keep_the_accepted_violations() {
+        let mut baseline = Baseline {
+            accepted: edges(&[("a.core", "a.db"), ("a.db", "a.http")]),
+        };
+
+        let added = baseline.merge(
+            edges(&[("a.db", "a.http"), ("a.db", "a.util")]).into_iter(),
+        );
+
+        assert_eq!(added, 1);
+        assert_eq!(
+            baseline.accepted,
+            edges(&[
+                ("a.core", "a.db"),
+                ("a.db", "a.http"),
+                ("a.db", "a.util")
+            ])
+        );
+    }
+
+    #[test]
+    fn baselines_are_json_reports() {
+        let baseline = Baseline {
+            accepted: edges(&[("a.core", "a.db")]),
+        };
+
+        assert_eq!(
+            baseline.to_json(),
+            json!({
+                "violations": [{ "namespace": "a.core", "reference": "a.db" }]
+            })
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Compares the violations of a run with those of a previous JSON report, to
+//! track how the architecture drifts over time.
+
+use crate::Report;
+use miette::Diagnostic;
+use serde_json::json;
+use std::{
+    collections::BTreeSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// A reference that breaks a rule, from the referencing namespace to the
+/// referenced namespace. Violations are compared by edge, rather than by
+/// position, so that moving code around a file doesn't change the outcome.
+pub(crate) type Edge = (String, String);
+
+#[derive(Debug, Error, Diagnostic)]
+#[diagnostic(
+    code(comparison_error),
+    help("the previous report, at {:?}, must be from --format json", self.path)
+)]
+#[error("there was a problem reading the previous report")]
+pub(crate) struct Error {
+    path: PathBuf,
+    source: Problem,
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum Problem {
+    #[error("the file could not be read")]
+    ReadFailure {
+        #[from]
+        source: io::Error,
+    },
+    #[error("the file does not contain valid JSON")]
+    ParseFailure {
+        #[from]
+        source: serde_json::Error,
+    },
+    #[error("the report does not contain a list of violations")]
+    NotAReport,
+}
+
+#[derive(Debug)]
+pub(crate) struct Comparison {
+    /// Violations that weren't in the previous report.
+    new: BTreeSet<Edge>,
+    /// Violations in the previous report that are now gone.
+    fixed: BTreeSet<Edge>,
+    /// Violations in both reports.
+    persisting: BTreeSet<Edge>,
+}
+
+impl Comparison {
+    /// Compares the violations in `report` with those in the JSON report at
+    /// `previous`.
+    pub(crate) fn new(previous: &Path, report: &Report) -> Result<Self, Error> {
+        let edges = read_edges(previous).map_err(|source| Error {
+            path: previous.into(),
+            source,
+        })?;
+
+        Ok(Self::of(edges, report.violation_edges().collect()))
+    }
+
+    fn of(previous: BTreeSet<Edge>, current: BTreeSet<Edge>) -> Self {
+        Self {
+            new: current.difference(&previous).cloned().collect(),
+            fixed: previous.difference(&current).cloned().collect(),
+            persisting: current.intersection(&previous).cloned().collect(),
+        }
+    }
+
+    /// Only new violations fail the comparison.
+    pub(crate) fn exit_status(&self) -> i32 {
+        if self.new.is_empty() {
+            0
+        } else {
+            1
+        }
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let edges = |edges: &BTreeSet<Edge>| {
+            edges
+                .iter()
+                .map(|(namespace, reference)| {
+                    json!({ "namespace": namespace, "reference": reference })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        json!({
+            "new": edges(&self.new),
+            "fixed": edges(&self.fixed),
+            "persisting": edges(&self.persisting),
+        })
+    }
+}
+
+impl Comparison {
+    /// Each violation as a JSON object with its status, for printing one per
+    /// line.
+    pub(crate) fn to_ndjson(&self) -> Vec<serde_json::Value> {
+        let sections = [
+            ("new", &self.new),
+            ("fixed", &self.fixed),
+            ("persisting", &self.persisting),
+        ];
+        sections
+            .iter()
+            .flat_map(|&(status, edges)| {
+                edges.iter().map(move |(namespace, reference)| {
+                    json!({
+                        "status": status,
+                        "namespace": namespace,
+                        "reference": reference,
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("NEW", &self.new),
+            ("FIXED", &self.fixed),
+            ("PERSISTING", &self.persisting),
+        ];
+        for (label, edges) in sections {
+            for (namespace, reference) in edges {
+                writeln!(f, "{} {} -> {}", label, namespace, reference)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The violations in the JSON report at `path`.
+pub(crate) fn read_edges(path: &Path) -> Result<BTreeSet<Edge>, Problem> {
+    let report: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(path)?)?;
+    let violations =
+        report["violations"].as_array().ok_or(Problem::NotAReport)?;
+
+    violations
+        .iter()
+        .map(|violation| {
+            match (
+                violation["namespace"].as_str(),
+                violation["reference"].as_str(),
+            ) {
+                (Some(namespace), Some(reference)) => {
+                    Ok((namespace.to_owned(), reference.to_owned()))
+                }
+                _ => Err(Problem::NotAReport),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn edges(edges: &[(&str, &str)]) -> BTreeSet<Edge> {
+        edges
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn violations_are_compared_by_edge() {
+        let comparison = Comparison::of(
+            edges(&[("a.core", "a.db"), ("a.db", "a.http")]),
+            edges(&[("a.db", "a.http"), ("a.db", "a.util")]),
+        );
+
+        assert_eq!(comparison.new, edges(&[("a.db", "a.util")]));
+        assert_eq!(comparison.fixed, edges(&[("a.core", "a.db")]));
+        assert_eq!(comparison.persisting, edges(&[("a.db", "a.http")]));
+        assert_eq!(comparison.exit_status(), 1);
+    }
+
+    #[test]
+    fn fixed_violations_alone_pass() {
+        let comparison =
+            Comparison::of(edges(&[("a.core", "a.db")]), edges(&[]));
+
+        assert_eq!(comparison.exit_status(), 0);
+    }
+}
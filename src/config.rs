@@ -1,25 +1,113 @@
-use std::{fs, io, path::{Path, PathBuf}, collections::BTreeMap};
+use std::{env, fmt, fs, io, iter, mem, path::{Path, PathBuf}, collections::{BTreeMap, BTreeSet}};
 use thiserror::Error;
-use miette::{Diagnostic};
-use edn_rs::{Edn, EdnError};
+use miette::{Diagnostic, DiagnosticSnippet, NamedSource, SourceSpan};
+use edn_rs::{Edn, EdnError, Map, Vector};
+use ignore::WalkBuilder;
+use tracing::debug;
 
-use crate::{NamespaceMatcher, Report, Rule};
+use crate::{report::{Warning, WarningCode}, NamespaceMatcher, PathMatcher, Report, Rule, Selector, Severity, TagCondition};
 
 #[derive(Debug, Default)]
 pub(crate) struct Config {
-    pub source_dirs: Vec<String>,
+    pub source_dirs: Vec<SourceDir>,
     pub rules: Vec<Rule>,
+    /// Files with this text in their first lines are generated, so are skipped.
+    pub skip_marker: Option<String>,
+    /// Whether files are known by the namespace their `ns` form declares,
+    /// rather than the one their path implies.
+    pub match_declared_ns: bool,
 }
 
-#[derive(Debug, Error, Diagnostic)]
-#[diagnostic(
-    code(configuration_error),
-    help("the configuration file is at {:?}", self.path)
-)]
+impl Config {
+    /// Moves the relative source directories, and the directories denied by
+    /// ':deny-src-dirs', under `root`, so that the configuration can be used
+    /// from outside the directory it was written for. Absolute directories are
+    /// left as they are.
+    pub(crate) fn move_under(&mut self, root: &Path) {
+        for source_dir in self.source_dirs.iter_mut().filter(|dir| Path::new(&dir.path).is_relative()) {
+            let moved = root.join(&source_dir.path).to_string_lossy().into_owned();
+            for rule in self.rules.iter_mut() {
+                for dir in rule.deny_src_dirs.iter_mut().filter(|dir| **dir == source_dir.path) {
+                    dir.clone_from(&moved);
+                }
+            }
+            source_dir.path = moved;
+        }
+    }
+}
+
+/// A directory searched for source files, which is either written as a string,
+/// or as a map of the directory's `:path` to the subdirectories to `:exclude`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SourceDir {
+    pub path: String,
+    /// Subdirectories that aren't searched, relative to `path`.
+    pub exclude: Vec<String>,
+}
+
+impl SourceDir {
+    /// Whether the path is a glob, such as `modules/*/src`, to be expanded into
+    /// the directories it matches.
+    pub(crate) fn is_glob(&self) -> bool {
+        self.path.contains(['*', '?', '[', '{'])
+    }
+
+    /// Whether `path` is within one of the excluded subdirectories.
+    pub(crate) fn excludes(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|excluded| path.starts_with(Path::new(&self.path).join(excluded)))
+    }
+}
+
+impl AsRef<Path> for SourceDir {
+    fn as_ref(&self) -> &Path {
+        Path::new(&self.path)
+    }
+}
+
+impl From<&str> for SourceDir {
+    fn from(path: &str) -> Self {
+        Self { path: path.into(), exclude: vec![] }
+    }
+}
+
+#[derive(Debug, Error)]
 #[error("there was a problem loading the configuration file")]
 pub(crate) struct Error {
     path: PathBuf,
     source: Problem,
+    /// The configuration and the position of a syntax error in it, when the
+    /// position is known.
+    syntax_error: Option<Box<SyntaxError>>,
+}
+
+#[derive(Debug)]
+struct SyntaxError {
+    src: NamedSource,
+    /// The line containing the error.
+    line: SourceSpan,
+    at: SourceSpan,
+}
+
+impl Diagnostic for Error {
+    fn code<'a>(&'a self) -> Box<dyn fmt::Display + 'a> {
+        Box::new("configuration_error")
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(format!("the configuration file is at {:?}", self.path)))
+    }
+
+    fn snippets<'a>(&'a self) -> Option<Box<dyn Iterator<Item = DiagnosticSnippet<'a>> + 'a>> {
+        let syntax_error = self.syntax_error.as_ref()?;
+        let snippet = DiagnosticSnippet {
+            message: None,
+            source: &syntax_error.src,
+            context: syntax_error.line.clone(),
+            highlights: Some(vec![(Some("this form could not be read".into()), syntax_error.at.clone())]),
+        };
+
+        Some(Box::new(iter::once(snippet)))
+    }
 }
 
 #[derive(Debug, Error)]
@@ -29,16 +117,28 @@ pub(crate) enum Problem {
         #[from]
         source: io::Error,
     },
-    #[error("the file not does not contain valid EDN")]
+    #[error("the file does not contain valid EDN")]
     ParseFailure {
         #[from]
         source: EdnError,
     },
+    #[error("the environment variable '{name}', read by #ns-rules/env, is not set")]
+    MissingEnvVar {
+        name: String,
+    },
+    #[error("#ns-rules/env at byte {offset} must be followed by the name of an environment variable as a string")]
+    BadEnvTag {
+        offset: usize,
+    },
     #[error("the top level form must be an map")]
     NotAMap,
+    #[error("the configuration must be a map under the key '{key}'")]
+    MissingConfigKey {
+        key: String,
+    },
     #[error("the required key ':src-dirs' is missing")]
     MissingSrcDirs,
-    #[error("':src-dirs' muat be a vector of strings")]
+    #[error("':src-dirs' muat be a vector of strings, or of maps with a ':path' string and an ':exclude' vector of strings")]
     BadSrcDirs,
     #[error("':src-dirs' must contain at least 1 directory")]
     EmptySrcDirs,
@@ -46,7 +146,7 @@ pub(crate) enum Problem {
     MissingRules,
     #[error("':rules' must be a vector containing an even number of forms")]
     BadRuleVector,
-    #[error("the namespace pattern for rule {position} is invalid, namespace patterns must be symbols")]
+    #[error("the pattern for rule {position} is invalid, patterns must be namespace symbols or path strings")]
     BadNsPattern {
         position: usize,
     },
@@ -54,21 +154,136 @@ pub(crate) enum Problem {
     BadRule {
         ns_pattern: String,
         detail: String,
-    }
+    },
+    #[error("':skip-marker' must be a non-empty string")]
+    BadSkipMarker,
+    #[error("':include-glob' must be a string containing a valid glob")]
+    BadIncludeGlob,
+    #[error("':forbid' must be a vector of pairs of namespace symbols, such as [a.* b.*]")]
+    BadForbid,
+    #[error("':match-declared-ns' must be true or false")]
+    BadMatchDeclaredNs,
+    #[error("':layers' must be a vector of namespace symbols")]
+    BadLayers,
+    #[error("':min-version' must be a version string, such as \"1.2.0\"")]
+    BadMinVersion,
+    #[error("the configuration requires ns-rules {required} or later, but this is ns-rules {current}")]
+    VersionTooOld {
+        required: String,
+        current: &'static str,
+    },
+    #[error("':templates' must be a map of template keywords to maps, which can't themselves use ':extends'")]
+    BadTemplates,
+    #[error("the rule '{ns_pattern}' extends the template '{template}', which is not defined in ':templates'")]
+    UnknownTemplate {
+        ns_pattern: String,
+        template: String,
+    },
+    #[error("':profiles' must be a map of profile keywords to maps")]
+    BadProfiles,
+    #[error("the profile '{profile}' is not defined in ':profiles'")]
+    UnknownProfile {
+        profile: String,
+    },
+    #[error("{location} contains the unknown key {key}{}", suggestion(.expected))]
+    UnknownKey {
+        location: String,
+        key: String,
+        expected: Option<&'static str>,
+    },
+}
+
+fn suggestion(expected: &Option<&'static str>) -> String {
+    expected.map(|key| format!(", did you mean {}?", key)).unwrap_or_default()
 }
 
+/// A key that the configuration accepts, with the type of its value and what
+/// it does, as printed by --config-schema.
+struct Key {
+    name: &'static str,
+    kind: &'static str,
+    doc: &'static str,
+}
+
+/// The keys allowed at the top level of the configuration. All but the last two
+/// may be overridden by a profile.
+const CONFIG_KEYS: &[Key] = &[
+    Key { name: ":src-dirs", kind: "vector of strings or maps", doc: "The directories containing the source code, which may be globs" },
+    Key { name: ":rules", kind: "vector of patterns and maps", doc: "Each namespace pattern, or path glob, followed by its rule" },
+    Key { name: ":forbid", kind: "vector of pairs of symbols", doc: "Pairs of namespace patterns, the first of which may not reference the second" },
+    Key { name: ":layers", kind: "vector of symbols", doc: "Namespace patterns, from the bottom layer up, for :left-of-self" },
+    Key { name: ":skip-marker", kind: "string", doc: "Skips the files with the marker in their first 5 lines" },
+    Key { name: ":match-declared-ns", kind: "boolean", doc: "Knows files by the namespace their ns form declares, rather than their path" },
+    Key { name: ":templates", kind: "map of keywords to maps", doc: "Rules that other rules can build on with :extends" },
+    Key { name: ":include-glob", kind: "string", doc: "Files of further :rules, relative to the configuration, added in order of path" },
+    Key { name: ":profiles", kind: "map of keywords to maps", doc: "Overrides of the keys above, selected with --profile" },
+    Key { name: ":min-version", kind: "string", doc: "The lowest version of ns-rules that can check the configuration" },
+];
+/// The keys allowed in the body of a rule.
+const RULE_KEYS: &[Key] = &[
+    Key { name: ":restrict-to", kind: "vector of symbols, strings, keywords or lists", doc: "The only namespaces that may be referenced, by pattern or path glob, with :self, :left-of-self, :same-or-lower-version, (:transitive pattern) and !negations" },
+    Key { name: ":deny", kind: "vector of symbols", doc: "Namespaces that may never be referenced, even from outside the source tree" },
+    Key { name: ":deny-src-dirs", kind: "vector of strings", doc: "Source directories whose namespaces may not be referenced" },
+    Key { name: ":deny-tagged", kind: "vector of keywords", doc: "Tags whose namespaces may not be referenced" },
+    Key { name: ":must-reference", kind: "vector of symbols", doc: "Patterns that every namespace selected must reference a namespace of" },
+    Key { name: ":severity", kind: "keyword", doc: "Whether violations fail the run, with :error, or are only reported, with :warn" },
+    Key { name: ":when-path", kind: "string", doc: "Narrows the rule to files whose path matches the glob" },
+    Key { name: ":tagged", kind: "keyword", doc: "Narrows the rule to namespaces with the tag, or without it when written :!tag" },
+    Key { name: ":allow-self", kind: "boolean", doc: "Whether namespaces may reference those selected by the same rule, which they may by default" },
+    Key { name: ":allow-same-root", kind: "boolean", doc: "Allows references to namespaces with the same first segment" },
+    Key { name: ":allow-parents", kind: "boolean", doc: "Allows references to the namespaces that a namespace is nested in" },
+    Key { name: ":no-external", kind: "boolean", doc: "Forbids references outside the source tree, unless allowed by :restrict-to" },
+    Key { name: ":tags", kind: "vector of keywords", doc: "Categories of the rule, by which a subset of the rules can be run with --tag" },
+    Key { name: ":extends", kind: "keyword", doc: "The template in :templates that the rule builds on" },
+    Key { name: ":doc", kind: "string", doc: "Documents the rule, for --explain and verbose output" },
+];
+/// The keys allowed in a file included by ':include-glob'.
+const FRAGMENT_KEYS: &[Key] = &[
+    Key { name: ":rules", kind: "vector of patterns and maps", doc: "Each namespace pattern, or path glob, followed by its rule" },
+];
+/// The keys allowed in a source directory written as a map.
+const SRC_DIR_KEYS: &[Key] = &[
+    Key { name: ":path", kind: "string", doc: "The directory, which may be a glob" },
+    Key { name: ":exclude", kind: "vector of strings", doc: "Globs of the subdirectories to leave out" },
+];
+/// The keys of a rule whose vectors are combined with those of the template
+/// that the rule extends, rather than replacing them.
+const COMBINED_RULE_KEYS: &[&str] = &[":restrict-to", ":deny", ":deny-src-dirs", ":deny-tagged", ":must-reference", ":tags"];
+
+/// Reads the configuration from the file at `path`.
+///
+/// The configuration may be nested under `key` in a larger map, as is the case
+/// when it is kept in a `deps.edn` file. Without a key, the configuration in a
+/// file named `deps.edn` is read from under `:ns-rules`.
 pub(crate) fn read_file<P: AsRef<Path>>(
-    path: P, report: &mut Report
+    path: P, key: Option<&str>, profile: Option<&str>, report: &mut Report
 ) -> Result<Config, Error> {
-    let config_edn: Edn = fs::read_to_string(&path)
-        .map_err(|err| error(&path, err.into()))?.parse()
-        .map_err(|err: EdnError| error(&path, err.into()))?;
+    debug!(path = %path.as_ref().display(), key, profile, "loading configuration");
+    let mut config_map = read_map(&path)?;
 
-    let mut config_map = if let Edn::Map(config_map) = config_edn {
-        config_map.to_map()
-    } else {
-        Err(error(&path, Problem::NotAMap))?
-    };
+    let is_deps_edn = path.as_ref().file_name().is_some_and(|name| name == "deps.edn");
+    let key = key.or(if is_deps_edn { Some(":ns-rules") } else { None });
+    if let Some(key) = key {
+        let key = format!(":{}", key.trim_start_matches(':'));
+        config_map = match config_map.remove(&key) {
+            Some(Edn::Map(config_map)) => config_map.to_map(),
+            _ => Err(error(&path, Problem::MissingConfigKey { key }))?,
+        };
+    }
+    // The version is checked first, as a configuration written for a newer
+    // version may well contain keys this version doesn't know.
+    check_min_version(config_map.remove(":min-version"), env!("CARGO_PKG_VERSION"))
+        .map_err(|problem| error(&path, problem))?;
+    check_keys(&config_map, CONFIG_KEYS, "the configuration")
+        .map_err(|problem| error(&path, problem))?;
+
+    let profiles = config_map.remove(":profiles");
+    if let Some(profile) = profile {
+        let overrides = select_profile(profiles, profile)
+            .map_err(|problem| error(&path, problem))?;
+        config_map = merge_config(config_map, overrides)
+            .map_err(|problem| error(&path, problem))?;
+    }
 
     let source_dirs = config_map.remove(":src-dirs")
         .ok_or(error(&path, Problem::MissingSrcDirs))?;
@@ -77,7 +292,7 @@ pub(crate) fn read_file<P: AsRef<Path>>(
         dir_list.to_vec()
             .into_iter()
             .map(expect_src_dir)
-            .collect::<Result<Vec<String>, Problem>>()
+            .collect::<Result<Vec<SourceDir>, Problem>>()
             .map_err(|err| error(&path, err))?
     } else {
         Err(error(&path, Problem::BadSrcDirs))?
@@ -87,65 +302,582 @@ pub(crate) fn read_file<P: AsRef<Path>>(
         Err(error(&path, Problem::EmptySrcDirs))?
     }
 
-    let rules = config_map.remove(":rules")
-        .ok_or(error(&path, Problem::MissingRules))?;
+    let layers = match config_map.remove(":layers") {
+        Some(edn) => parse_layers(edn).map_err(|err| error(&path, err))?,
+        None => Vec::new(),
+    };
+
+    let skip_marker = match config_map.remove(":skip-marker") {
+        Some(Edn::Str(marker)) if !marker.is_empty() => Some(marker),
+        Some(_) => Err(error(&path, Problem::BadSkipMarker))?,
+        None => None,
+    };
 
-    let rules = if let Edn::Vector(rules) = rules {
-        let rules = rules.to_vec();
-        if rules.len() % 2 != 0 {
-            Err(error(&path, Problem::BadRuleVector))?
+    let match_declared_ns = match config_map.remove(":match-declared-ns") {
+        Some(Edn::Bool(match_declared_ns)) => match_declared_ns,
+        Some(_) => Err(error(&path, Problem::BadMatchDeclaredNs))?,
+        None => false,
+    };
+
+    let templates = match config_map.remove(":templates") {
+        Some(edn) => parse_templates(edn).map_err(|err| error(&path, err))?,
+        None => BTreeMap::new(),
+    };
+
+    let include_glob = match config_map.remove(":include-glob") {
+        Some(Edn::Str(glob)) => Some(glob),
+        Some(_) => Err(error(&path, Problem::BadIncludeGlob))?,
+        None => None,
+    };
+
+    // The rules of the configuration come first, followed by those of each
+    // included file in order of path, so that the order is the same wherever
+    // the files are found.
+    let mut rules = vec![];
+    match config_map.remove(":rules") {
+        Some(edn) => parse_rules(edn, &templates, &layers, &mut rules, report).map_err(|problem| error(&path, problem))?,
+        None if include_glob.is_some() || config_map.contains_key(":forbid") => {}
+        None => Err(error(&path, Problem::MissingRules))?,
+    }
+    if let Some(glob) = include_glob {
+        for fragment in find_fragments(path.as_ref(), &glob).map_err(|problem| error(&path, problem))? {
+            debug!(path = %fragment.display(), "including rules");
+            let mut fragment_map = read_map(&fragment)?;
+            check_keys(&fragment_map, FRAGMENT_KEYS, "an included file").map_err(|problem| error(&fragment, problem))?;
+            let edn = fragment_map.remove(":rules").ok_or(error(&fragment, Problem::MissingRules))?;
+            parse_rules(edn, &templates, &layers, &mut rules, report).map_err(|problem| error(&fragment, problem))?;
         }
+    }
+    if let Some(edn) = config_map.remove(":forbid") {
+        parse_forbidden_pairs(edn, &mut rules).map_err(|problem| error(&path, problem))?;
+    }
+    resolve_transitive_allows(&mut rules).map_err(|problem| error(&path, problem))?;
+
+    for rule in rules.iter() {
+        if let Some(dir) = rule.deny_src_dirs.iter().find(|dir| !source_dirs.iter().any(|src_dir| src_dir.path == **dir)) {
+            Err(error(&path, Problem::BadRule {
+                ns_pattern: rule.selector.pattern().into(),
+                detail: format!("the directory {:?} in ':deny-src-dirs' is not in ':src-dirs'", dir),
+            }))?
+        }
+    }
 
-        let mut parsed_rules = vec![];
-        for (i, rule_definition) in rules.chunks_exact(2).enumerate() {
-            match rule_definition {
-                [Edn::Symbol(ns_pattern), Edn::Map(rule)] => {
-                    let rule = parse_rule(ns_pattern, rule.clone().to_map())
-                        .map_err(|problem| error(&path, problem))?;
+    debug!(?source_dirs, rules = rules.len(), "loaded configuration");
+    Ok(Config { source_dirs, rules, skip_marker, match_declared_ns })
+}
 
-                    if let Some(rule) = rule {
-                        parsed_rules.push(rule);
-                    } else {
-                        report.warn(format!("the rule for '{}' has no effect", ns_pattern));
-                    }
+/// Reads the EDN map in the file at `path`, with its comments removed and
+/// values read from the environment.
+fn read_map<P: AsRef<Path>>(path: P) -> Result<BTreeMap<String, Edn>, Error> {
+    let text = fs::read_to_string(&path).map_err(|err| error(&path, err.into()))?;
+    let stripped = strip_comments(&text);
+    let expanded = expand_env_tags(&stripped, |name| env::var(name).ok())
+        .map_err(|err| error(&path, err))?;
+    let edn: Edn = match expanded.parse() {
+        Ok(edn) => edn,
+        Err(err) => {
+            // Values read from the environment move the text after them, so
+            // that positions only match the file when there were none.
+            let syntax_error = if expanded == stripped {
+                locate_syntax_error(&path, &text, &err)
+            } else {
+                None
+            };
+            Err(Error { syntax_error, ..error(&path, err.into()) })?
+        }
+    };
+
+    if let Edn::Map(map) = edn {
+        Ok(map.to_map())
+    } else {
+        Err(error(&path, Problem::NotAMap))
+    }
+}
+
+/// Parses the rules in the vector `rules`, adding each to `parsed_rules`.
+fn parse_rules(
+    rules: Edn, templates: &BTreeMap<String, BTreeMap<String, Edn>>, layers: &[String], parsed_rules: &mut Vec<Rule>, report: &mut Report
+) -> Result<(), Problem> {
+    let rules = if let Edn::Vector(rules) = rules { rules.to_vec() } else { Err(Problem::BadRuleVector)? };
+    if rules.len() % 2 != 0 {
+        Err(Problem::BadRuleVector)?
+    }
+
+    for (i, rule_definition) in rules.chunks_exact(2).enumerate() {
+        match rule_definition {
+            [pattern @ (Edn::Symbol(ns_pattern) | Edn::Str(ns_pattern)), Edn::Map(rule)] => {
+                let rule = extend_template(ns_pattern, rule.clone().to_map(), templates)
+                    .and_then(|rule| parse_rule(pattern, rule, layers))?;
+
+                if let Some(rule) = rule {
+                    add_rule(parsed_rules, rule, report);
+                } else {
+                    report.warn(Warning::new(WarningCode::NoEffectRule, format!("the rule for '{}' has no effect", ns_pattern)));
                 }
-                [Edn::Symbol(ns_pattern), _] => {
-                    Err(error(&path, Problem::BadRule {
-                        ns_pattern: ns_pattern.clone(),
-                        detail: "the rule body must be a map".into()
-                    }))?
+            }
+            [Edn::Symbol(ns_pattern) | Edn::Str(ns_pattern), _] => {
+                Err(Problem::BadRule {
+                    ns_pattern: ns_pattern.clone(),
+                    detail: "the rule body must be a map".into()
+                })?
+            }
+            _ => {
+                Err(Problem::BadNsPattern { position: i })?
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Translates each pair of patterns in `forbid` into a rule that denies the
+/// second to the first. A pair whose first pattern is that of a rule without a
+/// path or tag condition adds to the rule's ':deny'. Any other pair becomes a
+/// rule of its own, after the rest, as only the first rule that matches a
+/// namespace applies.
+fn parse_forbidden_pairs(forbid: Edn, rules: &mut Vec<Rule>) -> Result<(), Problem> {
+    let pairs = if let Edn::Vector(pairs) = forbid { pairs.to_vec() } else { Err(Problem::BadForbid)? };
+
+    for pair in pairs {
+        let (from, to) = match pair {
+            Edn::Vector(pair) => match &pair.to_vec()[..] {
+                [Edn::Symbol(from), to @ Edn::Symbol(_)] => (from.clone(), to.clone()),
+                _ => Err(Problem::BadForbid)?,
+            },
+            _ => Err(Problem::BadForbid)?,
+        };
+        let denied = expect_ns_symbol(&from, ":forbid", to)?;
+
+        let existing = rules.iter_mut()
+            .find(|rule| rule.selector.pattern() == from && rule.when_path.is_none() && rule.tagged.is_none());
+        match existing {
+            Some(rule) if rule.deny.iter().any(|existing| existing.pattern == denied.pattern) => {}
+            Some(rule) => rule.deny.push(denied),
+            None => {
+                let selector = from.parse().map(Selector::Namespace).map_err(|detail: &str| Problem::BadRule {
+                    ns_pattern: from.clone(),
+                    detail: detail.into(),
+                })?;
+                rules.push(Rule {
+                    selector, restricted: false, allow: vec![], except: vec![], allow_paths: vec![], except_paths: vec![], allow_transitive: vec![], deny: vec![denied],
+                    deny_src_dirs: vec![], when_path: None, allow_self: true, allow_same_root: false, allow_parents: false,
+                    allow_lower_versions: false, no_external: false, tagged: None, deny_tagged: vec![], must_reference: vec![],
+                    severity: Severity::Error, tags: vec![], doc: None,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Expands each ':restrict-to' entry written as '(:transitive pattern)' into
+/// the pattern and everything allowed by the rules for that pattern, following
+/// their own transitive entries in turn. Each rule is followed at most once, so
+/// a cycle ends where it started.
+///
+/// Only the namespaces and paths that the other rules allow are inherited.
+/// Their negations, ':deny' and flags are not, while the inheriting rule's own
+/// negations and ':deny' apply to everything it inherits. A rule that doesn't
+/// restrict its references has nothing to inherit, so can't be followed.
+fn resolve_transitive_allows(rules: &mut [Rule]) -> Result<(), Problem> {
+    // Entries are resolved against the rules as written, so the order of the
+    // rules doesn't matter.
+    let pending: Vec<Vec<String>> = rules.iter_mut().map(|rule| mem::take(&mut rule.allow_transitive)).collect();
+    let mut inherited = Vec::with_capacity(rules.len());
+
+    for (i, patterns) in pending.iter().enumerate() {
+        let mut allow: Vec<NamespaceMatcher> = vec![];
+        let mut allow_paths: Vec<PathMatcher> = vec![];
+        let mut followed = BTreeSet::new();
+        let mut queue = patterns.clone();
+
+        while let Some(pattern) = queue.pop() {
+            let bad_rule = |detail| Problem::BadRule { ns_pattern: rules[i].selector.pattern().into(), detail };
+            let targets: Vec<_> = rules.iter()
+                .enumerate()
+                .filter(|(_, rule)| matches!(&rule.selector, Selector::Namespace(ns) if ns.pattern == pattern))
+                .map(|(index, _)| index)
+                .collect();
+            if targets.is_empty() {
+                Err(bad_rule(format!("there is no rule for '{}' to allow transitively", pattern)))?
+            }
+            allow.push(pattern.parse().map_err(|err: &str| bad_rule(err.into()))?);
+
+            for target in targets {
+                if !followed.insert(target) {
+                    continue;
                 }
-                _ => {
-                    Err(error(&path, Problem::BadNsPattern { position: i }))?
+                let rule = &rules[target];
+                if !rule.restricted {
+                    Err(bad_rule(format!("the rule for '{}' doesn't restrict its references, so can't be allowed transitively", pattern)))?
                 }
+                allow.extend(rule.allow.iter().cloned());
+                allow_paths.extend(rule.allow_paths.iter().cloned());
+                queue.extend(pending[target].iter().cloned());
             }
         }
 
-        parsed_rules
-    } else {
-        Err(error(&path, Problem::BadRuleVector))?
+        inherited.push((allow, allow_paths));
+    }
+
+    for (rule, (allow, allow_paths)) in rules.iter_mut().zip(inherited) {
+        for matcher in allow {
+            if !rule.allow.iter().any(|existing| existing.pattern == matcher.pattern) {
+                rule.allow.push(matcher);
+            }
+        }
+        for path in allow_paths {
+            if !rule.allow_paths.iter().any(|existing| existing.pattern == path.pattern) {
+                rule.allow_paths.push(path);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The files matching `glob`, relative to the directory of the configuration
+/// at `config`, in order of path.
+fn find_fragments(config: &Path, glob: &str) -> Result<Vec<PathBuf>, Problem> {
+    let pattern = config.parent().unwrap_or_else(|| Path::new("")).join(glob);
+    let matcher: PathMatcher = pattern.to_str()
+        .ok_or(Problem::BadIncludeGlob)?
+        .parse()
+        .map_err(|_| Problem::BadIncludeGlob)?;
+    // Only the files below the part of the glob without wildcards can match
+    let base: PathBuf = pattern.components()
+        .take_while(|component| !SourceDir::from(&*component.as_os_str().to_string_lossy()).is_glob())
+        .collect();
+    let base = if base.as_os_str().is_empty() { PathBuf::from(".") } else { base };
+
+    let mut fragments: Vec<PathBuf> = WalkBuilder::new(&base)
+        .standard_filters(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.path().strip_prefix("./").unwrap_or(entry.path()).to_owned())
+        .filter(|path| path.to_str().is_some_and(|path| matcher.matches(path)))
+        .collect();
+    fragments.sort();
+
+    Ok(fragments)
+}
+
+/// Adds `rule` to `rules`, merging it into any earlier rule with the same
+/// pattern, path condition and tag condition, which would otherwise leave it
+/// unused, as only the first matching rule is applied.
+///
+/// The namespaces allowed, excluded and denied by the rules are combined. A
+/// rule without ':restrict-to' adds nothing to the allow list of the other, so
+/// doesn't lift its restriction. The documentation of the first rule is kept.
+fn add_rule(rules: &mut Vec<Rule>, rule: Rule, report: &mut Report) {
+    let pattern = rule.selector.to_string();
+    let condition = |rule: &Rule| (rule.when_path.as_ref().map(ToString::to_string), rule.tagged.clone());
+    let existing = match rules.iter_mut().find(|existing| existing.selector.to_string() == pattern && condition(existing) == condition(&rule)) {
+        Some(existing) => existing,
+        None => return rules.push(rule),
+    };
+
+    report.warn(Warning::new(WarningCode::DuplicateRule, format!("the rule for '{}' is defined more than once, the definitions were merged", pattern)));
+    let union = |into: &mut Vec<NamespaceMatcher>, from: Vec<NamespaceMatcher>| {
+        for matcher in from {
+            if !into.iter().any(|existing| existing.pattern == matcher.pattern) {
+                into.push(matcher);
+            }
+        }
+    };
+    existing.restricted |= rule.restricted;
+    union(&mut existing.allow, rule.allow);
+    union(&mut existing.except, rule.except);
+    for pattern in rule.allow_transitive {
+        if !existing.allow_transitive.contains(&pattern) {
+            existing.allow_transitive.push(pattern);
+        }
+    }
+    for path in rule.allow_paths {
+        if !existing.allow_paths.iter().any(|existing| existing.pattern == path.pattern) {
+            existing.allow_paths.push(path);
+        }
+    }
+    for path in rule.except_paths {
+        if !existing.except_paths.iter().any(|existing| existing.pattern == path.pattern) {
+            existing.except_paths.push(path);
+        }
+    }
+    union(&mut existing.deny, rule.deny);
+    for dir in rule.deny_src_dirs {
+        if !existing.deny_src_dirs.contains(&dir) {
+            existing.deny_src_dirs.push(dir);
+        }
+    }
+    for tag in rule.deny_tagged {
+        if !existing.deny_tagged.contains(&tag) {
+            existing.deny_tagged.push(tag);
+        }
+    }
+    union(&mut existing.must_reference, rule.must_reference);
+    for tag in rule.tags {
+        if !existing.tags.contains(&tag) {
+            existing.tags.push(tag);
+        }
+    }
+    // Self-references are only allowed if every definition allows them
+    existing.allow_self &= rule.allow_self;
+    existing.allow_same_root |= rule.allow_same_root;
+    existing.allow_parents |= rule.allow_parents;
+    existing.allow_lower_versions |= rule.allow_lower_versions;
+    existing.no_external |= rule.no_external;
+    // Violations fail the run if either definition says that they should
+    if rule.severity == Severity::Error {
+        existing.severity = Severity::Error;
+    }
+    if existing.doc.is_none() {
+        existing.doc = rule.doc;
+    }
+}
+
+fn select_profile(
+    profiles: Option<Edn>, profile: &str
+) -> Result<BTreeMap<String, Edn>, Problem> {
+    let mut profiles = match profiles {
+        Some(Edn::Map(profiles)) => profiles.to_map(),
+        Some(_) => Err(Problem::BadProfiles)?,
+        None => BTreeMap::new(),
+    };
+
+    let key = format!(":{}", profile.trim_start_matches(':'));
+    match profiles.remove(&key) {
+        Some(Edn::Map(overrides)) => {
+            let overrides = overrides.to_map();
+            check_keys(&overrides, &CONFIG_KEYS[..8], &format!("the profile '{}'", key))?;
+            Ok(overrides)
+        }
+        Some(_) => Err(Problem::BadProfiles),
+        None => Err(Problem::UnknownProfile { profile: profile.into() }),
+    }
+}
+
+/// Merges the overrides of a profile over the base configuration.
+///
+/// Maps are merged recursively and all other values in the profile replace
+/// those in the base configuration, with the exception of ':rules'. Rules are
+/// merged by namespace pattern, a rule in the profile is merged over the rule
+/// with the same pattern in the base configuration, keeping its position. Rules
+/// for new patterns are appended after the base rules.
+fn merge_config(
+    mut base: BTreeMap<String, Edn>, overrides: BTreeMap<String, Edn>
+) -> Result<BTreeMap<String, Edn>, Problem> {
+    for (key, value) in overrides {
+        let merged = match (key.as_str(), base.remove(&key), value) {
+            (":rules", Some(Edn::Vector(base_rules)), Edn::Vector(rules)) => {
+                Edn::Vector(Vector::new(merge_rules(base_rules.to_vec(), rules.to_vec())?))
+            }
+            (_, Some(base_value), value) => merge_edn(base_value, value),
+            (_, None, value) => value,
+        };
+        base.insert(key, merged);
+    }
+
+    Ok(base)
+}
+
+fn merge_rules(mut base: Vec<Edn>, overrides: Vec<Edn>) -> Result<Vec<Edn>, Problem> {
+    if !base.len().is_multiple_of(2) || !overrides.len().is_multiple_of(2) {
+        Err(Problem::BadRuleVector)?
+    }
+
+    for rule_definition in overrides.chunks_exact(2) {
+        let (ns_pattern, rule) = (&rule_definition[0], &rule_definition[1]);
+        let existing = base.chunks_exact(2).position(|base_rule| &base_rule[0] == ns_pattern);
+        if let Some(i) = existing {
+            let base_rule = std::mem::replace(&mut base[i * 2 + 1], Edn::Nil);
+            base[i * 2 + 1] = merge_edn(base_rule, rule.clone());
+        } else {
+            base.push(ns_pattern.clone());
+            base.push(rule.clone());
+        }
+    }
+
+    Ok(base)
+}
+
+fn merge_edn(base: Edn, value: Edn) -> Edn {
+    match (base, value) {
+        (Edn::Map(base), Edn::Map(overrides)) => {
+            let mut merged = base.to_map();
+            for (key, value) in overrides.to_map() {
+                let value = match merged.remove(&key) {
+                    Some(base_value) => merge_edn(base_value, value),
+                    None => value,
+                };
+                merged.insert(key, value);
+            }
+            Edn::Map(Map::new(merged))
+        }
+        (_, value) => value,
+    }
+}
+
+/// Checks that the `current` version is no older than the ':min-version' of the
+/// configuration, if it has one. Missing components of a version are zero.
+fn check_min_version(min_version: Option<Edn>, current: &'static str) -> Result<(), Problem> {
+    let required = match min_version {
+        Some(Edn::Str(required)) => required,
+        Some(_) => Err(Problem::BadMinVersion)?,
+        None => return Ok(()),
+    };
+
+    let components = |version: &str| {
+        version.split('.').map(str::parse).collect::<Result<Vec<u64>, _>>().ok()
+    };
+    let (mut minimum, mut actual) = match (components(&required), components(current)) {
+        (Some(minimum), Some(actual)) => (minimum, actual),
+        _ => Err(Problem::BadMinVersion)?,
+    };
+    let len = minimum.len().max(actual.len());
+    minimum.resize(len, 0);
+    actual.resize(len, 0);
+
+    if actual < minimum {
+        Err(Problem::VersionTooOld { required, current })?
+    }
+    Ok(())
+}
+
+/// Parses the namespace patterns of ':layers', ordered from the bottom layer up.
+fn parse_layers(edn: Edn) -> Result<Vec<String>, Problem> {
+    let layers = if let Edn::Vector(layers) = edn { layers.to_vec() } else { Err(Problem::BadLayers)? };
+
+    layers.into_iter()
+        .map(|layer| match layer {
+            Edn::Symbol(ns_pattern) if ns_pattern.parse::<NamespaceMatcher>().is_ok() => Ok(ns_pattern),
+            _ => Err(Problem::BadLayers),
+        })
+        .collect()
+}
+
+/// Parses ':templates', a map of keywords to the rule bodies that rules may
+/// extend.
+fn parse_templates(edn: Edn) -> Result<BTreeMap<String, BTreeMap<String, Edn>>, Problem> {
+    let templates = if let Edn::Map(templates) = edn { templates.to_map() } else { Err(Problem::BadTemplates)? };
+
+    templates.into_iter()
+        .map(|(name, template)| match template {
+            Edn::Map(template) if keyword_name(&name).is_some() => {
+                let template = template.to_map();
+                if template.contains_key(":extends") {
+                    Err(Problem::BadTemplates)?
+                }
+                check_keys(&template, RULE_KEYS, &format!("the template '{}'", name))?;
+                Ok((name, template))
+            }
+            _ => Err(Problem::BadTemplates),
+        })
+        .collect()
+}
+
+/// Merges the template named by the ':extends' of `rule` under it. The vectors
+/// of namespaces allowed and denied by the template are combined with those of
+/// the rule, and any other key of the rule replaces that of the template.
+fn extend_template(
+    ns_pattern: &str, mut rule: BTreeMap<String, Edn>, templates: &BTreeMap<String, BTreeMap<String, Edn>>
+) -> Result<BTreeMap<String, Edn>, Problem> {
+    let template = match rule.remove(":extends") {
+        Some(Edn::Key(template)) => template,
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':extends' must be the keyword of a template".into(),
+        })?,
+        None => return Ok(rule),
     };
+    let mut extended = templates.get(&template)
+        .ok_or_else(|| Problem::UnknownTemplate { ns_pattern: ns_pattern.into(), template: template.clone() })?
+        .clone();
+
+    for (key, value) in rule {
+        let merged = match (extended.remove(&key), value) {
+            (Some(Edn::Vector(inherited)), Edn::Vector(own)) if COMBINED_RULE_KEYS.contains(&key.as_str()) => {
+                Edn::Vector(Vector::new(inherited.to_vec().into_iter().chain(own.to_vec()).collect()))
+            }
+            (_, value) => value,
+        };
+        extended.insert(key, merged);
+    }
 
-    Ok(Config { source_dirs, rules })
+    Ok(extended)
 }
 
+/// Parses the rule for `pattern`, which selects files by namespace when it is a
+/// symbol, or by path when it is a string. `layers` are the patterns of
+/// ':layers', which ':left-of-self' refers to.
 fn parse_rule(
-    ns_pattern: &String, mut rule: BTreeMap<String, Edn>
+    pattern: &Edn, mut rule: BTreeMap<String, Edn>, layers: &[String]
 ) -> Result<Option<Rule>, Problem> {
-    let ns_matcher: NamespaceMatcher = ns_pattern.parse()
-        .map_err(|err: &str| Problem::BadRule {
-            ns_pattern: ns_pattern.clone(),
-            detail: err.into(),
-        })?;
+    let (ns_pattern, selector) = match pattern {
+        Edn::Symbol(ns_pattern) => {
+            (ns_pattern, ns_pattern.parse().map(Selector::Namespace).map_err(String::from))
+        }
+        Edn::Str(path_pattern) => {
+            (path_pattern, path_pattern.parse().map(Selector::Path).map_err(|err| err.to_string()))
+        }
+        _ => unreachable!("rule patterns are symbols or strings"),
+    };
+    let selector = selector.map_err(|detail| Problem::BadRule {
+        ns_pattern: ns_pattern.clone(),
+        detail,
+    })?;
+    check_keys(&rule, RULE_KEYS, &format!("the rule '{}'", ns_pattern))?;
 
+    let mut allow_lower_versions = false;
+    let mut allow_paths = Vec::new();
+    let mut except_paths = Vec::new();
+    let mut allow_transitive = Vec::new();
     let allow_list = if let Some(edn) = rule.remove(":restrict-to") {
         if let Edn::Vector(allow_list) = edn {
-            let allow_list = allow_list.to_vec()
-                .into_iter()
-                .map(|allowed_ns| expect_ns_symbol(ns_pattern, allowed_ns))
-                .collect::<Result<Vec<NamespaceMatcher>, Problem>>()?;
+            let allow_list = allow_list.to_vec();
+            let mut allow = Vec::new();
+            let mut except = Vec::new();
+            for allowed_ns in allow_list.iter().cloned() {
+                match allowed_ns {
+                    // :self allows the namespaces selected by the rule, and their subtree
+                    Edn::Key(key) if key == ":self" => {
+                        allow.extend(self_subtree(ns_pattern, &selector)?);
+                    }
+                    // :left-of-self allows the layers below the rule's own layer
+                    Edn::Key(key) if key == ":left-of-self" => {
+                        allow.extend(layers_below(ns_pattern, &selector, layers)?);
+                    }
+                    // :same-or-lower-version allows the same or a lower version, such as api.v1 from api.v2
+                    Edn::Key(key) if key == ":same-or-lower-version" => {
+                        allow_lower_versions = true;
+                    }
+                    // (:transitive pattern) allows the pattern and whatever the rules for it allow
+                    Edn::List(list) => match &list.to_vec()[..] {
+                        [Edn::Key(key), target] if key == ":transitive" => {
+                            let target = expect_ns_symbol(ns_pattern, ":restrict-to", target.clone())?;
+                            allow_transitive.push(target.pattern);
+                        }
+                        _ => Err(Problem::BadRule {
+                            ns_pattern: ns_pattern.into(),
+                            detail: "a list in ':restrict-to' must be (:transitive pattern)".into(),
+                        })?,
+                    },
+                    // A string with a '/' is a glob, allowing the namespaces of the files whose path matches
+                    Edn::Str(path) if path.contains('/') => match path.strip_prefix('!') {
+                        Some(negated) => except_paths.push(expect_path_glob(ns_pattern, negated)?),
+                        None => allow_paths.push(expect_path_glob(ns_pattern, &path)?),
+                    },
+                    // A leading '!' removes namespaces from those allowed
+                    Edn::Symbol(negated) | Edn::Str(negated) if negated.starts_with('!') => {
+                        let negated = Edn::Symbol(negated[1..].into());
+                        except.push(expect_ns_symbol(ns_pattern, ":restrict-to", negated)?);
+                    }
+                    allowed_ns => allow.push(expect_ns_symbol(ns_pattern, ":restrict-to", allowed_ns)?),
+                }
+            }
 
-            if allow_list.is_empty() { None } else { Some(allow_list) }
+            if allow_list.is_empty() { None } else { Some((allow, except)) }
         } else {
             Err(Problem::BadRule {
                 ns_pattern: ns_pattern.into(),
@@ -156,29 +888,989 @@ fn parse_rule(
         None
     };
 
-    let rule = allow_list.map(|allow| Rule { namespace: ns_matcher, allow });
+    // Denied namespaces are checked against every reference, so they may
+    // include namespaces from outside the source tree, such as libraries.
+    let deny = match rule.remove(":deny") {
+        Some(Edn::Vector(deny)) => deny.to_vec()
+            .into_iter()
+            .map(|denied_ns| expect_ns_symbol(ns_pattern, ":deny", denied_ns))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':deny' must be a vector of symbols".into(),
+        })?,
+        None => Vec::new(),
+    };
+
+    // The namespaces in these source directories are denied. The directories
+    // are checked against ':src-dirs' once the whole configuration is read.
+    let bad_deny_src_dirs = || Problem::BadRule {
+        ns_pattern: ns_pattern.into(),
+        detail: "':deny-src-dirs' must be a vector of strings".into(),
+    };
+    let deny_src_dirs = match rule.remove(":deny-src-dirs") {
+        Some(Edn::Vector(dirs)) => dirs.to_vec()
+            .into_iter()
+            .map(|dir| if let Edn::Str(dir) = dir { Ok(dir) } else { Err(bad_deny_src_dirs()) })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => Err(bad_deny_src_dirs())?,
+        None => Vec::new(),
+    };
+
+    // Narrows the files selected by the pattern to those with a matching path
+    let when_path = match rule.remove(":when-path") {
+        Some(Edn::Str(when_path)) => Some(when_path.parse().map_err(|err: globset::Error| Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: err.to_string(),
+        })?),
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':when-path' must be a string".into(),
+        })?,
+        None => None,
+    };
+
+    // Narrows the namespaces selected by the pattern to those with, or without, a tag
+    let tagged = match rule.remove(":tagged") {
+        Some(Edn::Key(tag)) => {
+            let tag = &tag[1..];
+            Some(match tag.strip_prefix('!') {
+                Some(tag) => TagCondition { tag: tag.into(), present: false },
+                None => TagCondition { tag: tag.into(), present: true },
+            })
+        }
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':tagged' must be a keyword, such as :internal or :!internal".into(),
+        })?,
+        None => None,
+    };
+
+    // The namespaces with these tags in their metadata are denied
+    let bad_deny_tagged = || Problem::BadRule {
+        ns_pattern: ns_pattern.into(),
+        detail: "':deny-tagged' must be a vector of keywords".into(),
+    };
+    let deny_tagged = match rule.remove(":deny-tagged") {
+        Some(Edn::Vector(tags)) => tags.to_vec()
+            .into_iter()
+            .map(|tag| if let Edn::Key(tag) = tag { Ok(tag[1..].to_owned()) } else { Err(bad_deny_tagged()) })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => Err(bad_deny_tagged())?,
+        None => Vec::new(),
+    };
+
+    // Allows references to the namespaces selected by the rule, itself included
+    let allow_self = match rule.remove(":allow-self") {
+        Some(Edn::Bool(allow_self)) => allow_self,
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':allow-self' must be true or false".into(),
+        })?,
+        None => true,
+    };
+
+    // Allows references to namespaces with the same first segment
+    let allow_same_root = match rule.remove(":allow-same-root") {
+        Some(Edn::Bool(allow_same_root)) => allow_same_root,
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':allow-same-root' must be true or false".into(),
+        })?,
+        None => false,
+    };
+
+    // Allows references to the namespaces that the referencing namespace is in
+    let allow_parents = match rule.remove(":allow-parents") {
+        Some(Edn::Bool(allow_parents)) => allow_parents,
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':allow-parents' must be true or false".into(),
+        })?,
+        None => false,
+    };
+
+    // Forbids references to namespaces outside the source tree, such as libraries
+    let no_external = match rule.remove(":no-external") {
+        Some(Edn::Bool(no_external)) => no_external,
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':no-external' must be true or false".into(),
+        })?,
+        None => false,
+    };
+
+    let doc = match rule.remove(":doc") {
+        Some(Edn::Str(doc)) => Some(doc),
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':doc' must be a string".into(),
+        })?,
+        None => None,
+    };
+
+    // Each selected namespace must reference a namespace matching every one of
+    // these, which, like denied namespaces, may be outside the source tree.
+    let must_reference = match rule.remove(":must-reference") {
+        Some(Edn::Vector(required)) => required.to_vec()
+            .into_iter()
+            .map(|required_ns| expect_ns_symbol(ns_pattern, ":must-reference", required_ns))
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':must-reference' must be a vector of symbols".into(),
+        })?,
+        None => Vec::new(),
+    };
+
+    // Violations of a rule at :warn are reported without failing the run
+    let severity = match rule.remove(":severity") {
+        Some(Edn::Key(severity)) if severity == ":error" => Severity::Error,
+        Some(Edn::Key(severity)) if severity == ":warn" => Severity::Warn,
+        Some(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':severity' must be :error or :warn".into(),
+        })?,
+        None => Severity::Error,
+    };
+
+    // Categories, by which a subset of the rules can be run with --tag
+    let bad_tags = || Problem::BadRule {
+        ns_pattern: ns_pattern.into(),
+        detail: "':tags' must be a vector of keywords".into(),
+    };
+    let tags = match rule.remove(":tags") {
+        Some(Edn::Vector(tags)) => tags.to_vec()
+            .into_iter()
+            .map(|tag| if let Edn::Key(tag) = tag { Ok(tag[1..].to_owned()) } else { Err(bad_tags()) })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => Err(bad_tags())?,
+        None => Vec::new(),
+    };
+
+    let rule = if allow_list.is_none() && deny.is_empty() && deny_src_dirs.is_empty() && deny_tagged.is_empty() && must_reference.is_empty() && !allow_same_root && !allow_parents && !no_external {
+        None
+    } else {
+        let restricted = allow_list.is_some();
+        let (allow, except) = allow_list.unwrap_or_default();
+        Some(Rule { selector, restricted, allow, except, allow_paths, except_paths, allow_transitive, deny, deny_src_dirs, when_path, allow_self, allow_same_root, allow_parents, allow_lower_versions, no_external, tagged, deny_tagged, must_reference, severity, tags, doc })
+    };
 
     Ok(rule)
 }
 
-fn expect_src_dir(edn: Edn) -> Result<String, Problem> {
-    if let Edn::Str(s) = edn { Ok(s) } else { Err(Problem::BadSrcDirs) }
+/// Blanks out the comments in `edn`, which edn_rs would otherwise read as
+/// symbols. Comments are replaced by spaces so that positions are unchanged.
+fn strip_comments(edn: &str) -> String {
+    let mut stripped = String::with_capacity(edn.len());
+    let mut in_string = false;
+    let mut in_comment = false;
+    let mut chars = edn.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => in_comment = false,
+            _ if in_comment => {
+                stripped.extend(iter::repeat_n(' ', c.len_utf8()));
+                continue;
+            }
+            // Escaped characters, in strings or character literals, are kept
+            // as they are, so '\"' and '\;' don't start or end anything.
+            '\\' => {
+                stripped.push(c);
+                stripped.extend(chars.next());
+                continue;
+            }
+            '"' => in_string = !in_string,
+            ';' if !in_string => {
+                in_comment = true;
+                stripped.push(' ');
+                continue;
+            }
+            _ => {}
+        }
+        stripped.push(c);
+    }
+
+    stripped
+}
+
+/// The tag replaced by the value of an environment variable.
+const ENV_TAG: &str = "#ns-rules/env";
+
+/// Replaces each `#ns-rules/env "NAME"` in `edn` with the value of the
+/// environment variable `NAME`, as found by `lookup`, as a string. edn_rs can't
+/// read custom tags, so they are replaced before the EDN is read. Comments must
+/// already have been stripped.
+fn expand_env_tags(edn: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, Problem> {
+    let mut expanded = String::with_capacity(edn.len());
+    let mut in_string = false;
+    let mut rest = edn;
+    while let Some(c) = rest.chars().next() {
+        if !in_string && rest.starts_with(ENV_TAG) {
+            let offset = edn.len() - rest.len();
+            let name = rest[ENV_TAG.len()..].trim_start();
+            let (name, after) = name.strip_prefix('"')
+                .and_then(|name| name.split_once('"'))
+                .filter(|(name, _)| !name.contains('\\'))
+                .ok_or(Problem::BadEnvTag { offset })?;
+            let value = lookup(name).ok_or_else(|| Problem::MissingEnvVar { name: name.into() })?;
+
+            expanded.push('"');
+            expanded.push_str(&value.replace('\\', "\\\\").replace('"', "\\\""));
+            expanded.push('"');
+            rest = after;
+            continue;
+        }
+
+        match c {
+            // Escaped characters, in strings or character literals, are kept
+            // as they are, so '\"' doesn't start or end a string.
+            '\\' => {
+                let escaped = rest[1..].chars().next().map_or(0, char::len_utf8);
+                expanded.push_str(&rest[..1 + escaped]);
+                rest = &rest[1 + escaped..];
+                continue;
+            }
+            '"' => in_string = !in_string,
+            _ => {}
+        }
+        expanded.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    Ok(expanded)
+}
+
+/// The name of the keyword `key`, as stringified by edn_rs, or `None` if `key`
+/// is not a keyword.
+fn keyword_name(key: &str) -> Option<&str> {
+    key.strip_prefix(':')
+}
+
+/// Checks that `map` only contains keys from `expected`, so that a misspelt key
+/// is reported rather than silently ignored. When a key is close to an expected
+/// key, for example a symbol or string rather than a keyword, or written with
+/// '_' rather than '-', the expected key is suggested.
+fn check_keys(map: &BTreeMap<String, Edn>, expected: &[Key], location: &str) -> Result<(), Problem> {
+    let normalise = |key: &str| key.trim_matches('"').replace('_', "-").to_lowercase();
+
+    for key in map.keys() {
+        if keyword_name(key).is_some() && expected.iter().any(|expected| expected.name == key) {
+            continue;
+        }
+
+        let name = keyword_name(key).unwrap_or(key);
+        let expected = expected.iter().map(|expected| expected.name).find(|expected| {
+            keyword_name(expected).map(normalise) == Some(normalise(name))
+        });
+        Err(Problem::UnknownKey { location: location.into(), key: key.clone(), expected })?
+    }
+
+    Ok(())
+}
+
+/// Describes every key that the configuration accepts, with the type of its
+/// value, generated from the keys that the configuration is checked against.
+pub(crate) fn schema() -> String {
+    let sections = [
+        ("Configuration", CONFIG_KEYS), ("Rules", RULE_KEYS), ("Included files", FRAGMENT_KEYS), ("Source directories", SRC_DIR_KEYS),
+    ];
+    let keys = || sections.iter().flat_map(|(_, keys)| keys.iter());
+    let name_width = keys().map(|key| key.name.len()).max().unwrap_or_default();
+    let kind_width = keys().map(|key| key.kind.len()).max().unwrap_or_default();
+
+    sections.iter()
+        .map(|(title, keys)| {
+            let keys = keys.iter()
+                .map(|key| format!("  {:name_width$}  {:kind_width$}  {}\n", key.name, key.kind, key.doc))
+                .collect::<String>();
+            format!("{}\n{}", title, keys)
+        })
+        .intersperse("\n".to_string())
+        .collect()
+}
+
+/// Expands ':self' for the rule selecting `selector` into the rule's own
+/// pattern and the subtree below it, so ':self' in the rule for 'a.b' allows
+/// 'a.b' and 'a.b.*'. The expansion is of the pattern, rather than of each
+/// namespace it selects, so for 'a.*.c' it allows any namespace that matches
+/// 'a.*.c' or 'a.*.c.*', not just those below the referencing namespace.
+fn self_subtree(ns_pattern: &String, selector: &Selector) -> Result<Vec<NamespaceMatcher>, Problem> {
+    let pattern = match selector {
+        Selector::Namespace(matcher) => &matcher.pattern,
+        Selector::Path(_) => Err(Problem::BadRule {
+            ns_pattern: ns_pattern.into(),
+            detail: "':self' can only be allowed by rules that select namespaces".into(),
+        })?,
+    };
+
+    let mut patterns = vec![pattern.clone()];
+    if !pattern.ends_with(".*") {
+        patterns.push(format!("{}.*", pattern));
+    }
+
+    Ok(patterns.iter().map(|pattern| pattern.parse().expect("valid pattern")).collect())
+}
+
+/// Expands ':left-of-self' for the rule selecting `selector` into the patterns
+/// of the layers declared before the rule's own layer in `layers`. The rule's
+/// pattern must be one of `layers`, as a rule for any other pattern has no
+/// position among them. Namespaces that are in no layer are never allowed by
+/// ':left-of-self'.
+///
+/// The bottom layer has nothing below it, so is restricted to itself rather
+/// than being left unrestricted by an empty allow list.
+fn layers_below(
+    ns_pattern: &String, selector: &Selector, layers: &[String]
+) -> Result<Vec<NamespaceMatcher>, Problem> {
+    let position = match selector {
+        Selector::Namespace(matcher) => layers.iter().position(|layer| *layer == matcher.pattern),
+        Selector::Path(_) => None,
+    };
+    let position = position.ok_or_else(|| Problem::BadRule {
+        ns_pattern: ns_pattern.into(),
+        detail: "':left-of-self' can only be allowed by the rule for a layer in ':layers'".into(),
+    })?;
+
+    let below = if position == 0 { &layers[..1] } else { &layers[..position] };
+
+    Ok(below.iter().map(|pattern| pattern.parse().expect("valid pattern")).collect())
+}
+
+fn expect_src_dir(edn: Edn) -> Result<SourceDir, Problem> {
+    let mut dir = match edn {
+        Edn::Str(path) => return Ok(SourceDir { path, exclude: vec![] }),
+        Edn::Map(dir) => dir.to_map(),
+        _ => Err(Problem::BadSrcDirs)?,
+    };
+    check_keys(&dir, SRC_DIR_KEYS, "a source directory")?;
+
+    let path = match dir.remove(":path") {
+        Some(Edn::Str(path)) => path,
+        _ => Err(Problem::BadSrcDirs)?,
+    };
+    let exclude = match dir.remove(":exclude") {
+        Some(Edn::Vector(exclude)) => exclude.to_vec()
+            .into_iter()
+            .map(|excluded| if let Edn::Str(excluded) = excluded { Ok(excluded) } else { Err(Problem::BadSrcDirs) })
+            .collect::<Result<Vec<_>, _>>()?,
+        Some(_) => Err(Problem::BadSrcDirs)?,
+        None => vec![],
+    };
+
+    Ok(SourceDir { path, exclude })
 }
 
-fn expect_ns_symbol(ns_pattern: &String, edn: Edn) -> Result<NamespaceMatcher, Problem> {
-    if let Edn::Symbol(allowed_ns) = edn {
-        allowed_ns.parse().map_err(|err: &str| Problem::BadRule {
+/// Parses a namespace pattern in the list under `key`. Patterns are usually
+/// symbols, but may be written as strings when they contain characters that
+/// can't be read as part of a symbol, such as the brackets of a character class.
+fn expect_ns_symbol(ns_pattern: &String, key: &str, edn: Edn) -> Result<NamespaceMatcher, Problem> {
+    if let Edn::Symbol(ns) | Edn::Str(ns) = edn {
+        ns.parse().map_err(|err: &str| Problem::BadRule {
             ns_pattern: ns_pattern.into(),
-            detail: format!("the allowed namespace '{}' is invalid, {}", allowed_ns, err)
+            detail: format!("the namespace '{}' in '{}' is invalid, {}", ns, key, err)
         })
     } else {
         Err(Problem::BadRule {
             ns_pattern: ns_pattern.into(),
-            detail: "':restrict-to' must be a vector of symbols".into(),
+            detail: format!("'{}' must be a vector of symbols", key),
         })
     }
 }
 
+fn expect_path_glob(ns_pattern: &String, glob: &str) -> Result<PathMatcher, Problem> {
+    glob.parse().map_err(|err: globset::Error| Problem::BadRule {
+        ns_pattern: ns_pattern.into(),
+        detail: format!("the path '{}' in ':restrict-to' is invalid, {}", glob, err),
+    })
+}
+
 fn error<P: AsRef<Path>>(path: P, problem: Problem) -> Error {
-    Error { path: path.as_ref().into(), source: problem }
+    Error { path: path.as_ref().into(), source: problem, syntax_error: None }
+}
+
+/// Finds where in `text` edn_rs failed to read it, from the character count
+/// that ends some of its error messages, which is one past the start of the
+/// form that couldn't be read.
+fn locate_syntax_error<P: AsRef<Path>>(path: P, text: &str, err: &EdnError) -> Option<Box<SyntaxError>> {
+    let message = err.to_string();
+    let (_, count) = message.rsplit_once("at char count ")?;
+    let char_index = count.trim().parse::<usize>().ok()?.checked_sub(1)?;
+    let (offset, c) = text.char_indices().nth(char_index)?;
+
+    let line_start = text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = text[offset..].find('\n').map(|i| offset + i).unwrap_or(text.len());
+
+    Some(Box::new(SyntaxError {
+        src: NamedSource::new(path.as_ref().display().to_string(), text.to_owned()),
+        line: (line_start, line_end - line_start).into(),
+        at: (offset, c.len_utf8()).into(),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn edn_map(edn: &str) -> BTreeMap<String, Edn> {
+        match edn.parse() {
+            Ok(Edn::Map(map)) => map.to_map(),
+            _ => panic!("not an EDN map: {}", edn),
+        }
+    }
+
+    #[test]
+    fn comments_are_blanked_out() {
+        let edn = "{:src-dirs [\"src;main\"] ; the sources\n :rules [a.b {:restrict-to [\\;]}]}";
+
+        let stripped = strip_comments(edn);
+
+        assert_eq!(stripped.len(), edn.len());
+        assert_eq!(
+            stripped.parse::<Edn>().unwrap(),
+            "{:src-dirs [\"src;main\"] :rules [a.b {:restrict-to [\\;]}]}".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn env_tags_are_replaced_by_the_variable() {
+        let lookup = |name: &str| (name == "SRC_DIR").then(|| r#"src\"main"#.to_string());
+        let edn = r##"{:src-dirs [#ns-rules/env "SRC_DIR" "#ns-rules/env"]}"##;
+
+        let expanded = expand_env_tags(edn, lookup).unwrap();
+
+        assert_eq!(
+            expanded.parse::<Edn>().unwrap(),
+            r##"{:src-dirs ["src\\\"main" "#ns-rules/env"]}"##.parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_env_vars_are_errors() {
+        let edn = r#"{:src-dirs [#ns-rules/env "SRC_DIR"]}"#;
+
+        let problem = expand_env_tags(edn, |_| None).unwrap_err();
+
+        assert!(matches!(problem, Problem::MissingEnvVar { name } if name == "SRC_DIR"));
+        assert!(matches!(expand_env_tags("[#ns-rules/env SRC_DIR]", |_| None), Err(Problem::BadEnvTag { offset: 1 })));
+    }
+
+    #[test]
+    fn src_dirs_may_exclude_subdirectories() {
+        let src = expect_src_dir(r#""src""#.parse().unwrap()).unwrap();
+        let gen = expect_src_dir(r#"{:path "gen" :exclude ["vendor" "tmp"]}"#.parse().unwrap()).unwrap();
+
+        assert_eq!(src, SourceDir::from("src"));
+        assert_eq!(gen.exclude, vec!["vendor", "tmp"]);
+        assert!(gen.excludes(Path::new("gen/vendor/lib.clj")));
+        assert!(!gen.excludes(Path::new("gen/vendored.clj")));
+        assert!(expect_src_dir(r#"{:path "src" :excludes []}"#.parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn profile_replaces_src_dirs() {
+        let base = edn_map(r#"{:src-dirs ["src"] :rules []}"#);
+        let profile = edn_map(r#"{:src-dirs ["src" "test"]}"#);
+
+        let merged = merge_config(base, profile).unwrap();
+
+        assert_eq!(merged[":src-dirs"], r#"["src" "test"]"#.parse().unwrap());
+    }
+
+    #[test]
+    fn profile_rules_merge_by_pattern() {
+        let base = edn_map(
+            "{:rules [a.* {:restrict-to [b.*]} c.* {:restrict-to [d.*]}]}"
+        );
+        let profile = edn_map(
+            "{:rules [c.* {:restrict-to [e.*]} f.* {:restrict-to [g.*]}]}"
+        );
+
+        let merged = merge_config(base, profile).unwrap();
+
+        let expected = edn_map(
+            "{:rules [a.* {:restrict-to [b.*]}
+                      c.* {:restrict-to [e.*]}
+                      f.* {:restrict-to [g.*]}]}"
+        );
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn unknown_profile_is_an_error() {
+        let profiles = "{:ci {:src-dirs [\"src\"]}}".parse().ok();
+
+        assert!(select_profile(profiles, "ci").is_ok());
+        assert!(matches!(
+            select_profile("{}".parse().ok(), "dev"),
+            Err(Problem::UnknownProfile { .. })
+        ));
+    }
+
+    #[test]
+    fn negated_allowed_namespaces_are_parsed() {
+        let rule = edn_map("{:restrict-to [com.acme.* !com.acme.internal]}");
+
+        let rule = parse_rule(&Edn::Symbol("com.acme.app".into()), rule, &[]).unwrap().unwrap();
+
+        assert_eq!(rule.allow[0].to_string(), "com.acme.*");
+        assert_eq!(rule.except[0].to_string(), "com.acme.internal");
+    }
+
+    #[test]
+    fn string_namespace_patterns_are_allowed() {
+        let rule = edn_map(r#"{:restrict-to ["shipping.v[0-9].api" "!shipping.v0.api"]}"#);
+
+        let rule = parse_rule(&Edn::Symbol("shipping.ui".into()), rule, &[]).unwrap().unwrap();
+
+        assert!(rule.allow[0].matches("shipping.v2.api"));
+        assert_eq!(rule.except[0].to_string(), "shipping.v0.api");
+    }
+
+    #[test]
+    fn string_patterns_select_by_path() {
+        let rule = edn_map("{:restrict-to [com.acme.*]}");
+
+        let rule = parse_rule(&Edn::Str("src/acme/**".into()), rule, &[]).unwrap().unwrap();
+
+        assert!(matches!(rule.selector, Selector::Path(_)));
+        assert_eq!(rule.selector.to_string(), r#""src/acme/**""#);
+    }
+
+    #[test]
+    fn syntax_errors_are_located() {
+        let text = "{:src-dirs [\"src\"]\n :rules [app.core {:restrict-to [app.db 1.2.3]}]}";
+        let err = text.parse::<Edn>().unwrap_err();
+
+        let syntax_error = locate_syntax_error("ns-rules.edn", text, &err).unwrap();
+
+        assert_eq!((syntax_error.line.offset(), syntax_error.line.len()), (19, 49));
+        assert_eq!(&text[syntax_error.at.offset()..][..5], "1.2.3");
+    }
+
+    #[test]
+    fn expected_keywords_are_accepted() {
+        let config = edn_map(r#"{:src-dirs ["src"] :rules [] :profiles {}}"#);
+
+        assert!(check_keys(&config, CONFIG_KEYS, "the configuration").is_ok());
+        assert_eq!(keyword_name(":src-dirs"), Some("src-dirs"));
+    }
+
+    #[test]
+    fn configs_requiring_a_newer_version_are_refused() {
+        let min_version = |version: &str| Some(Edn::Str(version.into()));
+
+        assert!(check_min_version(None, "1.2.0").is_ok());
+        assert!(check_min_version(min_version("1.2"), "1.2.0").is_ok());
+        assert!(check_min_version(min_version("1.1.9"), "1.2.0").is_ok());
+        assert!(matches!(
+            check_min_version(min_version("1.10.0"), "1.2.0"),
+            Err(Problem::VersionTooOld { .. })
+        ));
+        assert!(matches!(
+            check_min_version(min_version("one"), "1.2.0"),
+            Err(Problem::BadMinVersion)
+        ));
+        assert!(matches!(
+            check_min_version(Some(Edn::Double(1.2.into())), "1.2.0"),
+            Err(Problem::BadMinVersion)
+        ));
+    }
+
+    #[test]
+    fn misspelt_keys_suggest_the_expected_keyword() {
+        for config in [r#"{:src_dirs ["src"]}"#, r#"{"src-dirs" ["src"]}"#, r#"{src-dirs ["src"]}"#] {
+            let problem = check_keys(&edn_map(config), CONFIG_KEYS, "the configuration").unwrap_err();
+
+            assert!(
+                matches!(problem, Problem::UnknownKey { expected: Some(":src-dirs"), .. }),
+                "{} gave {:?}", config, problem
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_rule_keys_are_errors() {
+        let rule = edn_map("{:restrict-to [com.acme.*] :restrict [com.acme.db]}");
+
+        let problem = parse_rule(&Edn::Symbol("com.acme.app".into()), rule, &[]).unwrap_err();
+
+        assert_eq!(
+            problem.to_string(),
+            "the rule 'com.acme.app' contains the unknown key :restrict"
+        );
+    }
+
+    #[test]
+    fn deny_only_rules_are_parsed() {
+        let rule = edn_map("{:deny [cheshire.*]}");
+
+        let rule = parse_rule(&Edn::Symbol("com.acme.app".into()), rule, &[]).unwrap().unwrap();
+
+        assert!(!rule.restricted);
+        assert!(rule.allow.is_empty());
+        assert_eq!(rule.deny[0].to_string(), "cheshire.*");
+    }
+
+    #[test]
+    fn negation_only_allow_lists_restrict_references() {
+        let rule = edn_map("{:restrict-to [!a.internal]}");
+
+        let rule = parse_rule(&Edn::Symbol("a.core".into()), rule, &[]).unwrap().unwrap();
+
+        assert!(rule.restricted);
+        assert!(rule.allow.is_empty());
+        assert_eq!(rule.except[0].to_string(), "a.internal");
+    }
+
+    #[test]
+    fn self_allows_the_rule_pattern_and_its_subtree() {
+        let rule = edn_map("{:restrict-to [:self clojure.*]}");
+
+        let rule = parse_rule(&Edn::Symbol("com.acme.app".into()), rule, &[]).unwrap().unwrap();
+
+        let allowed: Vec<_> = rule.allow.iter().map(ToString::to_string).collect();
+        assert_eq!(allowed, vec!["com.acme.app", "com.acme.app.*", "clojure.*"]);
+    }
+
+    #[test]
+    fn self_is_not_expanded_twice_for_subtree_patterns() {
+        let rule = edn_map("{:restrict-to [:self]}");
+
+        let rule = parse_rule(&Edn::Symbol("com.*.app.*".into()), rule, &[]).unwrap().unwrap();
+
+        let allowed: Vec<_> = rule.allow.iter().map(ToString::to_string).collect();
+        assert_eq!(allowed, vec!["com.*.app.*"]);
+    }
+
+    #[test]
+    fn self_cannot_be_used_with_path_rules() {
+        let rule = edn_map("{:restrict-to [:self]}");
+
+        assert!(parse_rule(&Edn::Str("src/**".into()), rule, &[]).is_err());
+    }
+
+    #[test]
+    fn left_of_self_allows_the_layers_below() {
+        let layers = ["app.entity.*".to_string(), "app.service.*".into(), "app.api.*".into()];
+        let rule = edn_map("{:restrict-to [:left-of-self clojure.*]}");
+
+        let rule = parse_rule(&Edn::Symbol("app.api.*".into()), rule, &layers).unwrap().unwrap();
+
+        let allowed: Vec<_> = rule.allow.iter().map(ToString::to_string).collect();
+        assert_eq!(allowed, vec!["app.entity.*", "app.service.*", "clojure.*"]);
+    }
+
+    #[test]
+    fn left_of_self_restricts_the_bottom_layer_to_itself() {
+        let layers = ["app.entity.*".to_string(), "app.service.*".into()];
+        let rule = edn_map("{:restrict-to [:left-of-self]}");
+
+        let rule = parse_rule(&Edn::Symbol("app.entity.*".into()), rule, &layers).unwrap().unwrap();
+
+        let allowed: Vec<_> = rule.allow.iter().map(ToString::to_string).collect();
+        assert_eq!(allowed, vec!["app.entity.*"]);
+    }
+
+    #[test]
+    fn left_of_self_requires_the_rule_to_be_a_layer() {
+        let layers = ["app.entity.*".to_string()];
+        let rule = edn_map("{:restrict-to [:left-of-self]}");
+
+        assert!(parse_rule(&Edn::Symbol("app.util.*".into()), rule, &layers).is_err());
+    }
+
+    #[test]
+    fn duplicate_patterns_are_merged_with_a_warning() {
+        let pattern = Edn::Symbol("com.acme.app".into());
+        let first = edn_map("{:restrict-to [com.acme.db] :deny [java.sql]}");
+        let second = edn_map(r#"{:restrict-to [com.acme.db com.acme.http] :doc "thin"}"#);
+        let mut report = Report::new(Default::default());
+
+        let mut rules = vec![];
+        add_rule(&mut rules, parse_rule(&pattern, first, &[]).unwrap().unwrap(), &mut report);
+        add_rule(&mut rules, parse_rule(&pattern, second, &[]).unwrap().unwrap(), &mut report);
+
+        assert_eq!(rules.len(), 1);
+        let allowed: Vec<_> = rules[0].allow.iter().map(ToString::to_string).collect();
+        assert_eq!(allowed, vec!["com.acme.db", "com.acme.http"]);
+        assert_eq!(rules[0].deny[0].to_string(), "java.sql");
+        assert_eq!(rules[0].doc.as_deref(), Some("thin"));
+        assert!(report.to_string().contains("'com.acme.app' is defined more than once"));
+    }
+
+    #[test]
+    fn deny_src_dirs_rules_are_parsed() {
+        let rule = edn_map(r#"{:deny-src-dirs ["test"]}"#);
+
+        let rule = parse_rule(&Edn::Str("src/**".into()), rule, &[]).unwrap().unwrap();
+
+        assert!(rule.allow.is_empty());
+        assert_eq!(rule.deny_src_dirs, vec!["test"]);
+    }
+
+    #[test]
+    fn when_path_rules_are_parsed() {
+        let rule = edn_map(r#"{:restrict-to [app.service.*] :when-path "**/*_handler.clj"}"#);
+
+        let rule = parse_rule(&Edn::Symbol("app.web.*".into()), rule, &[]).unwrap().unwrap();
+
+        assert_eq!(rule.when_path.unwrap().to_string(), r#""**/*_handler.clj""#);
+    }
+
+    #[test]
+    fn rules_with_different_path_conditions_are_not_merged() {
+        let pattern = Edn::Symbol("app.web.*".into());
+        let handlers = edn_map(r#"{:restrict-to [app.service.*] :when-path "**/*_handler.clj"}"#);
+        let others = edn_map("{:restrict-to [app.web.*]}");
+        let mut report = Report::new(Default::default());
+
+        let mut rules = vec![];
+        add_rule(&mut rules, parse_rule(&pattern, handlers, &[]).unwrap().unwrap(), &mut report);
+        add_rule(&mut rules, parse_rule(&pattern, others, &[]).unwrap().unwrap(), &mut report);
+
+        assert_eq!(rules.len(), 2);
+        assert!(!report.to_string().contains("defined more than once"));
+    }
+
+    #[test]
+    fn same_root_rules_are_parsed() {
+        let rule = edn_map("{:allow-same-root true}");
+
+        let rule = parse_rule(&Edn::Symbol("app.*".into()), rule, &[]).unwrap().unwrap();
+
+        assert!(rule.allow_same_root);
+        assert!(parse_rule(&Edn::Symbol("app.*".into()), edn_map("{:allow-same-root 1}"), &[]).is_err());
+    }
+
+    #[test]
+    fn rules_extend_templates() {
+        let templates = parse_templates(
+            "{:leaf {:restrict-to [clojure.*] :deny [java.sql] :doc \"A leaf\"}}".parse().unwrap()
+        ).unwrap();
+        let rule = edn_map("{:extends :leaf :restrict-to [app.util] :doc \"The db\"}");
+
+        let rule = extend_template("app.db", rule, &templates).unwrap();
+        let rule = parse_rule(&Edn::Symbol("app.db".into()), rule, &[]).unwrap().unwrap();
+
+        let allowed: Vec<_> = rule.allow.iter().map(ToString::to_string).collect();
+        assert_eq!(allowed, vec!["clojure.*", "app.util"]);
+        assert_eq!(rule.deny.len(), 1);
+        assert_eq!(rule.doc.as_deref(), Some("The db"));
+    }
+
+    #[test]
+    fn extending_an_undefined_template_is_an_error() {
+        let templates = parse_templates("{:leaf {:deny [java.sql]}}".parse().unwrap()).unwrap();
+
+        let result = extend_template("app.db", edn_map("{:extends :branch}"), &templates);
+
+        assert!(matches!(result, Err(Problem::UnknownTemplate { template, .. }) if template == ":branch"));
+        assert!(extend_template("app.db", edn_map("{:extends \"leaf\"}"), &templates).is_err());
+        assert!(parse_templates("{:leaf {:extends :branch}}".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn parent_rules_are_parsed() {
+        let rule = edn_map("{:allow-parents true}");
+
+        let rule = parse_rule(&Edn::Symbol("app.*".into()), rule, &[]).unwrap().unwrap();
+
+        assert!(rule.allow_parents);
+        assert!(parse_rule(&Edn::Symbol("app.*".into()), edn_map("{:allow-parents \"yes\"}"), &[]).is_err());
+    }
+
+    #[test]
+    fn included_rules_follow_the_configuration_in_order_of_path() {
+        let mut report = Report::new(Default::default());
+
+        let config = read_file("tests/config/include/ns-rules.edn", None, None, &mut report).unwrap();
+
+        let patterns: Vec<_> = config.rules.iter().map(|rule| rule.selector.pattern()).collect();
+        assert_eq!(patterns, vec!["app.*", "billing.*", "shipping.*"]);
+        assert_eq!(config.rules[1].allow[0].to_string(), "clojure.*");
+    }
+
+    #[test]
+    fn included_files_may_only_contain_rules() {
+        let fragment = edn_map(r#"{:rules [] :src-dirs ["src"]}"#);
+
+        let problem = check_keys(&fragment, FRAGMENT_KEYS, "an included file").unwrap_err();
+
+        assert!(matches!(problem, Problem::UnknownKey { key, .. } if key == ":src-dirs"));
+    }
+
+    #[test]
+    fn schema_describes_every_key() {
+        let schema = schema();
+
+        for key in CONFIG_KEYS.iter().chain(RULE_KEYS).chain(FRAGMENT_KEYS).chain(SRC_DIR_KEYS) {
+            assert!(schema.lines().any(|line| line.trim_start().starts_with(key.name)), "{} is missing", key.name);
+        }
+        assert!(schema.starts_with("Configuration\n  :src-dirs "));
+    }
+
+    #[test]
+    fn path_globs_are_allowed_alongside_namespace_patterns() {
+        let rule = edn_map(r#"{:restrict-to [com.acme.* "**/shared/**" "!**/shared/internal/**" "!com.acme.db"]}"#);
+
+        let rule = parse_rule(&Edn::Symbol("com.acme.app".into()), rule, &[]).unwrap().unwrap();
+
+        assert_eq!(rule.allow[0].to_string(), "com.acme.*");
+        assert!(rule.allow_paths[0].matches("src/shared/util.clj"));
+        assert!(rule.except_paths[0].matches("src/shared/internal/impl.clj"));
+        assert_eq!(rule.except[0].to_string(), "com.acme.db");
+        assert!(parse_rule(&Edn::Symbol("com.acme.app".into()), edn_map(r#"{:restrict-to ["src/[a"]}"#), &[]).is_err());
+    }
+
+    #[test]
+    fn forbidden_pairs_become_denied_namespaces() {
+        let mut report = Report::new(Default::default());
+        let mut rules = vec![];
+        parse_rules("[app.domain.* {:restrict-to [app.*]}]".parse().unwrap(), &BTreeMap::new(), &[], &mut rules, &mut report).unwrap();
+
+        let forbid = "[[app.domain.* app.infra.*] [app.api.* app.infra.db] [app.api.* app.infra.db]]";
+        parse_forbidden_pairs(forbid.parse().unwrap(), &mut rules).unwrap();
+
+        let denied: Vec<_> = rules.iter().map(|rule| (rule.selector.pattern(), rule.deny.iter().map(ToString::to_string).collect::<Vec<_>>())).collect();
+        assert_eq!(denied, vec![("app.domain.*", vec!["app.infra.*".to_string()]), ("app.api.*", vec!["app.infra.db".to_string()])]);
+        assert!(report.warnings().is_empty());
+
+        let rules: Vec<_> = rules.into_iter().map(|rule| rule.compile(&[])).collect();
+        let violations = crate::check_source("app.domain.order", "(ns app.domain.order (:require app.infra.queue app.util))", &rules);
+        assert_eq!(violations.iter().map(|violation| violation.ref_ns.as_str()).collect::<Vec<_>>(), vec!["app.infra.queue"]);
+
+        assert!(matches!(parse_forbidden_pairs("[[app.*]]".parse().unwrap(), &mut vec![]), Err(Problem::BadForbid)));
+        assert!(matches!(parse_forbidden_pairs("[app.* lib.*]".parse().unwrap(), &mut vec![]), Err(Problem::BadForbid)));
+    }
+
+    #[test]
+    fn transitive_allows_inherit_the_allow_lists_they_lead_to() {
+        let mut report = Report::new(Default::default());
+        let mut rules = vec![];
+        let edn = "[app.api      {:restrict-to [(:transitive app.facade) !app.db.admin]}
+                    app.facade   {:restrict-to [(:transitive app.service) app.util] :deny [app.http]}
+                    app.service  {:restrict-to [app.db.* \"shared/**\" (:transitive app.facade)] :allow-parents true}]";
+        parse_rules(edn.parse().unwrap(), &BTreeMap::new(), &[], &mut rules, &mut report).unwrap();
+
+        resolve_transitive_allows(&mut rules).unwrap();
+
+        let allowed = |rule: &Rule| rule.allow.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(allowed(&rules[0]), vec!["app.facade", "app.util", "app.service", "app.db.*"]);
+        assert_eq!(allowed(&rules[1]), vec!["app.util", "app.service", "app.db.*", "app.facade"]);
+        assert_eq!(rules[0].allow_paths.iter().map(|path| path.pattern.as_str()).collect::<Vec<_>>(), vec!["shared/**"]);
+        assert!(rules[0].deny.is_empty() && !rules[0].allow_parents);
+
+        let files: Vec<_> = ["app.db.users", "app.db.admin", "app.http"].iter()
+            .map(|ns| crate::ClojureSourceFile { entry: format!("{}src/{}.clj", ns, ns.replace('.', "/")), path_start: ns.len(), tags: vec![] })
+            .collect();
+        let rules: Vec<_> = rules.into_iter().map(|rule| rule.compile(&files)).collect();
+        let violations = crate::check_source("app.api", "(ns app.api (:require app.db.users app.db.admin app.http))", &rules);
+        assert_eq!(violations.iter().map(|violation| violation.ref_ns.as_str()).collect::<Vec<_>>(), vec!["app.db.admin", "app.http"]);
+    }
+
+    #[test]
+    fn transitive_allows_must_lead_to_a_restricting_rule() {
+        let mut report = Report::new(Default::default());
+        let mut rules = vec![];
+        let edn = "[app.api {:restrict-to [(:transitive app.facade)]} app.util {:deny [app.db]}]";
+        parse_rules(edn.parse().unwrap(), &BTreeMap::new(), &[], &mut rules, &mut report).unwrap();
+        assert!(matches!(resolve_transitive_allows(&mut rules), Err(Problem::BadRule { .. })));
+
+        let mut rules = vec![];
+        let edn = "[app.api {:restrict-to [(:transitive app.util)]} app.util {:deny [app.db]}]";
+        parse_rules(edn.parse().unwrap(), &BTreeMap::new(), &[], &mut rules, &mut report).unwrap();
+        assert!(matches!(resolve_transitive_allows(&mut rules), Err(Problem::BadRule { .. })));
+
+        assert!(parse_rule(&Edn::Symbol("app.api".into()), edn_map("{:restrict-to [(:via app.util)]}"), &[]).is_err());
+    }
+
+    #[test]
+    fn relative_source_dirs_are_moved_under_the_root() {
+        let mut report = Report::new(Default::default());
+        let mut rules = vec![];
+        parse_rules("[app.* {:deny-src-dirs [\"test\" \"/opt/shared\"]}]".parse().unwrap(), &BTreeMap::new(), &[], &mut rules, &mut report).unwrap();
+        let mut config = Config {
+            source_dirs: vec!["src".into(), "test".into(), "/opt/shared".into(), "modules/*/src".into()],
+            rules,
+            ..Config::default()
+        };
+
+        config.move_under(Path::new("/ci/workspace"));
+
+        let paths: Vec<_> = config.source_dirs.iter().map(|dir| dir.path.as_str()).collect();
+        assert_eq!(paths, vec!["/ci/workspace/src", "/ci/workspace/test", "/opt/shared", "/ci/workspace/modules/*/src"]);
+        assert_eq!(config.rules[0].deny_src_dirs, vec!["/ci/workspace/test", "/opt/shared"]);
+    }
+
+    #[test]
+    fn rule_tags_are_parsed() {
+        let rule = parse_rule(&Edn::Symbol("app.*".into()), edn_map("{:deny [app.db] :tags [:security :fast]}"), &[]).unwrap().unwrap();
+
+        assert_eq!(rule.tags, vec!["security", "fast"]);
+        assert!(rule.to_string().ends_with(":tags [:security :fast]}"));
+        assert!(parse_rule(&Edn::Symbol("app.*".into()), edn_map("{:deny [app.db] :tags [security]}"), &[]).is_err());
+    }
+
+    #[test]
+    fn no_external_rules_are_parsed() {
+        let rule = parse_rule(&Edn::Symbol("app.*".into()), edn_map("{:no-external true}"), &[]).unwrap().unwrap();
+
+        assert!(rule.no_external);
+        assert!(parse_rule(&Edn::Symbol("app.*".into()), edn_map("{:no-external :yes}"), &[]).is_err());
+    }
+
+    #[test]
+    fn version_rules_are_parsed() {
+        let rule = edn_map("{:restrict-to [:same-or-lower-version app.util]}");
+
+        let rule = parse_rule(&Edn::Symbol("app.api.*".into()), rule, &[]).unwrap().unwrap();
+
+        assert!(rule.allow_lower_versions);
+        assert_eq!(rule.allow.len(), 1);
+    }
+
+    #[test]
+    fn tagged_rules_are_parsed() {
+        let rule = edn_map("{:tagged :!internal :deny-tagged [:internal :experimental]}");
+
+        let rule = parse_rule(&Edn::Symbol("app.*".into()), rule, &[]).unwrap().unwrap();
+
+        assert_eq!(rule.tagged, Some(TagCondition { tag: "internal".into(), present: false }));
+        assert_eq!(rule.deny_tagged, vec!["internal", "experimental"]);
+        assert!(parse_rule(&Edn::Symbol("app.*".into()), edn_map("{:deny-tagged [internal]}"), &[]).is_err());
+    }
+
+    #[test]
+    fn required_references_are_parsed() {
+        let rule = edn_map("{:must-reference [com.acme.auth com.acme.middleware.*]}");
+
+        // Requiring references is effect enough for a rule of its own
+        let rule = parse_rule(&Edn::Symbol("com.acme.handlers.*".into()), rule, &[]).unwrap().unwrap();
+
+        let required: Vec<_> = rule.must_reference.iter().map(ToString::to_string).collect();
+        assert_eq!(required, vec!["com.acme.auth", "com.acme.middleware.*"]);
+        assert!(rule.allow.is_empty());
+        assert!(parse_rule(&Edn::Symbol("app.*".into()), edn_map("{:must-reference com.acme.auth}"), &[]).is_err());
+    }
+
+    #[test]
+    fn severities_are_parsed() {
+        let severity = |rule: &str| parse_rule(&Edn::Symbol("app.*".into()), edn_map(rule), &[]).map(|rule| rule.unwrap().severity);
+
+        assert_eq!(severity("{:deny [db.*]}").unwrap(), Severity::Error);
+        assert_eq!(severity("{:deny [db.*] :severity :error}").unwrap(), Severity::Error);
+        assert_eq!(severity("{:deny [db.*] :severity :warn}").unwrap(), Severity::Warn);
+        assert!(severity("{:deny [db.*] :severity :info}").is_err());
+        assert!(severity("{:deny [db.*] :severity \"warn\"}").is_err());
+    }
+
+    #[test]
+    fn rule_docs_are_parsed() {
+        let rule = edn_map(r#"{:restrict-to [com.acme.*] :doc "keep the app portable"}"#);
+
+        let rule = parse_rule(&Edn::Symbol("com.acme.app".into()), rule, &[]).unwrap().unwrap();
+
+        assert_eq!(rule.doc.as_deref(), Some("keep the app portable"));
+    }
 }
@@ -0,0 +1,218 @@
+//! Measures how far the rules and the code have drifted apart: the entries of
+//! each rule's allow list that no reference needs, and the references that
+//! the rules forbid.
+
+use crate::{
+    ns, report::Warning, ClojureSourceFile, CompiledRule, Encoding, Report,
+};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+#[derive(Debug)]
+pub(crate) struct Drift<'r> {
+    rules: &'r [CompiledRule],
+    /// The `:restrict-to` entries that no reference matches, by the index of
+    /// their rule.
+    dead_allows: BTreeSet<(usize, String)>,
+    /// The references that break a rule, by the index of the rule, from the
+    /// referencing namespace to the referenced namespace.
+    forbidden: BTreeSet<(usize, String, String)>,
+}
+
+impl<'r> Drift<'r> {
+    /// Checks the references of every source file against the rule that
+    /// governs it, noting which allow list entries each reference is allowed
+    /// by.
+    pub(crate) fn collect(
+        rules: &'r [CompiledRule],
+        source_files: &[ClojureSourceFile],
+        encoding: Encoding,
+        report: &mut Report,
+    ) -> Self {
+        // Entries are struck off as references are found that they allow
+        let mut dead_allows: BTreeSet<(usize, String)> = rules
+            .iter()
+            .enumerate()
+            .flat_map(|(index, rule)| {
+                let entries = rule.allow.iter().map(ToString::to_string);
+                let paths = rule.allow_paths.iter().map(ToString::to_string);
+                entries.chain(paths).map(move |entry| (index, entry))
+            })
+            .collect();
+        let mut forbidden = BTreeSet::new();
+
+        // Path globs allow the namespaces of the files that they match
+        let mut paths: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+        for file in source_files {
+            paths.entry(file.namespace()).or_default().push(file.path());
+        }
+
+        for file in source_files {
+            let (index, rule) = match rules
+                .iter()
+                .enumerate()
+                .find(|(_, rule)| rule.matches(file))
+            {
+                Some(rule) => rule,
+                None => continue,
+            };
+
+            let code = match file.read(encoding, report) {
+                Some(code) => code,
+                None => continue,
+            };
+            let references = match ns::read_references(&code) {
+                Ok(references) => references.unwrap_or_default(),
+                Err(error) => {
+                    report.file_skipped(Warning::skipped(
+                        file.path(),
+                        format!(
+                            "failed to read the ns form of {}: {}",
+                            file.path(),
+                            error
+                        ),
+                    ));
+                    continue;
+                }
+            };
+
+            for reference in references {
+                let namespace = reference.namespace;
+                if rule
+                    .why_forbidden_from(file.namespace(), &namespace)
+                    .is_some()
+                {
+                    forbidden.insert((
+                        index,
+                        file.namespace().to_owned(),
+                        namespace,
+                    ));
+                    continue;
+                }
+
+                for entry in
+                    rule.allow.iter().filter(|ns| ns.matches(&namespace))
+                {
+                    dead_allows.remove(&(index, entry.to_string()));
+                }
+                let referenced_paths =
+                    paths.get(namespace.as_str()).into_iter().flatten();
+                for path in referenced_paths {
+                    for glob in rule
+                        .allow_paths
+                        .iter()
+                        .filter(|glob| glob.matches(path))
+                    {
+                        dead_allows.remove(&(index, glob.to_string()));
+                    }
+                }
+            }
+        }
+
+        Self {
+            rules,
+            dead_allows,
+            forbidden,
+        }
+    }
+
+    /// The number of dead allow list entries and forbidden references, which
+    /// is zero when the rules and the code agree.
+    pub(crate) fn score(&self) -> usize {
+        self.dead_allows.len() + self.forbidden.len()
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        json!({
+            "dead-allows": self
+                .dead_allows
+                .iter()
+                .map(|(index, entry)| {
+                    json!({
+                        "rule": self.rules[*index].selector.to_string(),
+                        "allow": entry,
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "forbidden": self
+                .forbidden
+                .iter()
+                .map(|(index, namespace, reference)| {
+                    json!({
+                        "rule": self.rules[*index].selector.to_string(),
+                        "namespace": namespace,
+                        "reference": reference,
+                    })
+                })
+                .collect::<Vec<_>>(),
+            "score": self.score(),
+        })
+    }
+}
+
+impl fmt::Display for Drift<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, entry) in self.dead_allows.iter() {
+            writeln!(
+                f,
+                "DEAD {} :restrict-to {}",
+                self.rules[*index].selector, entry
+            )?;
+        }
+        for (index, namespace, reference) in self.forbidden.iter() {
+            writeln!(
+                f,
+                "FORBIDDEN {} {} -> {}",
+                self.rules[*index].selector, namespace, reference
+            )?;
+        }
+
+        writeln!(
+            f,
+            "Drift score {}: {} dead allow{}, {} forbidden reference{}",
+            self.score(),
+            self.dead_allows.len(),
+            if self.dead_allows.len() == 1 { "" } else { "s" },
+            self.forbidden.len(),
+            if self.forbidden.len() == 1 { "" } else { "s" },
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test::rule, Rule};
+
+    #[test]
+    fn unused_allows_and_forbidden_references_are_drift() {
+        let files = [ClojureSourceFile {
+            entry: "com.acme.apptests/fixtures/prefix_list.clj".into(),
+            path_start: "com.acme.app".len(),
+            tags: vec![],
+        }];
+        let rules = vec![Rule {
+            allow: vec![
+                "com.acme.*".parse().unwrap(),
+                "clojure.set".parse().unwrap(),
+                "java.*".parse().unwrap(),
+            ],
+            deny: vec!["com.acme.db".parse().unwrap()],
+            ..rule("com.acme.app")
+        }
+        .compile(&files)];
+        let mut report = Report::new(Default::default());
+
+        let drift = Drift::collect(&rules, &files, Encoding::Utf8, &mut report);
+
+        assert_eq!(
+            drift.to_string(),
+            "DEAD com.acme.app :restrict-to java.*\n\
+             FORBIDDEN com.acme.app com.acme.app -> com.acme.db\n\
+             Drift score 2: 1 dead allow, 1 forbidden reference\n"
+        );
+    }
+}
@@ -0,0 +1,4522 @@
+//! Applies namespace referencing rules to Clojure source code.
+//!
+//! Most use is through the `ns-rules` command, which calls [`run`], but
+//! [`check_source`] checks code that is held in memory, such as an unsaved
+//! editor buffer, without touching the filesystem, and [`check_source_tree`]
+//! passes each violation in a source tree to a callback as it is found.
+
+#![feature(iter_intersperse)]
+
+use clap::{AppSettings, ArgEnum, Clap};
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::{WalkBuilder, WalkState};
+use miette::{Diagnostic, DiagnosticResult, NamedSource};
+use regex::Regex;
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsStr,
+    fmt, fs,
+    io::{self, IsTerminal},
+    iter,
+    num::NonZeroUsize,
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+};
+use thiserror::Error;
+use tracing::{debug, trace};
+use tracing_subscriber::EnvFilter;
+
+mod baseline;
+mod compare;
+mod config;
+mod drift;
+mod lsp;
+mod ns;
+mod profile;
+mod report;
+mod stats;
+mod suggest;
+mod transitive;
+
+use config::SourceDir;
+pub use report::Violation;
+use report::{
+    ColorChoice, DisplayOptions, Format, GroupBy, Pluralise, Report, Stream,
+    Theme, Verbosity, Warning, WarningCode,
+};
+
+/// Applies namespace referencing rules to Clojure source code.
+#[derive(Clap)]
+#[clap(version = "1.0", author = "Nick Ogden <nick@nickogden.org>")]
+#[clap(setting = AppSettings::ColoredHelp)]
+pub(crate) struct Options {
+    /// The path to the configuration file.
+    #[clap(short, long, default_value = "ns-rules.edn")]
+    config: PathBuf,
+
+    /// The directory in which relative source directories are found, rather
+    /// than the working directory.
+    #[clap(long, value_name = "DIR")]
+    root: Option<PathBuf>,
+
+    /// The key under which the configuration is nested in the configuration
+    /// file. Defaults to ':ns-rules' for files named deps.edn.
+    #[clap(long, value_name = "KEY")]
+    config_key: Option<String>,
+
+    /// The configuration profile to merge over the base configuration.
+    #[clap(short, long)]
+    profile: Option<String>,
+
+    /// Print the rule matched by every namespace, without checking for
+    /// violations.
+    #[clap(long, arg_enum)]
+    audit: Option<AuditFormat>,
+
+    /// The format in which to print the report.
+    #[clap(short, long, arg_enum, default_value = "graphical")]
+    format: Format,
+
+    /// Print statistics about the references between namespaces, without
+    /// checking for violations.
+    #[clap(long)]
+    stats: bool,
+
+    /// Print the additions to each rule's :restrict-to that would allow every
+    /// current reference, rather than checking for violations.
+    #[clap(long)]
+    suggest: bool,
+
+    /// Compare the violations with those in a previous report, printed with
+    /// '--format json', failing only if there are new violations.
+    #[clap(long, value_name = "PREVIOUS_REPORT")]
+    compare: Option<PathBuf>,
+
+    /// Accept the violations recorded in the given baseline, failing only if
+    /// there are others.
+    #[clap(long, value_name = "BASELINE")]
+    baseline: Option<PathBuf>,
+
+    /// Add the current violations to the baseline, keeping those already
+    /// recorded, rather than reporting them. Creates the baseline if it
+    /// doesn't exist.
+    #[clap(
+        long,
+        requires = "baseline",
+        conflicts_with_all = &["compare", "count-only"]
+    )]
+    baseline_update: bool,
+
+    /// Always exit with status 0, even if there are violations, for advisory
+    /// runs. Problems with the configuration still fail.
+    #[clap(long)]
+    exit_zero: bool,
+
+    /// Only fail when there are more than N violations, so that they can be
+    /// fixed gradually. Ungoverned namespaces still fail.
+    #[clap(long, value_name = "N")]
+    fail_after: Option<usize>,
+
+    /// Also check the namespaces that each namespace depends on through
+    /// others, reporting the chain of references to any that its rule
+    /// forbids.
+    #[clap(long)]
+    transitive: bool,
+
+    /// Fail if any namespace is not governed by a rule.
+    #[clap(long)]
+    require_coverage: bool,
+
+    /// Fail if no source files are found, which usually means that the
+    /// source directories are misconfigured.
+    #[clap(long)]
+    error_on_empty: bool,
+
+    /// Also check the fully qualified symbols in the code of each namespace,
+    /// such as com.acme.db/query, that refer to namespaces in the source tree.
+    #[clap(long)]
+    deep: bool,
+
+    /// Check every namespace that a file declares, with the references of
+    /// each `ns` form attributed to the namespace it declares, rather than
+    /// only the first.
+    #[clap(long)]
+    multi_ns: bool,
+
+    /// Check the code read from stdin, as though it were a file in the source
+    /// tree, rather than the files in the source directories.
+    #[clap(long, conflicts_with_all = &["transitive", "baseline-update"])]
+    stdin: bool,
+
+    /// The namespace of the code read by --stdin when it has no ns form, such
+    /// as a snippet. A namespace that the code declares takes precedence.
+    #[clap(long, value_name = "NAMESPACE", requires = "stdin")]
+    assume_namespace: Option<String>,
+
+    /// The number of files to check at once. Defaults to the number of CPUs.
+    #[clap(short, long, value_name = "N")]
+    jobs: Option<NonZeroUsize>,
+
+    /// Ignore the rule with the given pattern, as written in the
+    /// configuration. May be repeated.
+    #[clap(long, value_name = "PATTERN", multiple_occurrences = true)]
+    disable_rule: Vec<String>,
+
+    /// Run only the rules with the given tag in their `:tags`. May be
+    /// repeated, to run the rules with any of the tags.
+    #[clap(long, value_name = "TAG", multiple_occurrences = true)]
+    tag: Vec<String>,
+
+    /// Leave warnings of the given kind out of the report. May be repeated.
+    #[clap(long, arg_enum, value_name = "CODE", multiple_occurrences = true)]
+    allow_warning: Vec<WarningCode>,
+
+    /// Fail if there are warnings of the given kind. May be repeated.
+    #[clap(long, arg_enum, value_name = "CODE", multiple_occurrences = true)]
+    deny_warning: Vec<WarningCode>,
+
+    /// Print the regexes compiled from the rule with the given pattern, as
+    /// written in the configuration, and exit.
+    #[clap(long, value_name = "PATTERN")]
+    debug_rule: Option<String>,
+
+    /// Print the length of each rule's regex, and the time taken to compile
+    /// it and to match references against it, to stderr.
+    #[clap(long)]
+    profile_regex: bool,
+
+    /// Warn about each entry of a rule's :restrict-to that another entry
+    /// already allows, to keep the configuration minimal.
+    #[clap(long)]
+    check_redundant_allows: bool,
+
+    /// Print the configured rules, along with their documentation, and exit.
+    #[clap(long)]
+    explain: bool,
+
+    /// Print every key that the configuration accepts, with the type of its
+    /// value and what it does, and exit.
+    #[clap(long)]
+    config_schema: bool,
+
+    /// Print whether stdout is a terminal, the variables that decide whether
+    /// colour is used, and a sample violation in the chosen colours, and exit.
+    #[clap(long)]
+    color_test: bool,
+
+    /// Include the documentation of the broken rule with each violation.
+    #[clap(short, long, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Explain why each violation breaks its rule.
+    #[clap(long)]
+    why: bool,
+
+    /// Print only the violations, omitting warnings and totals.
+    #[clap(short, long)]
+    quiet: bool,
+
+    /// Print only the number of violations, with any warnings on stderr.
+    #[clap(long, conflicts_with = "compare")]
+    count_only: bool,
+
+    /// Report the share of the references made by governed namespaces that
+    /// their rules allow, as a measure of the health of the architecture.
+    #[clap(long)]
+    compliance: bool,
+
+    /// Write each JUnit test suite to a file of its own in the given
+    /// directory, rather than printing them. Requires --format junit.
+    #[clap(long, value_name = "DIR")]
+    output_dir: Option<PathBuf>,
+
+    /// Print the violations of rules at `:severity :warn` to the given
+    /// stream, and the rest of the report to the other, so that they can be
+    /// captured apart.
+    #[clap(
+        long,
+        arg_enum,
+        value_name = "STREAM",
+        conflicts_with = "output-dir"
+    )]
+    split_severity: Option<Stream>,
+
+    /// Omit the totals printed after the violations.
+    #[clap(long)]
+    no_summary: bool,
+
+    /// The number of rules, and of namespaces, with the most violations to
+    /// list in the summary.
+    #[clap(long, value_name = "N", default_value = "5")]
+    top: usize,
+
+    /// When to use colour in the report.
+    #[clap(long, arg_enum, default_value = "auto")]
+    color: ColorChoice,
+
+    /// The colours used to draw violations.
+    #[clap(long, arg_enum, default_value = "default")]
+    theme: Theme,
+
+    /// How to group the violations in the graphical report.
+    #[clap(long, arg_enum, default_value = "none")]
+    group_by: GroupBy,
+
+    /// Show the absolute paths of files in violations, rather than paths
+    /// relative to the working directory.
+    #[clap(long)]
+    absolute_paths: bool,
+
+    /// How to read source files that are not valid UTF-8.
+    #[clap(long, arg_enum, default_value = "utf8")]
+    encoding: Encoding,
+
+    /// How the namespace of each source file is derived from its path.
+    #[clap(long, arg_enum, default_value = "clojure")]
+    ns_mapping: NamespaceMapping,
+
+    /// Log what ns-rules is doing to stderr, at the given level, or as
+    /// directed by RUST_LOG. Logging is off by default.
+    #[clap(long, value_name = "LEVEL")]
+    log_level: Option<EnvFilter>,
+
+    /// The number of lines of context to print around each violation.
+    #[clap(short = 'n', long, default_value = "4")]
+    context_lines: usize,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Run a language server, over stdin and stdout, that publishes the
+    /// violations in each open Clojure buffer as diagnostics.
+    Lsp,
+    /// Report the allow list entries that no reference needs, and the
+    /// references that the rules forbid, to show how far the rules and the
+    /// code have drifted apart.
+    AuditDrift,
+    /// Browse the violations in the terminal, listed by rule and namespace,
+    /// with the source of the one selected. Requires ns-rules to be built
+    /// with the tui feature.
+    Tui,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum AuditFormat {
+    Json,
+}
+
+/// How source files are read.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    /// Files that are not valid UTF-8 are skipped.
+    Utf8,
+    /// Invalid UTF-8 sequences are replaced, so that the rest of the file can
+    /// still be checked.
+    Lossy,
+}
+
+/// A convention for naming source files after the namespaces they declare.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+enum NamespaceMapping {
+    /// The '-' in a namespace is a '_' in the path, so that com/my_org/core.clj
+    /// is com.my-org.core.
+    Clojure,
+    /// As for Clojure, but also undoes the names ClojureScript gives to
+    /// special characters, such as _QMARK_ for '?', and to JavaScript reserved
+    /// words, such as default$ for default.
+    Clojurescript,
+}
+
+impl NamespaceMapping {
+    /// The namespace of the source file at `path`, relative to its source
+    /// directory.
+    ///
+    /// The namespace is built from the components of the path, rather than by
+    /// splitting it on a separator, so that it is the same whichever
+    /// separators the path contains. On Windows a path may contain both '\'
+    /// and '/'.
+    fn namespace_of(self, path: &Path) -> Option<String> {
+        // com/my_org/core.clj -> [com, my_org, core] -> com.my-org.core
+        let path = path.with_extension("");
+        let segments = path
+            .components()
+            .map(|segment| segment.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()?;
+
+        let segments: Vec<String> = match self {
+            NamespaceMapping::Clojure => segments
+                .into_iter()
+                .map(|segment| segment.replace('_', "-"))
+                .collect(),
+            NamespaceMapping::Clojurescript => {
+                segments.into_iter().map(demunge_cljs).collect()
+            }
+        };
+
+        Some(segments.join("."))
+    }
+}
+
+/// The special characters that ClojureScript replaces in the names of files.
+const CLJS_MUNGED_CHARS: &[(&str, &str)] = &[
+    ("_BANG_", "!"),
+    ("_QMARK_", "?"),
+    ("_STAR_", "*"),
+    ("_PLUS_", "+"),
+    ("_GT_", ">"),
+    ("_LT_", "<"),
+    ("_EQ_", "="),
+    ("_SINGLEQUOTE_", "'"),
+];
+
+/// The JavaScript reserved words that ClojureScript suffixes with '$' when
+/// they appear as a segment of a namespace.
+const JS_RESERVED_WORDS: &[&str] = &[
+    "abstract",
+    "boolean",
+    "break",
+    "byte",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "final",
+    "finally",
+    "float",
+    "for",
+    "function",
+    "goto",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "int",
+    "interface",
+    "let",
+    "long",
+    "native",
+    "new",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "static",
+    "super",
+    "switch",
+    "synchronized",
+    "this",
+    "throw",
+    "throws",
+    "transient",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "volatile",
+    "while",
+    "with",
+    "yield",
+];
+
+/// The segment of a namespace that ClojureScript names `segment` in a path.
+fn demunge_cljs(segment: &str) -> String {
+    if let Some(word) = segment.strip_suffix('$') {
+        if JS_RESERVED_WORDS.contains(&word) {
+            return word.to_owned();
+        }
+    }
+
+    let mut demunged = segment.to_owned();
+    for (munged, c) in CLJS_MUNGED_CHARS {
+        demunged = demunged.replace(munged, c);
+    }
+    demunged.replace('_', "-")
+}
+
+impl Options {
+    fn display_options(&self) -> DisplayOptions {
+        let verbosity = if self.quiet {
+            Verbosity::Quiet
+        } else if self.verbose {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        };
+
+        DisplayOptions {
+            format: self.format,
+            color: self.color,
+            theme: self.theme,
+            group_by: self.group_by,
+            verbosity,
+            summary: !self.no_summary,
+            top: self.top,
+            absolute_paths: self.absolute_paths,
+            why: self.why,
+            stream: self.streams(),
+            allowed_warnings: self.allow_warning.clone(),
+            denied_warnings: self.deny_warning.clone(),
+            compliance: self.compliance,
+            split_severity: self.split_severity,
+        }
+    }
+
+    /// Whether failures are printed as they are found, which is only the case
+    /// for the ndjson format when the report itself is to be printed.
+    fn streams(&self) -> bool {
+        self.format == Format::Ndjson
+            && self.compare.is_none()
+            && !self.count_only
+            && !self.baseline_update
+    }
+
+    /// Whether to print how many namespaces and rules were found before
+    /// checking them, which only suits the graphical report.
+    fn show_banner(&self) -> bool {
+        self.format == Format::Graphical && !self.quiet && !self.count_only
+    }
+
+    /// The status to exit with, given the `status` of the checks.
+    fn exit_status(&self, status: i32) -> i32 {
+        if self.exit_zero {
+            0
+        } else {
+            status
+        }
+    }
+}
+
+/// Runs the `ns-rules` command with the arguments it was given, exiting once
+/// the report has been printed.
+pub fn run() -> DiagnosticResult<()> {
+    let options = Options::parse();
+    init_logging(options.log_level.clone(), options.color);
+    if let Some(Command::Lsp) = options.command {
+        return lsp::serve(lsp::ConfigSource {
+            path: options.config,
+            key: options.config_key,
+            profile: options.profile,
+        });
+    }
+    if options.config_schema {
+        print!("{}", config::schema());
+        process::exit(0);
+    }
+    if options.color_test {
+        print!("{}", report::ColorTest(&options.display_options()));
+        process::exit(0);
+    }
+    if options.output_dir.is_some() && options.format != Format::Junit {
+        Err(OutputDirError::NotJunit)?
+    }
+    let mut report = Report::new(options.display_options());
+    if let Some(budget) = options.fail_after {
+        report.set_budget(budget);
+    }
+    let mut config = config::read_file(
+        &options.config,
+        options.config_key.as_deref(),
+        options.profile.as_deref(),
+        &mut report,
+    )?;
+    disable_rules(&mut config.rules, &options.disable_rule, &mut report);
+    if options.check_redundant_allows {
+        warn_redundant_allows(&config.rules, &mut report);
+    }
+    if let Some(root) = &options.root {
+        config.move_under(root);
+    }
+
+    if options.explain {
+        for rule in config.rules.iter() {
+            println!("{}", rule);
+        }
+        process::exit(0);
+    }
+
+    let mut source_files =
+        find_source_files(&config.source_dirs, options.ns_mapping, &mut report);
+    if options.error_on_empty && source_files.is_empty() {
+        Err(NoSourceFiles)?
+    }
+    if config.match_declared_ns {
+        read_declared_namespaces(&mut source_files, options.encoding);
+    }
+    if config.rules.iter().any(Rule::uses_tags) {
+        read_tags(&mut source_files, options.encoding);
+    }
+
+    if options.stats {
+        let stats =
+            stats::Stats::collect(&source_files, options.encoding, &mut report);
+        match options.format {
+            Format::Json => println!("{:#}", stats.to_json()),
+            Format::Ndjson => println!("{}", stats.to_json()),
+            Format::Graphical
+            | Format::Stable
+            | Format::Junit
+            | Format::Tap
+            | Format::Codeclimate => {
+                print!("{}", stats)
+            }
+        }
+        process::exit(0);
+    }
+
+    let compiled_rules: Vec<_> = config
+        .rules
+        .into_iter()
+        .map(|rule| rule.compile(&source_files))
+        .collect();
+
+    report.rules(compiled_rules.iter().map(|rule| rule.selector.to_string()));
+    let tags: Vec<String> = options
+        .tag
+        .iter()
+        .map(|tag| tag.trim_start_matches(':').to_owned())
+        .collect();
+    warn_unknown_tags(&compiled_rules, &tags, &mut report);
+
+    if options.profile_regex {
+        eprint!(
+            "{}",
+            profile::RegexProfile::collect(
+                &compiled_rules,
+                &source_files,
+                options.encoding,
+            )
+        );
+    }
+
+    if let Some(pattern) = &options.debug_rule {
+        let mut found = false;
+        for rule in compiled_rules.iter() {
+            if rule.selector.pattern() == pattern {
+                print!("{}", rule.regexes());
+                found = true;
+            }
+        }
+        if !found {
+            Err(UnknownDebugRule {
+                pattern: pattern.clone(),
+            })?
+        }
+        process::exit(0);
+    }
+
+    if let Some(AuditFormat::Json) = options.audit {
+        let audit = audit_rules(&compiled_rules, &source_files);
+        println!("{:#}", audit);
+        process::exit(0);
+    }
+
+    if let Some(Command::AuditDrift) = options.command {
+        let drift = drift::Drift::collect(
+            &compiled_rules,
+            &source_files,
+            options.encoding,
+            &mut report,
+        );
+        match options.format {
+            Format::Json => println!("{:#}", drift.to_json()),
+            Format::Ndjson => println!("{}", drift.to_json()),
+            Format::Graphical
+            | Format::Stable
+            | Format::Junit
+            | Format::Tap
+            | Format::Codeclimate => {
+                print!("{}", drift)
+            }
+        }
+        process::exit(0);
+    }
+
+    if options.suggest {
+        let suggestions = suggest::Suggestions::collect(
+            &compiled_rules,
+            &source_files,
+            options.encoding,
+            &mut report,
+        );
+        print!("{}", suggestions);
+        process::exit(0);
+    }
+
+    // The code on stdin is checked in place of the source tree, which is still
+    // needed to compile the rules and to find qualified symbols
+    let stdin = if options.stdin {
+        let code = io::read_to_string(io::stdin())
+            .map_err(|source| StdinUnreadable { source })?;
+        let file =
+            stdin_file(&code, options.assume_namespace.as_deref(), &mut report);
+        report.candidate_files(file.as_slice());
+        file.map(|file| (file, code))
+    } else {
+        None
+    };
+
+    // Printed to stderr, so as not to become part of the report
+    if options.show_banner() {
+        eprintln!("{}", report.banner());
+    }
+
+    // Accepted before checking, so that they are left out of streamed output
+    let baseline = match &options.baseline {
+        Some(path) => {
+            Some(baseline::Baseline::read(path, options.baseline_update)?)
+        }
+        None => None,
+    };
+    if let (Some(baseline), false) = (&baseline, options.baseline_update) {
+        baseline.apply(&mut report);
+    }
+
+    let checks = Checks {
+        encoding: options.encoding,
+        require_coverage: options.require_coverage,
+        deep: options.deep,
+        multi_ns: options.multi_ns,
+        skip_marker: config.skip_marker.as_deref(),
+        tags: &tags,
+        jobs: options.jobs.map_or_else(
+            || thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            NonZeroUsize::get,
+        ),
+    };
+    if options.stdin {
+        if let Some((file, code)) = &stdin {
+            check_stdin(
+                &compiled_rules,
+                &source_files,
+                file,
+                code,
+                &checks,
+                &mut report,
+            );
+        }
+    } else {
+        apply_rules(&compiled_rules, &source_files, &checks, &mut report);
+    }
+    if options.transitive {
+        transitive::check(
+            &compiled_rules,
+            &source_files,
+            options.encoding,
+            &tags,
+            &mut report,
+        );
+    }
+
+    if let (Some(path), Some(baseline)) = (&options.baseline, baseline) {
+        if options.baseline_update {
+            let added = baseline.update(path, &report)?;
+            println!(
+                "Added {} violation{} to {}",
+                added,
+                added.pluralise(),
+                path.display()
+            );
+            process::exit(0);
+        }
+    }
+
+    if options.count_only {
+        if report.options().verbosity != Verbosity::Quiet {
+            for warning in report.warnings() {
+                eprintln!("warning: {}", warning);
+            }
+        }
+        println!("{}", report.violation_count());
+        process::exit(options.exit_status(report.exit_status()));
+    }
+
+    if let Some(previous) = &options.compare {
+        let comparison = compare::Comparison::new(previous, &report)?;
+        match options.format {
+            Format::Json => println!("{:#}", comparison.to_json()),
+            Format::Ndjson => {
+                for line in comparison.to_ndjson() {
+                    println!("{}", line);
+                }
+            }
+            Format::Graphical
+            | Format::Stable
+            | Format::Junit
+            | Format::Tap
+            | Format::Codeclimate => {
+                print!("{}", comparison)
+            }
+        }
+        process::exit(options.exit_status(comparison.exit_status()));
+    }
+
+    if let Some(Command::Tui) = options.command {
+        browse(&report)?;
+        process::exit(options.exit_status(report.exit_status()));
+    }
+
+    match &options.output_dir {
+        Some(dir) => {
+            let written = report.write_junit_files(dir).map_err(|source| {
+                OutputDirError::WriteFailure {
+                    path: dir.clone(),
+                    source,
+                }
+            })?;
+            eprint!("{}", report.summary());
+            eprintln!(
+                "Wrote {} file{} to {}",
+                written,
+                written.pluralise(),
+                dir.display()
+            );
+        }
+        None => print_report(
+            &mut report,
+            options.split_severity,
+            &mut io::stdout(),
+            &mut io::stderr(),
+        )
+        .expect("failed printing the report"),
+    }
+    if options.format == Format::Ndjson {
+        eprint!("{}", report.summary());
+    }
+    process::exit(options.exit_status(report.exit_status()));
+}
+
+/// Prints `report` to `stdout` or, if `split_severity` is given, the
+/// violations of rules at `:severity :warn` to that stream and the rest of the
+/// report to the other.
+fn print_report<'w>(
+    report: &mut Report,
+    split_severity: Option<Stream>,
+    stdout: &'w mut dyn io::Write,
+    stderr: &'w mut dyn io::Write,
+) -> io::Result<()> {
+    let warned = match split_severity {
+        Some(warned) => warned,
+        None => return write!(stdout, "{}", report),
+    };
+    let warned_report = report.split_warned();
+    let (rest, warned) = match warned {
+        Stream::Stdout => (stderr, stdout),
+        Stream::Stderr => (stdout, stderr),
+    };
+    write!(rest, "{}", report)?;
+    write!(warned, "{}", warned_report)
+}
+
+#[cfg(feature = "tui")]
+fn browse(report: &Report) -> DiagnosticResult<()> {
+    Ok(report::tui::browse(report)?)
+}
+
+#[cfg(not(feature = "tui"))]
+fn browse(_: &Report) -> DiagnosticResult<()> {
+    Err(TuiUnavailable)?
+}
+
+#[cfg(not(feature = "tui"))]
+#[derive(Debug, Error, Diagnostic)]
+#[diagnostic(
+    code(tui_unavailable),
+    help("build ns-rules with `cargo install ns-rules --features tui`")
+)]
+#[error("this build of ns-rules can't browse violations")]
+struct TuiUnavailable;
+
+#[derive(Debug, Error, Diagnostic)]
+enum OutputDirError {
+    #[diagnostic(
+        code(output_dir_error),
+        help("add --format junit, or leave out --output-dir")
+    )]
+    #[error("--output-dir can only be used with --format junit")]
+    NotJunit,
+    #[diagnostic(code(output_dir_error))]
+    #[error("the JUnit files could not be written to {path:?}")]
+    WriteFailure { path: PathBuf, source: io::Error },
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[diagnostic(code(stdin_unreadable))]
+#[error("the code on stdin could not be read")]
+struct StdinUnreadable {
+    source: io::Error,
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[diagnostic(
+    code(no_source_files),
+    help("check that ':src-dirs' names the directories containing the code")
+)]
+#[error("no Clojure source files were found")]
+struct NoSourceFiles;
+
+#[derive(Debug, Error, Diagnostic)]
+#[diagnostic(
+    code(unknown_rule),
+    help("give the pattern of the rule as written in the configuration")
+)]
+#[error("no rule has the pattern '{pattern}'")]
+struct UnknownDebugRule {
+    pattern: String,
+}
+
+/// Sends logs to stderr, filtered by `filter` or, failing that, by RUST_LOG.
+/// Without either, nothing is logged.
+fn init_logging(filter: Option<EnvFilter>, color: ColorChoice) {
+    let filter = filter
+        .or_else(|| EnvFilter::try_from_default_env().ok())
+        .unwrap_or_else(|| EnvFilter::new("off"));
+
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(match color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => io::stderr().is_terminal(),
+        })
+        .init();
+}
+
+/// Removes the rules with the given patterns, warning about any pattern that
+/// has no rule.
+fn disable_rules(
+    rules: &mut Vec<Rule>,
+    patterns: &[String],
+    report: &mut Report,
+) {
+    for pattern in patterns {
+        let before = rules.len();
+        rules.retain(|rule| rule.selector.pattern() != pattern);
+        debug!(pattern, disabled = before - rules.len(), "disabling rule");
+        if rules.len() == before {
+            report.warn(Warning::new(
+                WarningCode::UnknownRule,
+                format!(
+                    "no rule has the pattern '{}', so it cannot be disabled",
+                    pattern
+                ),
+            ));
+        }
+    }
+}
+
+/// Warns about each of `tags` that no rule has, as no rules are run for it.
+fn warn_unknown_tags(
+    rules: &[CompiledRule],
+    tags: &[String],
+    report: &mut Report,
+) {
+    for tag in tags {
+        if !rules.iter().any(|rule| rule.tags.contains(tag)) {
+            report.warn(Warning::new(
+                WarningCode::UnknownRule,
+                format!(
+                    "no rule has the tag ':{}', so none are run for it",
+                    tag
+                ),
+            ));
+        }
+    }
+}
+
+/// Warns about each namespace pattern allowed by a rule that another of its
+/// patterns already allows. Of two identical patterns, the second is the one
+/// that is redundant.
+fn warn_redundant_allows(rules: &[Rule], report: &mut Report) {
+    for rule in rules {
+        for (i, redundant) in rule.allow.iter().enumerate() {
+            let subsuming = rule.allow.iter().enumerate().find(|(j, other)| {
+                *j != i
+                    && other.subsumes(redundant)
+                    && (other.pattern != redundant.pattern || *j < i)
+            });
+            if let Some((_, other)) = subsuming {
+                report.warn(Warning::new(
+                    WarningCode::RedundantAllow,
+                    format!(
+                        "the rule for '{}' allows '{}', which '{}' already \
+                         allows",
+                        rule.selector, redundant, other
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Expands the source directories written as globs into the directories they
+/// match, in order, warning about any glob that matches none. The directories
+/// that aren't globs are kept as they are.
+fn expand_source_dirs(
+    source_dirs: &[SourceDir],
+    report: &mut Report,
+) -> Vec<SourceDir> {
+    let mut expanded = Vec::new();
+    for source_dir in source_dirs {
+        if !source_dir.is_glob() {
+            expanded.push(source_dir.clone());
+            continue;
+        }
+
+        let glob: PathMatcher = match source_dir.path.parse() {
+            Ok(glob) => glob,
+            Err(error) => {
+                report.warn(Warning::new(
+                    WarningCode::EmptySrcDir,
+                    format!(
+                        "source directory {} is not a valid glob: {}",
+                        source_dir.path, error
+                    ),
+                ));
+                continue;
+            }
+        };
+        // Only the directories below the part of the glob without wildcards
+        // can match.
+        let base: PathBuf = Path::new(&source_dir.path)
+            .components()
+            .take_while(|component| {
+                !SourceDir::from(&*component.as_os_str().to_string_lossy())
+                    .is_glob()
+            })
+            .collect();
+        let base = if base.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            base
+        };
+
+        let matches: Vec<SourceDir> = WalkBuilder::new(&base)
+            .standard_filters(false)
+            .sort_by_file_name(Ord::cmp)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().is_dir())
+            .filter_map(|entry| {
+                let path =
+                    entry.path().strip_prefix("./").unwrap_or(entry.path());
+                let path = path.to_str()?;
+                glob.matches(path).then(|| SourceDir {
+                    path: path.to_owned(),
+                    exclude: source_dir.exclude.clone(),
+                })
+            })
+            .collect();
+        debug!(glob = %source_dir.path, matches = matches.len(), "expanded");
+
+        if matches.is_empty() {
+            report.warn(Warning::new(
+                WarningCode::EmptySrcDir,
+                format!(
+                    "source directory {} matches no directories",
+                    source_dir.path
+                ),
+            ));
+        }
+        expanded.extend(matches);
+    }
+
+    expanded
+}
+
+/// Finds the Clojure source files in `source_dirs`, expanding any globs.
+/// Directories are walked in parallel, but the files, and any warnings, are
+/// always in the same order.
+fn find_source_files(
+    source_dirs: &[SourceDir],
+    mapping: NamespaceMapping,
+    report: &mut Report,
+) -> Vec<ClojureSourceFile> {
+    let mut found = Vec::new();
+    for source_dir in expand_source_dirs(source_dirs, report).iter() {
+        debug!(source_dir = %source_dir.as_ref().display(), "walking");
+        let entries = Mutex::new(Vec::new());
+        WalkBuilder::new(source_dir)
+            .standard_filters(false)
+            .build_parallel()
+            .run(|| {
+                Box::new(|entry| {
+                    let entry = match entry {
+                        Ok(entry) if source_dir.excludes(entry.path()) => {
+                            return WalkState::Skip;
+                        }
+                        Ok(entry) if entry.depth() == 0 => None,
+                        Ok(entry) if entry.path().is_dir() => None,
+                        Ok(entry) => {
+                            Some(source_file(source_dir, entry.path(), mapping))
+                        }
+                        Err(error) => Some(Err(Warning::new(
+                            WarningCode::SkippedFile,
+                            error.to_string(),
+                        ))),
+                    };
+                    entries.lock().expect("not poisoned").extend(entry);
+                    WalkState::Continue
+                })
+            });
+
+        let mut entries = entries.into_inner().expect("not poisoned");
+        entries.sort_by(|a, b| sort_key(a).cmp(&sort_key(b)));
+        // Another directory may have plenty, so that the total alone doesn't
+        // show a misconfigured directory. A missing directory is already
+        // reported by the walk.
+        if source_dir.as_ref().is_dir() && !entries.iter().any(Result::is_ok) {
+            report.warn(Warning::new(
+                WarningCode::EmptySrcDir,
+                format!(
+                    "source directory {} contains no Clojure source files",
+                    source_dir.as_ref().display()
+                ),
+            ));
+        }
+        found.extend(entries);
+    }
+
+    let mut source_files = Vec::new();
+    for entry in found {
+        match entry {
+            Ok(source_file) => {
+                trace!(
+                    path = source_file.path(),
+                    namespace = source_file.namespace(),
+                    "found source file"
+                );
+                source_files.push(source_file);
+            }
+            Err(warning) => {
+                debug!(reason = %warning.message, "skipping file");
+                report.file_skipped(warning);
+            }
+        }
+    }
+    debug!(files = source_files.len(), "found source files");
+    report.candidate_files(&source_files);
+
+    source_files
+}
+
+/// Reads the tags of the namespace declared by each source file. Files that
+/// can't be read are left without tags, to be reported when they are checked.
+fn read_tags(source_files: &mut [ClojureSourceFile], encoding: Encoding) {
+    let mut ignored = Report::new(Default::default());
+    for file in source_files {
+        if let Some(code) = file.read(encoding, &mut ignored) {
+            file.tags = ns::read_tags(&code).unwrap_or_default();
+        }
+    }
+}
+
+/// Names each source file after the namespace that its `ns` form declares, for
+/// layouts in which paths don't follow namespaces. Files whose `ns` form can't
+/// be read keep the namespace of their path.
+fn read_declared_namespaces(
+    source_files: &mut [ClojureSourceFile],
+    encoding: Encoding,
+) {
+    let mut ignored = Report::new(Default::default());
+    for file in source_files {
+        let declared = file
+            .read(encoding, &mut ignored)
+            .and_then(|code| ns::read_namespace(&code).ok().flatten());
+        if let Some(namespace) = declared {
+            if namespace != file.namespace() {
+                debug!(
+                    path = file.path(),
+                    from_path = file.namespace(),
+                    declared = %namespace,
+                    "using the declared namespace"
+                );
+            }
+            file.entry = format!("{}{}", namespace, file.path());
+            file.path_start = namespace.len();
+        }
+    }
+}
+
+/// Orders the files found in a source directory by path, with the errors that
+/// have no path after them.
+fn sort_key(entry: &Result<ClojureSourceFile, Warning>) -> (bool, &str) {
+    match entry {
+        Ok(source_file) => (false, source_file.path()),
+        Err(warning) => (true, &warning.message),
+    }
+}
+
+/// The Clojure source file at `path` in `source_dir`, or why the file is
+/// skipped.
+fn source_file(
+    source_dir: impl AsRef<Path>,
+    path: &Path,
+    mapping: NamespaceMapping,
+) -> Result<ClojureSourceFile, Warning> {
+    let ext = path.extension().and_then(OsStr::to_str);
+    if let Some("clj" | "cljs" | "cljc") = ext {
+        let ns = mapping.namespace_of(
+            path.strip_prefix(source_dir)
+                .expect("source root is a prefix of file path"),
+        );
+
+        if let (Some(mut ns), Some(path)) = (ns, path.to_str()) {
+            let path_start = ns.len();
+            ns.push_str(path);
+            Ok(ClojureSourceFile {
+                entry: ns,
+                path_start,
+                tags: vec![],
+            })
+        } else {
+            Err(Warning::new(
+                WarningCode::SkippedFile,
+                format!(
+                    "path {} contains invalid utf8 characters, skipping",
+                    path.display()
+                ),
+            ))
+        }
+    } else
+    /* not a Clojure source file */
+    {
+        Err(Warning::new(
+            WarningCode::SkippedFile,
+            format!(
+                "{} is not a Clojure source file, skipping",
+                path.display()
+            ),
+        )
+        .at(&path.to_string_lossy()))
+    }
+}
+
+#[derive(Debug)]
+struct ClojureSourceFile {
+    entry: String,
+    path_start: usize,
+    /// The tags in the metadata of the namespace, which are only read when a
+    /// rule uses them.
+    tags: Vec<String>,
+}
+
+/// The byte order mark that some editors put at the start of UTF-8 files.
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+impl ClojureSourceFile {
+    fn path(&self) -> &str {
+        &self.entry[self.path_start..]
+    }
+
+    fn namespace(&self) -> &str {
+        &self.entry[..self.path_start]
+    }
+
+    /// The same file, known by `namespace`, for the code that follows a
+    /// declaration of it part way through the file.
+    fn declaring(&self, namespace: &str) -> Self {
+        Self {
+            entry: format!("{}{}", namespace, self.path()),
+            path_start: namespace.len(),
+            tags: self.tags.clone(),
+        }
+    }
+
+    /// Reads the source code of the file, or skips the file if it can't be
+    /// read. A leading byte order mark is dropped, so that it can't get in
+    /// the way of the `ns` form, and offsets into the code start after it.
+    fn read(&self, encoding: Encoding, report: &mut Report) -> Option<String> {
+        let bytes = match fs::read(self.path()) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                report.file_skipped(Warning::skipped(
+                    self.path(),
+                    format!("failed to read file {}: {}", self.path(), error),
+                ));
+                return None;
+            }
+        };
+
+        let bytes = match bytes.strip_prefix(UTF8_BOM) {
+            Some(rest) => rest.to_vec(),
+            None => bytes,
+        };
+
+        match (String::from_utf8(bytes), encoding) {
+            (Ok(code), _) => Some(code),
+            (Err(error), Encoding::Utf8) => {
+                report.file_skipped(Warning::skipped(
+                    self.path(),
+                    format!(
+                        "failed to read file {}: {}",
+                        self.path(),
+                        error.utf8_error()
+                    ),
+                ));
+                None
+            }
+            (Err(error), Encoding::Lossy) => {
+                let message = format!(
+                    "{} is not valid UTF-8, invalid characters were replaced",
+                    self.path()
+                );
+                report.warn(
+                    Warning::new(WarningCode::InvalidUtf8, message)
+                        .at(self.path()),
+                );
+                Some(String::from_utf8_lossy(error.as_bytes()).into_owned())
+            }
+        }
+    }
+}
+
+/// How source files are checked against their rules.
+#[derive(Debug)]
+struct Checks<'a> {
+    encoding: Encoding,
+    require_coverage: bool,
+    deep: bool,
+    /// Whether the namespaces declared after the first in a file are checked.
+    multi_ns: bool,
+    skip_marker: Option<&'a str>,
+    /// The tags of the rules to run, or every rule when empty.
+    tags: &'a [String],
+    /// The number of files checked at once.
+    jobs: usize,
+}
+
+impl Default for Checks<'_> {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::Utf8,
+            require_coverage: false,
+            deep: false,
+            multi_ns: false,
+            skip_marker: None,
+            tags: &[],
+            jobs: 1,
+        }
+    }
+}
+
+/// Checks each source file against the first of the `rules` that governs it,
+/// unless the rule isn't among those selected by tag, in which case the file
+/// is passed over rather than falling to a later rule. Files are checked in
+/// parallel, but the results of each are collected on
+/// their own and added to `report` in the order of the files, so that the
+/// report, and any streamed output, is the same however many jobs there are.
+fn apply_rules(
+    rules: &[CompiledRule],
+    source_files: &[ClojureSourceFile],
+    checks: &Checks,
+    report: &mut Report,
+) {
+    // Qualified symbols only count when they refer to a namespace in the source
+    // tree, as most refer to Java classes, such as Math/abs, or to aliases.
+    let known_namespaces: Option<BTreeSet<&str>> = checks.deep.then(|| {
+        source_files
+            .iter()
+            .map(ClojureSourceFile::namespace)
+            .collect()
+    });
+    let options = report.options().clone();
+
+    in_order(
+        source_files,
+        checks.jobs,
+        |file| {
+            check_file(rules, file, checks, known_namespaces.as_ref(), &options)
+        },
+        |file, (rule, segments, buffer)| {
+            let segments =
+                segments.iter().map(|(rule, segment)| (*rule, segment));
+            for (rule, file) in iter::once((rule, file)).chain(segments) {
+                match rule {
+                    Some(i) if rules[i].has_any_tag(checks.tags) => {
+                        report.rule_matched(i, file)
+                    }
+                    Some(_) => {}
+                    None if checks.require_coverage => report.ungoverned(file),
+                    None => {}
+                }
+            }
+            report.append(buffer);
+        },
+    );
+}
+
+/// The namespaces declared by a file after its first, as checked by
+/// [`check_file`], each with the index of the rule that governs it.
+type Segments = Vec<(Option<usize>, ClojureSourceFile)>;
+
+/// Checks `file` against the first of the `rules` that governs it, and each
+/// namespace that it declares after its first against the rule that governs
+/// that, if the rule is among those selected by tag. Returns the index of the
+/// rule that governs the file, the later namespaces, and a report of the
+/// results, which are kept apart so that files can be checked in parallel.
+/// Generated files, and those that can't be read, are skipped with a warning.
+fn check_file(
+    rules: &[CompiledRule],
+    file: &ClojureSourceFile,
+    checks: &Checks,
+    known_namespaces: Option<&BTreeSet<&str>>,
+    options: &DisplayOptions,
+) -> (Option<usize>, Segments, Report) {
+    let find_rule = |file: &ClojureSourceFile| {
+        rules
+            .iter()
+            .enumerate()
+            .find(|(_, rule)| rule.matches(file))
+    };
+
+    let mut buffer = Report::buffer(options);
+    let governing = find_rule(file);
+    let rule = governing.filter(|(_, rule)| rule.has_any_tag(checks.tags));
+    match governing {
+        Some((_, rule)) => debug!(
+            path = file.path(),
+            namespace = file.namespace(),
+            rule = %rule.selector,
+            "checking"
+        ),
+        None => debug!(
+            path = file.path(),
+            namespace = file.namespace(),
+            "no rule matches"
+        ),
+    }
+    // A file that no rule governs need only be read for the namespaces it
+    // declares after its first
+    if rule.is_none() && !checks.multi_ns {
+        return (governing.map(|(i, _)| i), vec![], buffer);
+    }
+
+    let code = file.read(checks.encoding, &mut buffer).filter(|code| {
+        let generated = is_generated(code, checks.skip_marker);
+        if generated {
+            buffer.file_skipped(Warning::skipped(
+                file.path(),
+                format!("{} is generated, skipping", file.path()),
+            ));
+        }
+        !generated
+    });
+    let code = match code {
+        Some(code) => code,
+        None => return (governing.map(|(i, _)| i), vec![], buffer),
+    };
+    if let Some((i, rule)) = rule {
+        rule.apply(i, file, &code, known_namespaces, &mut buffer);
+    }
+
+    // Each later namespace is checked against the rule that governs it
+    let segments = later_segments(file, &code, checks.multi_ns, &mut buffer)
+        .into_iter()
+        .map(|(segment, references)| {
+            let governing = find_rule(&segment);
+            let rule =
+                governing.filter(|(_, rule)| rule.has_any_tag(checks.tags));
+            if let Some((i, rule)) = rule {
+                buffer.references_checked(references.len());
+                let violations =
+                    rule.violations(i, &segment, &code, references, options);
+                for violation in violations {
+                    buffer.violation(violation);
+                }
+            }
+            (governing.map(|(i, _)| i), segment)
+        })
+        .collect();
+    (governing.map(|(i, _)| i), segments, buffer)
+}
+
+/// The path shown for code read from stdin.
+const STDIN: &str = "<stdin>";
+
+/// The code read from stdin as though it were a file of the namespace that it
+/// declares or, failing that, of the `assumed` namespace. Code that has
+/// neither is skipped, as no rule can be found for it.
+fn stdin_file(
+    code: &str,
+    assumed: Option<&str>,
+    report: &mut Report,
+) -> Option<ClojureSourceFile> {
+    let namespace = match (ns::read_namespace(code), assumed) {
+        (Err(error), _) => {
+            report.file_skipped(Warning::skipped(
+                STDIN,
+                format!("failed to read the ns form of {}: {}", STDIN, error),
+            ));
+            return None;
+        }
+        (Ok(Some(declared)), Some(assumed)) if declared != assumed => {
+            report.warn(
+                Warning::new(
+                    WarningCode::AssumedNamespace,
+                    format!(
+                        "{} declares {}, so --assume-namespace {} is ignored",
+                        STDIN, declared, assumed
+                    ),
+                )
+                .at(STDIN),
+            );
+            declared
+        }
+        (Ok(Some(declared)), _) => declared,
+        (Ok(None), Some(assumed)) => assumed.to_owned(),
+        (Ok(None), None) => {
+            report.file_skipped(Warning::skipped(
+                STDIN,
+                format!(
+                    "{} does not declare a namespace, pass --assume-namespace \
+                     to check it",
+                    STDIN
+                ),
+            ));
+            return None;
+        }
+    };
+
+    Some(ClojureSourceFile {
+        entry: format!("{}{}", namespace, STDIN),
+        path_start: namespace.len(),
+        tags: ns::read_tags(code).unwrap_or_default(),
+    })
+}
+
+/// Checks `code`, read from stdin as `file`, against the first of the `rules`
+/// that governs it. Code without an `ns` form has no other references, so it
+/// references the namespaces in the source tree that its qualified symbols
+/// refer to, as under --deep.
+fn check_stdin(
+    rules: &[CompiledRule],
+    source_files: &[ClojureSourceFile],
+    file: &ClojureSourceFile,
+    code: &str,
+    checks: &Checks,
+    report: &mut Report,
+) {
+    let (i, rule) = match rules
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| rule.matches(file))
+    {
+        Some((i, rule)) if rule.has_any_tag(checks.tags) => (i, rule),
+        Some(_) => return,
+        None => {
+            if checks.require_coverage {
+                report.ungoverned(file);
+            }
+            return;
+        }
+    };
+    report.rule_matched(i, file);
+
+    let known_namespaces: BTreeSet<&str> = source_files
+        .iter()
+        .map(ClojureSourceFile::namespace)
+        .collect();
+    match ns::read_references(code) {
+        Ok(None) => {
+            let mut references = vec![];
+            rule.read_qualified_symbols(
+                file,
+                code,
+                &known_namespaces,
+                &mut references,
+                report,
+            );
+            report.references_checked(references.len());
+            for violation in
+                rule.violations(i, file, code, references, report.options())
+            {
+                report.violation(violation);
+            }
+        }
+        _ => rule.apply(
+            i,
+            file,
+            code,
+            checks.deep.then_some(&known_namespaces),
+            report,
+        ),
+    }
+}
+
+/// The namespaces that `code` declares after its first, each as though it
+/// were a file of its own, with the references of its `ns` form. Unless they
+/// are to be checked, they are only warned about.
+fn later_segments(
+    file: &ClojureSourceFile,
+    code: &str,
+    multi_ns: bool,
+    report: &mut Report,
+) -> Vec<(ClojureSourceFile, Vec<ns::Reference>)> {
+    // Problems reading the first ns form are reported when checking it, and
+    // the rest of the code is otherwise never read
+    let mut segments = ns::read_segments(code).unwrap_or_default().into_iter();
+    let first = match segments.next() {
+        Some(first) => first.namespace,
+        None => return vec![],
+    };
+    let later: Vec<_> = segments
+        .filter(|segment| segment.namespace != first)
+        .collect();
+
+    if later.is_empty() {
+        vec![]
+    } else if multi_ns {
+        later
+            .into_iter()
+            .map(|segment| {
+                (file.declaring(&segment.namespace), segment.references)
+            })
+            .collect()
+    } else {
+        report.warn(
+            Warning::new(
+                WarningCode::MultipleNs,
+                format!(
+                    "{} declares more than one namespace, only the first is \
+                     checked",
+                    file.path()
+                ),
+            )
+            .at(file.path()),
+        );
+        vec![]
+    }
+}
+
+/// Maps each of `items` with `map`, on up to `jobs` threads, passing each
+/// result to `collect` in the order of `items`, as soon as the results of the
+/// items before it have been collected.
+fn in_order<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    map: impl Fn(&T) -> R + Sync,
+    mut collect: impl FnMut(&T, R),
+) {
+    if jobs <= 1 {
+        for item in items {
+            collect(item, map(item));
+        }
+        return;
+    }
+
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            let (next, map, sender) = (&next, &map, sender.clone());
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let item = match items.get(index) {
+                    Some(item) => item,
+                    None => break,
+                };
+                // The receiver outlives every thread
+                let _ = sender.send((index, map(item)));
+            });
+        }
+        drop(sender);
+
+        // Results that arrive before those of earlier items wait their turn
+        let mut pending = BTreeMap::new();
+        let mut collected = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&collected) {
+                collect(&items[collected], result);
+                collected += 1;
+            }
+        }
+    });
+}
+
+/// Checks the `code` of `namespace`, which need not be saved to a file, against
+/// the first of the `rules` that governs it. Rules that select files by path
+/// never govern it, as it has none, and code without an `ns` form has no
+/// references to check.
+pub fn check_source(
+    namespace: &str,
+    code: &str,
+    rules: &[CompiledRule],
+) -> Vec<Violation> {
+    check_source_at(namespace, "", code, rules)
+}
+
+/// As [`check_source`], for code that belongs at `path`, relative to the
+/// working directory, so that rules that select files by path apply to it.
+fn check_source_at(
+    namespace: &str,
+    path: &str,
+    code: &str,
+    rules: &[CompiledRule],
+) -> Vec<Violation> {
+    let file = ClojureSourceFile {
+        entry: format!("{}{}", namespace, path),
+        path_start: namespace.len(),
+        tags: ns::read_tags(code).unwrap_or_default(),
+    };
+    let rule = rules
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| rule.matches(&file));
+
+    match (rule, ns::read_references(code)) {
+        (Some((i, rule)), Ok(Some(references))) => {
+            rule.violations(i, &file, code, references, &Default::default())
+        }
+        _ => vec![],
+    }
+}
+
+/// Reads the configuration file at `config`, nested under `config_key` and with
+/// `profile` merged over it if given, and compiles its rules against the
+/// namespaces in its source directories, ready for [`check_source`].
+pub fn compile_rules(
+    config: &Path,
+    config_key: Option<&str>,
+    profile: Option<&str>,
+) -> DiagnosticResult<Vec<CompiledRule>> {
+    let (rules, ..) =
+        compile_source_tree(config, config_key, profile, Encoding::Utf8)?;
+    Ok(rules)
+}
+
+/// Checks every source file found by the configuration file at `config`, read
+/// as by [`compile_rules`], against the first of its rules that governs it,
+/// calling `on_violation` with each violation as it is found. Returns the
+/// number of violations. Files are read with `encoding`, and those marked as
+/// generated by the configuration's `:skip-marker` are passed over, as they
+/// are by the command line tool.
+///
+/// The files are checked on up to `jobs` threads, but `on_violation` is only
+/// ever called on the calling thread, with the violations of each file in
+/// turn, in the order in which the files were found. It therefore need be
+/// neither `Send` nor `Sync`, and sees the same order however many jobs there
+/// are. The results of files checked ahead of their turn wait in memory until
+/// `on_violation` has been called for the files before them, so a slow
+/// callback holds the results back rather than slowing the checks. Files that
+/// can't be read, or declare no namespace, are passed over.
+pub fn check_source_tree(
+    config: &Path,
+    config_key: Option<&str>,
+    profile: Option<&str>,
+    encoding: Encoding,
+    jobs: NonZeroUsize,
+    on_violation: impl FnMut(&Violation),
+) -> DiagnosticResult<usize> {
+    let (rules, source_files, skip_marker) =
+        compile_source_tree(config, config_key, profile, encoding)?;
+    let checks = Checks {
+        encoding,
+        skip_marker: skip_marker.as_deref(),
+        jobs: jobs.get(),
+        ..Checks::default()
+    };
+    Ok(check_files(&rules, &source_files, &checks, on_violation))
+}
+
+/// Checks each of the `source_files` as [`apply_rules`] does, calling
+/// `on_violation` in the order of the files rather than adding to a report.
+fn check_files(
+    rules: &[CompiledRule],
+    source_files: &[ClojureSourceFile],
+    checks: &Checks,
+    mut on_violation: impl FnMut(&Violation),
+) -> usize {
+    let options = DisplayOptions::default();
+    let check = |file: &ClojureSourceFile| {
+        let (.., buffer) = check_file(rules, file, checks, None, &options);
+        buffer.into_violations()
+    };
+
+    let mut found = 0;
+    in_order(source_files, checks.jobs, check, |_, violations| {
+        found += violations.len();
+        violations.iter().for_each(&mut on_violation);
+    });
+    found
+}
+
+/// The compiled rules of the configuration file at `config`, as for
+/// [`compile_rules`], along with the source files that they were compiled
+/// against, read with `encoding`, and the configuration's skip marker.
+fn compile_source_tree(
+    config: &Path,
+    config_key: Option<&str>,
+    profile: Option<&str>,
+    encoding: Encoding,
+) -> DiagnosticResult<(Vec<CompiledRule>, Vec<ClojureSourceFile>, Option<String>)>
+{
+    let mut ignored = Report::new(Default::default());
+    let config = config::read_file(config, config_key, profile, &mut ignored)?;
+    let mut source_files = find_source_files(
+        &config.source_dirs,
+        NamespaceMapping::Clojure,
+        &mut ignored,
+    );
+    if config.match_declared_ns {
+        read_declared_namespaces(&mut source_files, encoding);
+    }
+    if config.rules.iter().any(Rule::uses_tags) {
+        read_tags(&mut source_files, encoding);
+    }
+
+    let rules = config
+        .rules
+        .into_iter()
+        .map(|rule| rule.compile(&source_files))
+        .collect();
+    Ok((rules, source_files, config.skip_marker))
+}
+
+/// The number of lines at the start of a file searched for the skip marker.
+const MARKER_LINES: usize = 5;
+
+/// Whether `code` contains the `skip_marker` in its first lines, marking it as
+/// generated.
+fn is_generated(code: &str, skip_marker: Option<&str>) -> bool {
+    skip_marker.is_some_and(|marker| {
+        code.lines()
+            .take(MARKER_LINES)
+            .any(|line| line.contains(marker))
+    })
+}
+
+/// Describes the rule that governs each source file, and the policy that the
+/// rule enforces.
+fn audit_rules(
+    rules: &[CompiledRule],
+    source_files: &[ClojureSourceFile],
+) -> serde_json::Value {
+    let namespaces = source_files
+        .iter()
+        .map(|file| {
+            let rule = rules.iter().find(|rule| rule.matches(file));
+            json!({
+                "namespace": file.namespace(),
+                "path": file.path(),
+                "rule": rule.map(|rule| json!({
+                    "pattern": rule.selector.to_string(),
+                    "restrict-to": rule.restrict_to(),
+                    "deny": rule
+                        .deny
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>(),
+                    "deny-src-dirs": rule.deny_src_dirs,
+                    "allow-self": rule.allow_self,
+                    "allow-same-root": rule.allow_same_root,
+                    "allow-parents": rule.allow_parents,
+                    "no-external": rule.no_external,
+                    "tagged": rule.tagged.as_ref().map(ToString::to_string),
+                    "deny-tagged": rule.deny_tagged,
+                    "must-reference": rule
+                        .must_reference
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>(),
+                    "severity": rule.severity.to_string(),
+                    "tags": rule.tags,
+                    "when-path": rule
+                        .when_path
+                        .as_ref()
+                        .map(|path| path.pattern.as_str()),
+                    "forbidden": rule.forbidden,
+                })),
+            })
+        })
+        .collect();
+
+    serde_json::Value::Array(namespaces)
+}
+
+#[derive(Clone, Debug)]
+struct NamespaceMatcher {
+    pattern: String,
+    regex: Regex,
+}
+
+impl NamespaceMatcher {
+    fn matches(&self, namespace: &str) -> bool {
+        self.regex.is_match(namespace)
+    }
+
+    /// Whether every namespace matched by `other` is also matched by this
+    /// pattern. Only a namespace written out in full, optionally followed by
+    /// '.*', is known to be subsumed by another pattern, and otherwise only
+    /// by the same pattern.
+    fn subsumes(&self, other: &NamespaceMatcher) -> bool {
+        let literal = |s: &str| !s.contains(&['*', '['][..]);
+        if self.pattern == other.pattern {
+            true
+        } else if let Some(prefix) = other.pattern.strip_suffix(".*") {
+            // Only another '.*' matches namespaces of any length
+            literal(prefix)
+                && self.pattern.ends_with(".*")
+                && self.matches(prefix)
+        } else {
+            literal(&other.pattern) && self.matches(&other.pattern)
+        }
+    }
+}
+
+impl fmt::Display for NamespaceMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+impl FromStr for NamespaceMatcher {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("namespace patterns cannot be empty")?,
+            s if s.contains(' ') => {
+                Err("namespace patterns cannot contains spaces")?
+            }
+            s if s.starts_with('.') || s.ends_with('.') => {
+                Err("namespace patterns cannot start with or end with '.'")?
+            }
+            _ => {}
+        }
+
+        // Characters allowed in EDN symbols
+        // For a segment we exclude '.', but we include it for the whole ns.
+        const NS_REGEX: &str = r"[[[:alnum:]]\.\*\+!\-_\?\$%\&=<>]+";
+        const NS_SEGMENT_REGEX: &str = r"[[[:alnum:]]\*\+!\-_\?\$%\&=<>]+";
+
+        let pattern: String = if let Some((head, "*")) = s.rsplit_once('.') {
+            // Last element is a wildcard, so we end with recursive search
+            head.split('.')
+                .map(|segment| segment_regex(segment, NS_SEGMENT_REGEX))
+                .chain(iter::once(Ok(NS_REGEX.to_string())))
+                .intersperse(Ok("\\.".to_string()))
+                .collect::<Result<_, _>>()?
+        } else {
+            s.split('.')
+                .map(|segment| segment_regex(segment, NS_SEGMENT_REGEX))
+                .intersperse(Ok("\\.".to_string()))
+                .collect::<Result<_, _>>()?
+        };
+
+        // Patterns describe whole namespaces, so a match must span the entire
+        // namespace rather than just some part of it.
+        let pattern = format!("^{}$", pattern);
+
+        Ok(Self {
+            pattern: s.to_string(),
+            regex: Regex::new(&pattern).expect("valid regex"),
+        })
+    }
+}
+
+/// The regex for a segment of a namespace pattern, in which `*` matches
+/// `wildcard` and a character class, such as `[0-9]`, matches a single
+/// character, or one or more when followed by `+`. Everything else matches
+/// itself.
+///
+/// Character classes may only contain letters, digits, '_' and '-', which
+/// denotes a range when between two other characters.
+fn segment_regex(
+    segment: &str,
+    wildcard: &str,
+) -> Result<String, &'static str> {
+    let mut regex = String::new();
+    let mut chars = segment.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' => regex.push_str(wildcard),
+            '[' => {
+                let mut class = String::new();
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some(c @ ('-' | '_')) => class.push(c),
+                        Some(c) if c.is_alphanumeric() => class.push(c),
+                        Some('[') | None => Err(
+                            "namespace patterns cannot contain an unclosed '['",
+                        )?,
+                        Some(_) => {
+                            Err("character classes in namespace patterns \
+                             may only contain letters, digits, '_' and '-'")?
+                        }
+                    }
+                }
+                if class.is_empty() {
+                    Err("character classes in namespace patterns cannot be \
+                         empty")?
+                }
+                regex.push_str(&format!("[{}]", class));
+                if chars.next_if_eq(&'+').is_some() {
+                    regex.push('+');
+                }
+            }
+            ']' => Err("namespace patterns cannot contain an unmatched ']'")?,
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+
+    Ok(regex)
+}
+
+#[derive(Clone, Debug)]
+struct PathMatcher {
+    pattern: String,
+    glob: GlobMatcher,
+}
+
+impl PathMatcher {
+    fn matches(&self, path: &str) -> bool {
+        self.glob.is_match(path)
+    }
+}
+
+impl fmt::Display for PathMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.pattern)
+    }
+}
+
+impl FromStr for PathMatcher {
+    type Err = globset::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A '*' stays within a directory, so that only '**' crosses them.
+        let glob = GlobBuilder::new(s).literal_separator(true).build()?;
+
+        Ok(Self {
+            pattern: s.to_string(),
+            glob: glob.compile_matcher(),
+        })
+    }
+}
+
+/// Selects the source files that a rule applies to, either by namespace or by
+/// file path.
+#[derive(Debug)]
+enum Selector {
+    Namespace(NamespaceMatcher),
+    Path(PathMatcher),
+}
+
+impl Selector {
+    /// The pattern as written in the configuration.
+    fn pattern(&self) -> &str {
+        match self {
+            Selector::Namespace(matcher) => &matcher.pattern,
+            Selector::Path(matcher) => &matcher.pattern,
+        }
+    }
+
+    fn matches(&self, file: &ClojureSourceFile) -> bool {
+        match self {
+            Selector::Namespace(matcher) => matcher.matches(file.namespace()),
+            Selector::Path(matcher) => matcher.matches(file.path()),
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Namespace(matcher) => matcher.fmt(f),
+            Selector::Path(matcher) => matcher.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Rule {
+    selector: Selector,
+    /// Whether the rule has a `:restrict-to`, which forbids every namespace in
+    /// the source tree that it doesn't allow, even when it only has negations.
+    restricted: bool,
+    allow: Vec<NamespaceMatcher>,
+    except: Vec<NamespaceMatcher>,
+    /// Globs of the paths of the files whose namespaces are allowed, written as
+    /// strings containing a '/' in `:restrict-to`.
+    allow_paths: Vec<PathMatcher>,
+    /// Globs of the paths of the files whose namespaces are removed from those
+    /// allowed.
+    except_paths: Vec<PathMatcher>,
+    /// The patterns of the rules whose allow lists are inherited, written as
+    /// `(:transitive pattern)` in `:restrict-to`, until they are resolved with
+    /// the rest of the configuration.
+    allow_transitive: Vec<String>,
+    deny: Vec<NamespaceMatcher>,
+    /// Source directories, as written in `:src-dirs`, whose namespaces are
+    /// denied.
+    deny_src_dirs: Vec<String>,
+    /// Narrows the files selected by the rule to those whose path matches.
+    when_path: Option<PathMatcher>,
+    /// Whether namespaces may reference the namespaces selected by the rule,
+    /// including themselves, as well as those allowed by `allow`.
+    allow_self: bool,
+    /// Whether namespaces may reference any namespace with the same first
+    /// segment, as well as those allowed by `allow`.
+    allow_same_root: bool,
+    /// Whether namespaces may reference their ancestors, such as `app.db` from
+    /// `app.db.pool`, as well as those allowed by `allow`.
+    allow_parents: bool,
+    /// Whether namespaces with a version segment, such as `api.v2.users`, may
+    /// reference the same or a lower version, such as `api.v1.users`, as well
+    /// as those allowed by `allow`. Written as `:same-or-lower-version` in
+    /// `:restrict-to`.
+    allow_lower_versions: bool,
+    /// Whether references to namespaces outside the source tree, such as
+    /// libraries, are forbidden unless allowed by `allow`.
+    no_external: bool,
+    /// Narrows the namespaces selected by the rule to those with, or without,
+    /// a tag in their metadata.
+    tagged: Option<TagCondition>,
+    /// Tags whose namespaces are denied.
+    deny_tagged: Vec<String>,
+    /// Patterns of which every namespace selected by the rule must reference
+    /// at least one namespace each.
+    must_reference: Vec<NamespaceMatcher>,
+    /// Whether violations of the rule fail the run.
+    severity: Severity,
+    /// The categories of the rule, written as `:tags`, by which a subset of
+    /// the rules can be run with `--tag`.
+    tags: Vec<String>,
+    doc: Option<String>,
+}
+
+/// Whether a namespace has, or doesn't have, a tag in its metadata, written as
+/// `:tag` or `:!tag`.
+#[derive(Debug, Clone, PartialEq)]
+struct TagCondition {
+    tag: String,
+    present: bool,
+}
+
+impl TagCondition {
+    fn matches(&self, file: &ClojureSourceFile) -> bool {
+        file.tags.contains(&self.tag) == self.present
+    }
+}
+
+impl fmt::Display for TagCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let negation = if self.present { "" } else { "!" };
+        write!(f, ":{}{}", negation, self.tag)
+    }
+}
+
+/// How much the violations of a rule matter, written as `:severity`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// Violations fail the run, as they do unless the rule says otherwise.
+    Error,
+    /// Violations are reported, but don't fail the run.
+    Warn,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warn => "warn",
+        })
+    }
+}
+
+/// `tags` as keywords, separated by spaces, as they are written in EDN.
+fn keywords(tags: &[String]) -> String {
+    tags.iter()
+        .map(|tag| format!(":{}", tag))
+        .intersperse(" ".to_string())
+        .collect()
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let allowed = self.restrict_to().join(" ");
+        write!(f, "{} {{", self.selector)?;
+        if let Some(when_path) = &self.when_path {
+            write!(f, ":when-path {} ", when_path)?;
+        }
+        if let Some(tagged) = &self.tagged {
+            write!(f, ":tagged {} ", tagged)?;
+        }
+        write!(f, ":restrict-to [{}]", allowed)?;
+        if !self.deny.is_empty() {
+            let denied = self
+                .deny
+                .iter()
+                .map(ToString::to_string)
+                .intersperse(" ".to_string())
+                .collect::<String>();
+            write!(f, " :deny [{}]", denied)?;
+        }
+        if !self.deny_src_dirs.is_empty() {
+            write!(f, " :deny-src-dirs [{}]", quoted(&self.deny_src_dirs))?;
+        }
+        if !self.deny_tagged.is_empty() {
+            write!(f, " :deny-tagged [{}]", keywords(&self.deny_tagged))?;
+        }
+        if !self.must_reference.is_empty() {
+            let required = self
+                .must_reference
+                .iter()
+                .map(ToString::to_string)
+                .intersperse(" ".to_string())
+                .collect::<String>();
+            write!(f, " :must-reference [{}]", required)?;
+        }
+        if self.severity != Severity::Error {
+            write!(f, " :severity :{}", self.severity)?;
+        }
+        if !self.allow_self {
+            f.write_str(" :allow-self false")?;
+        }
+        if self.allow_same_root {
+            f.write_str(" :allow-same-root true")?;
+        }
+        if self.allow_parents {
+            f.write_str(" :allow-parents true")?;
+        }
+        if self.no_external {
+            f.write_str(" :no-external true")?;
+        }
+        if !self.tags.is_empty() {
+            write!(f, " :tags [{}]", keywords(&self.tags))?;
+        }
+        f.write_str("}")?;
+        if let Some(doc) = &self.doc {
+            write!(f, "\n    {}", doc)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Rule {
+    fn restrict_to(&self) -> Vec<String> {
+        restrict_to(
+            &self.allow,
+            &self.allow_paths,
+            self.allow_lower_versions,
+            &self.except,
+            &self.except_paths,
+        )
+    }
+
+    /// Whether the rule selects or denies namespaces by their tags, which
+    /// must then be read before the rule is compiled.
+    fn uses_tags(&self) -> bool {
+        self.tagged.is_some() || !self.deny_tagged.is_empty()
+    }
+
+    fn compile(self, source_files: &[ClojureSourceFile]) -> CompiledRule {
+        let not_allowed = |source_file: &&ClojureSourceFile| {
+            // A namespace may be referenced when it is matched by `allow` or
+            // `allow_paths`, by namespace or by the path of its file, and by
+            // neither `except` nor `except_paths`, the negations taking
+            // precedence. Without `:restrict-to` every namespace is allowed
+            // here, leaving only denied namespaces forbidden, unless namespaces
+            // are restricted to their own root, their parents or their
+            // versions, which `why_forbidden_from` lets through per reference
+            // as it needs both ends of it. The rule's own namespaces may always
+            // be referenced, whatever the negations, unless `:allow-self` is
+            // false.
+            let in_allow_list = ((!self.restricted
+                && !self.allow_same_root
+                && !self.allow_parents
+                && !self.allow_lower_versions)
+                || self
+                    .allow
+                    .iter()
+                    .any(|ns| ns.matches(source_file.namespace()))
+                || self
+                    .allow_paths
+                    .iter()
+                    .any(|path| path.matches(source_file.path())))
+                && !self
+                    .except
+                    .iter()
+                    .any(|ns| ns.matches(source_file.namespace()))
+                && !self
+                    .except_paths
+                    .iter()
+                    .any(|path| path.matches(source_file.path()));
+            let self_reference =
+                self.allow_self && self.selector.matches(source_file);
+
+            !in_allow_list && !self_reference
+        };
+
+        // The namespaces found in a denied source directory, by the index of
+        // the directory. A directory written as a glob denies every directory
+        // that it matches.
+        let denied_dirs: Vec<Option<PathMatcher>> = self
+            .deny_src_dirs
+            .iter()
+            .map(|dir| {
+                let glob = SourceDir::from(dir.as_str()).is_glob();
+                glob.then(|| dir.parse().ok()).flatten()
+            })
+            .collect();
+        let in_denied_src_dir: BTreeMap<String, usize> = source_files
+            .iter()
+            .filter_map(|source_file| {
+                let path = Path::new(source_file.path());
+                let dir =
+                    self.deny_src_dirs.iter().zip(&denied_dirs).position(
+                        |(dir, glob)| match glob {
+                            Some(glob) => path.ancestors().any(|ancestor| {
+                                ancestor
+                                    .to_str()
+                                    .is_some_and(|dir| glob.matches(dir))
+                            }),
+                            None => path.starts_with(dir),
+                        },
+                    )?;
+                Some((source_file.namespace().to_owned(), dir))
+            })
+            .collect();
+
+        // The namespaces with a denied tag, by the index of the tag.
+        let with_denied_tag: BTreeMap<String, usize> = source_files
+            .iter()
+            .filter_map(|source_file| {
+                let tag = self
+                    .deny_tagged
+                    .iter()
+                    .position(|tag| source_file.tags.contains(tag))?;
+                Some((source_file.namespace().to_owned(), tag))
+            })
+            .collect();
+
+        let mut forbidden: Vec<String> = source_files
+            .iter()
+            .filter(|source_file| {
+                not_allowed(source_file)
+                    || in_denied_src_dir.contains_key(source_file.namespace())
+                    || with_denied_tag.contains_key(source_file.namespace())
+            })
+            .map(|source_file| source_file.namespace().to_owned())
+            .collect();
+        // Sorted, so that the regex is the same whatever order the files were
+        // found in, and without the duplicates of namespaces with a file for
+        // each platform.
+        forbidden.sort();
+        forbidden.dedup();
+
+        // Only kept when needed, as they are every namespace in the tree.
+        let internal: BTreeSet<String> = if self.no_external {
+            source_files
+                .iter()
+                .map(|source_file| source_file.namespace().to_owned())
+                .collect()
+        } else {
+            BTreeSet::new()
+        };
+
+        let alternation = forbidden
+            .iter()
+            .map(|namespace| regex::escape(namespace))
+            .intersperse("|".to_string())
+            .collect::<String>();
+        // References are checked individually, so the whole reference must
+        // be a forbidden namespace. Being anchored, the alternation can't
+        // stop at a forbidden parent of the reference, whatever their order.
+        let regex = format!("^(?:{})$", alternation);
+        debug!(
+            rule = %self.selector,
+            forbidden = forbidden.len(),
+            "compiled rule"
+        );
+
+        CompiledRule {
+            selector: self.selector,
+            allow: self.allow,
+            except: self.except,
+            allow_paths: self.allow_paths,
+            except_paths: self.except_paths,
+            deny: self.deny,
+            deny_src_dirs: self.deny_src_dirs,
+            when_path: self.when_path,
+            allow_self: self.allow_self,
+            allow_same_root: self.allow_same_root,
+            allow_parents: self.allow_parents,
+            allow_lower_versions: self.allow_lower_versions,
+            no_external: self.no_external,
+            tagged: self.tagged,
+            deny_tagged: self.deny_tagged,
+            must_reference: self.must_reference,
+            severity: self.severity,
+            tags: self.tags,
+            doc: self.doc,
+            in_denied_src_dir,
+            with_denied_tag,
+            forbidden,
+            internal,
+            checker: Regex::new(&regex).expect("valid regex"),
+        }
+    }
+}
+
+/// A rule, ready to check source code against.
+#[derive(Debug)]
+pub struct CompiledRule {
+    selector: Selector,
+    allow: Vec<NamespaceMatcher>,
+    except: Vec<NamespaceMatcher>,
+    allow_paths: Vec<PathMatcher>,
+    except_paths: Vec<PathMatcher>,
+    deny: Vec<NamespaceMatcher>,
+    deny_src_dirs: Vec<String>,
+    when_path: Option<PathMatcher>,
+    allow_self: bool,
+    allow_same_root: bool,
+    allow_parents: bool,
+    allow_lower_versions: bool,
+    no_external: bool,
+    tagged: Option<TagCondition>,
+    deny_tagged: Vec<String>,
+    must_reference: Vec<NamespaceMatcher>,
+    severity: Severity,
+    tags: Vec<String>,
+    doc: Option<String>,
+    in_denied_src_dir: BTreeMap<String, usize>,
+    with_denied_tag: BTreeMap<String, usize>,
+    forbidden: Vec<String>,
+    /// The namespaces in the source tree, when references to others are
+    /// forbidden.
+    internal: BTreeSet<String>,
+    checker: Regex,
+}
+
+impl CompiledRule {
+    fn matches(&self, file: &ClojureSourceFile) -> bool {
+        self.selector.matches(file)
+            && self
+                .when_path
+                .as_ref()
+                .is_none_or(|when_path| when_path.matches(file.path()))
+            && self
+                .tagged
+                .as_ref()
+                .is_none_or(|tagged| tagged.matches(file))
+    }
+
+    /// Whether the rule is run when only the rules with one of `tags` are,
+    /// which every rule is when no tags are given.
+    fn has_any_tag(&self, tags: &[String]) -> bool {
+        tags.is_empty() || self.tags.iter().any(|tag| tags.contains(tag))
+    }
+
+    fn restrict_to(&self) -> Vec<String> {
+        restrict_to(
+            &self.allow,
+            &self.allow_paths,
+            self.allow_lower_versions,
+            &self.except,
+            &self.except_paths,
+        )
+    }
+
+    /// Describes the regexes compiled from the rule's patterns, for debugging
+    /// the translation of patterns to regexes.
+    fn regexes(&self) -> String {
+        let matchers = |key: &str, matchers: &[NamespaceMatcher]| {
+            matchers
+                .iter()
+                .map(|ns| format!("  {} {}  {}\n", key, ns, ns.regex.as_str()))
+                .collect::<String>()
+        };
+        let selector = match &self.selector {
+            Selector::Namespace(ns) => ns.regex.as_str().to_owned(),
+            Selector::Path(path) => path.glob.glob().regex().to_owned(),
+        };
+        let paths = |key: &str, matchers: &[PathMatcher]| {
+            matchers
+                .iter()
+                .map(|path| {
+                    format!(
+                        "  {} {}  {}\n",
+                        key,
+                        path,
+                        path.glob.glob().regex()
+                    )
+                })
+                .collect::<String>()
+        };
+        let when_path = paths(":when-path", self.when_path.as_slice());
+
+        format!(
+            "{}  {}\n{}{}{}{}{}{}  checker  {}\n",
+            self.selector,
+            selector,
+            when_path,
+            matchers(":restrict-to", &self.allow),
+            paths(":restrict-to", &self.allow_paths),
+            matchers(":restrict-to !", &self.except),
+            paths(":restrict-to !", &self.except_paths),
+            matchers(":deny", &self.deny),
+            self.checker.as_str()
+        )
+    }
+
+    /// Why a reference to `namespace` breaks the rule, if it does. The allow
+    /// list only forbids namespaces in the source tree, unless the rule
+    /// forbids external namespaces, but denied namespaces are forbidden
+    /// wherever they come from.
+    fn why_forbidden(&self, namespace: &str) -> Option<Forbidden<'_>> {
+        if let Some(denied) = self.deny.iter().find(|ns| ns.matches(namespace))
+        {
+            Some(Forbidden::Denied(denied))
+        } else if let Some(dir) = self.in_denied_src_dir.get(namespace) {
+            Some(Forbidden::InDeniedSrcDir(&self.deny_src_dirs[*dir]))
+        } else if let Some(tag) = self.with_denied_tag.get(namespace) {
+            Some(Forbidden::DeniedTag(&self.deny_tagged[*tag]))
+        } else if self.checker.is_match(namespace) {
+            match self.except.iter().find(|ns| ns.matches(namespace)) {
+                Some(excluded) => Some(Forbidden::Excluded(excluded)),
+                None => Some(Forbidden::NotAllowed),
+            }
+        } else if self.no_external && !self.internal.contains(namespace) {
+            match self.except.iter().find(|ns| ns.matches(namespace)) {
+                Some(excluded) => Some(Forbidden::Excluded(excluded)),
+                None if self.allow.iter().any(|ns| ns.matches(namespace)) => {
+                    None
+                }
+                None => Some(Forbidden::External),
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Why a reference from `source` to `namespace` breaks the rule, if it
+    /// does. Unlike `why_forbidden`, this takes into account the references
+    /// that the rule allows because of the namespace they are made from.
+    fn why_forbidden_from(
+        &self,
+        source: &str,
+        namespace: &str,
+    ) -> Option<Forbidden<'_>> {
+        match self.why_forbidden(namespace) {
+            Some(Forbidden::NotAllowed)
+                if (self.allow_same_root && same_root(source, namespace))
+                    || (self.allow_parents
+                        && is_ancestor(namespace, source))
+                    || (self.allow_lower_versions
+                        && same_or_lower_version(source, namespace)) =>
+            {
+                None
+            }
+            reason => reason,
+        }
+    }
+
+    /// Describes why a reference to `namespace` breaks the rule.
+    fn explain(&self, namespace: &str, reason: Forbidden<'_>) -> String {
+        let rule = format!("the rule for '{}'", self.selector);
+        match reason {
+            Forbidden::Denied(denied) => {
+                format!(
+                    "{} denies '{}' with ':deny [{}]'",
+                    rule, namespace, denied
+                )
+            }
+            Forbidden::InDeniedSrcDir(dir) => format!(
+                "{} denies '{}', as it is in the source directory {:?}, with \
+                 ':deny-src-dirs [{:?}]'",
+                rule, namespace, dir, dir
+            ),
+            Forbidden::DeniedTag(tag) => format!(
+                "{} denies '{}', as it is tagged ^:{}, with ':deny-tagged \
+                 [:{}]'",
+                rule, namespace, tag, tag
+            ),
+            Forbidden::Excluded(excluded) => format!(
+                "{} excludes '{}' from the namespaces it allows with '!{}'",
+                rule, namespace, excluded
+            ),
+            Forbidden::External => format!(
+                "{} forbids references outside the source tree with \
+                 ':no-external true', and '{}' is neither in the source tree \
+                 nor allowed by ':restrict-to [{}]'",
+                rule,
+                namespace,
+                self.restrict_to().join(" ")
+            ),
+            Forbidden::NotAllowed => {
+                let others: Vec<&str> = [
+                    (self.allow_self, "itself"),
+                    (self.allow_same_root, "namespaces with the same root"),
+                    (self.allow_parents, "its parents"),
+                ]
+                .iter()
+                .filter(|(allowed, _)| *allowed)
+                .map(|(_, description)| *description)
+                .collect();
+                let restrict_to = format!(
+                    "':restrict-to [{}]'",
+                    self.restrict_to().join(" ")
+                );
+                let allowed = if others.is_empty() {
+                    restrict_to
+                } else {
+                    format!("{} and to {}", others.join(", to "), restrict_to)
+                };
+                format!(
+                    "{} only allows references to {}, which doesn't include \
+                     '{}'",
+                    rule, allowed, namespace
+                )
+            }
+        }
+    }
+
+    /// The help shown with a violation of the rule, which may include the
+    /// rule's documentation and why the reference broke the rule.
+    fn help(&self, why: String, options: &DisplayOptions) -> Option<String> {
+        let doc = match options.verbosity {
+            Verbosity::Verbose => self.doc.clone(),
+            Verbosity::Quiet | Verbosity::Normal => None,
+        };
+        let why = options.why.then_some(why);
+
+        match (doc, why) {
+            (Some(doc), Some(why)) => Some(format!("{}\n{}", why, doc)),
+            (doc, why) => doc.or(why),
+        }
+    }
+
+    /// Checks `file` against the rule, attributing any violations to the rule
+    /// at `index`.
+    fn apply(
+        &self,
+        index: usize,
+        file: &ClojureSourceFile,
+        code: &str,
+        known_namespaces: Option<&BTreeSet<&str>>,
+        report: &mut Report,
+    ) {
+        let mut references = match ns::read_references(code) {
+            Ok(Some(references)) => references,
+            Ok(None) => {
+                report.file_skipped(Warning::skipped(
+                    file.path(),
+                    format!(
+                        "{} does not declare a namespace, skipping",
+                        file.path()
+                    ),
+                ));
+                return;
+            }
+            Err(error) => {
+                report.file_skipped(Warning::skipped(
+                    file.path(),
+                    format!(
+                        "failed to read the ns form of {}: {}",
+                        file.path(),
+                        error
+                    ),
+                ));
+                return;
+            }
+        };
+
+        if let Some(known_namespaces) = known_namespaces {
+            self.read_qualified_symbols(
+                file,
+                code,
+                known_namespaces,
+                &mut references,
+                report,
+            );
+        }
+
+        report.references_checked(references.len());
+        for violation in
+            self.violations(index, file, code, references, report.options())
+        {
+            report.violation(violation);
+        }
+    }
+
+    /// The `references` made by the `code` of `file` that break the rule,
+    /// attributed to the rule at `index`.
+    fn violations(
+        &self,
+        index: usize,
+        file: &ClojureSourceFile,
+        code: &str,
+        references: Vec<ns::Reference>,
+        options: &DisplayOptions,
+    ) -> Vec<Violation> {
+        let missing =
+            self.missing_references(index, file, code, &references, options);
+        let forbidden = references.into_iter().filter_map(|reference| {
+            let reason = self
+                .why_forbidden_from(file.namespace(), &reference.namespace)?;
+            let why = self.explain(&reference.namespace, reason);
+            Some((reference, why))
+        });
+
+        forbidden
+            .map(|(reference, why)| {
+                let Range { start, end } = reference.span;
+                let (snippet_start, snippet_end) =
+                    snippet_bounds(code, start, end);
+
+                let (line, column) = line_and_column(code, start);
+                let path = options.display_path(file.path());
+                Violation {
+                    src: NamedSource::new(&path, code.to_owned()),
+                    path,
+                    line,
+                    column,
+                    src_ns: file.namespace().to_owned(),
+                    ref_ns: reference.namespace,
+                    missing: false,
+                    rule: index,
+                    severity: self.severity,
+                    help: self.help(why, options),
+                    reason: ns::read_reason(code, end).map(str::to_owned),
+                    ns_style: options.ns_style(),
+                    snippet: (snippet_start, snippet_end - snippet_start)
+                        .into(),
+                    ref_location: (start, end - start).into(),
+                }
+            })
+            .chain(missing)
+            .collect()
+    }
+
+    /// A violation for each of the rule's `:must-reference` patterns that
+    /// none of the `references` made by the `code` of `file` match, located
+    /// at the name in its `ns` form, or at the start of code without one.
+    fn missing_references(
+        &self,
+        index: usize,
+        file: &ClojureSourceFile,
+        code: &str,
+        references: &[ns::Reference],
+        options: &DisplayOptions,
+    ) -> Vec<Violation> {
+        let Range { start, end } =
+            ns::read_ns_name(code, file.namespace()).unwrap_or(0..0);
+
+        self.must_reference
+            .iter()
+            .filter(|required| {
+                !references
+                    .iter()
+                    .any(|reference| required.matches(&reference.namespace))
+            })
+            .map(|required| {
+                let (snippet_start, snippet_end) =
+                    snippet_bounds(code, start, end);
+                let (line, column) = line_and_column(code, start);
+                let path = options.display_path(file.path());
+                let why = format!(
+                    "the rule for '{}' requires every namespace it governs to \
+                     reference '{}'",
+                    self.selector, required
+                );
+                Violation {
+                    src: NamedSource::new(&path, code.to_owned()),
+                    path,
+                    line,
+                    column,
+                    src_ns: file.namespace().to_owned(),
+                    ref_ns: required.to_string(),
+                    missing: true,
+                    rule: index,
+                    severity: self.severity,
+                    help: self.help(why, options),
+                    reason: None,
+                    ns_style: options.ns_style(),
+                    snippet: (snippet_start, snippet_end - snippet_start)
+                        .into(),
+                    ref_location: (start, end - start).into(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl CompiledRule {
+    /// Adds the first qualified symbol in `code` that refers to each of the
+    /// `known_namespaces` to `references`, unless the `ns` form already
+    /// references the namespace, so that each is reported only once.
+    fn read_qualified_symbols(
+        &self,
+        file: &ClojureSourceFile,
+        code: &str,
+        known_namespaces: &BTreeSet<&str>,
+        references: &mut Vec<ns::Reference>,
+        report: &mut Report,
+    ) {
+        let qualified = match ns::read_qualified_symbols(code) {
+            Ok(qualified) => qualified,
+            Err(error) => {
+                report.warn(
+                    Warning::new(
+                        WarningCode::UnreadableSymbols,
+                        format!(
+                            "failed to read the qualified symbols in {}: {}",
+                            file.path(),
+                            error
+                        ),
+                    )
+                    .at(file.path()),
+                );
+                return;
+            }
+        };
+
+        let mut seen: BTreeSet<String> = references
+            .iter()
+            .map(|reference| reference.namespace.clone())
+            .collect();
+        for reference in qualified {
+            if known_namespaces.contains(reference.namespace.as_str())
+                && seen.insert(reference.namespace.clone())
+            {
+                references.push(reference);
+            }
+        }
+    }
+}
+
+/// Why a reference breaks a rule.
+#[derive(Debug)]
+enum Forbidden<'r> {
+    /// The reference is matched by the rule's `:deny` list.
+    Denied(&'r NamespaceMatcher),
+    /// The reference is to a namespace in a source directory in the rule's
+    /// `:deny-src-dirs` list.
+    InDeniedSrcDir(&'r str),
+    /// The reference is to a namespace with a tag in the rule's `:deny-tagged`
+    /// list.
+    DeniedTag(&'r str),
+    /// The reference is removed from the allowed namespaces by a negated
+    /// `:restrict-to` entry.
+    Excluded(&'r NamespaceMatcher),
+    /// The reference is in the source tree, but is neither a self-reference
+    /// nor allowed by `:restrict-to`.
+    NotAllowed,
+    /// The reference is outside the source tree, which the rule forbids, and
+    /// isn't allowed by `:restrict-to`.
+    External,
+}
+
+/// Whether two namespaces have the same first segment, such as `app.core` and
+/// `app.db`. A namespace with a single segment, such as `user`, belongs to no
+/// package, so shares its root with no other namespace.
+fn same_root(a: &str, b: &str) -> bool {
+    match (a.split_once('.'), b.split_once('.')) {
+        (Some((a, _)), Some((b, _))) => a == b,
+        _ => false,
+    }
+}
+
+/// Whether `ancestor` is a parent of `namespace`, or of one of its parents,
+/// such as `app` and `app.db` are of `app.db.pool`.
+fn is_ancestor(ancestor: &str, namespace: &str) -> bool {
+    namespace
+        .strip_prefix(ancestor)
+        .is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// The entries of a rule's `:restrict-to`, as they are written in EDN.
+fn restrict_to(
+    allow: &[NamespaceMatcher],
+    allow_paths: &[PathMatcher],
+    allow_lower_versions: bool,
+    except: &[NamespaceMatcher],
+    except_paths: &[PathMatcher],
+) -> Vec<String> {
+    allow
+        .iter()
+        .map(ToString::to_string)
+        .chain(allow_paths.iter().map(ToString::to_string))
+        .chain(
+            allow_lower_versions.then(|| ":same-or-lower-version".to_string()),
+        )
+        .chain(except.iter().map(|ns| format!("!{}", ns)))
+        .chain(
+            except_paths
+                .iter()
+                .map(|path| format!("{:?}", format!("!{}", path.pattern))),
+        )
+        .collect()
+}
+
+/// Whether `namespace` is the same or a lower version than `source`, such as
+/// `api.v1.users` is of `api.v2.handlers`. Both must have a version segment,
+/// a `v` followed by a number, after the same leading segments.
+fn same_or_lower_version(source: &str, namespace: &str) -> bool {
+    let version =
+        |segment: &str| segment.strip_prefix('v')?.parse::<u64>().ok();
+    source
+        .split('.')
+        .zip(namespace.split('.'))
+        .find_map(|(a, b)| match (version(a), version(b)) {
+            (Some(a), Some(b)) => Some(b <= a),
+            _ if a == b => None,
+            _ => Some(false),
+        })
+        .unwrap_or(false)
+}
+
+/// `strings` quoted, and separated by spaces, as they are written in EDN.
+fn quoted(strings: &[String]) -> String {
+    strings
+        .iter()
+        .map(|string| format!("{:?}", string))
+        .intersperse(" ".to_string())
+        .collect()
+}
+
+/// The byte offsets of the start and end of the lines around the reference
+/// from `start` to `end`. The end excludes the line ending, including the \r
+/// of a \r\n, so that files with Windows line endings show no stray \r.
+fn snippet_bounds(code: &str, start: usize, end: usize) -> (usize, usize) {
+    let snippet_start = code[..start]
+        .rmatch_indices('\n')
+        .nth(4)
+        .map(|(i, _)| i + 1) // Skip over the \n itself
+        .unwrap_or(0);
+    let snippet_end = code[end..]
+        .match_indices('\n')
+        .nth(4)
+        .map(|(i, _)| i + end)
+        .unwrap_or(code.len());
+    let snippet_end =
+        end.max(code[..snippet_end].trim_end_matches(['\r', '\n']).len());
+
+    (snippet_start, snippet_end)
+}
+
+/// The 1-based line and column of the character at byte `offset` in `code`.
+fn line_and_column(code: &str, offset: usize) -> (usize, usize) {
+    let preceding = &code[..offset];
+    let line_start = preceding.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = preceding.matches('\n').count() + 1;
+    let column = preceding[line_start..].chars().count() + 1;
+
+    (line, column)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn source_file(namespace: &str, path: &str) -> ClojureSourceFile {
+        ClojureSourceFile {
+            entry: format!("{}{}", namespace, path),
+            path_start: namespace.len(),
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn source_files_are_found_in_order() {
+        let mut report = Report::new(DisplayOptions::default());
+
+        let source_files = find_source_files(
+            &["tests/fixtures".into()],
+            NamespaceMapping::Clojure,
+            &mut report,
+        );
+
+        let namespaces: Vec<_> =
+            source_files.iter().map(|file| file.namespace()).collect();
+        assert_eq!(
+            namespaces,
+            vec![
+                "bom",
+                "cljs.refer-clojure",
+                "cljs.require-macros",
+                "cljs.string-requires",
+                "commented-require",
+                "crlf",
+                "declarations.code-before-ns",
+                "declarations.gen-class",
+                "declarations.in-ns",
+                "declarations.license-header",
+                "declarations.multiple-ns",
+                "declarations.no-ns",
+                "declarations.ns-and-in-ns",
+                "deep.qualified",
+                "discarded-require",
+                "generated",
+                "latin1",
+                "munged.com.acme.app",
+                "munged.com.my-org.core",
+                "prefix-list",
+                "relocated.legacy.billing-api",
+                "relocated.scratch",
+                "roots.src.shipping.port",
+                "roots.src.shipping.ship",
+                "roots.test.shipping.fixtures",
+                "tagged.internal",
+                "tagged.public",
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_namespaces_replace_those_of_the_path() {
+        let mut report = Report::new(DisplayOptions::default());
+        let mut source_files = find_source_files(
+            &["tests/fixtures/relocated".into()],
+            NamespaceMapping::Clojure,
+            &mut report,
+        );
+
+        read_declared_namespaces(&mut source_files, Encoding::Utf8);
+
+        let namespaces: Vec<_> =
+            source_files.iter().map(|file| file.namespace()).collect();
+        assert_eq!(namespaces, vec!["billing.api", "scratch"]);
+        assert_eq!(
+            source_files[0].path(),
+            "tests/fixtures/relocated/legacy/billing_api.clj"
+        );
+    }
+
+    #[test]
+    fn excluded_subdirectories_are_not_searched() {
+        let mut report = Report::new(DisplayOptions::default());
+        let source_dir = SourceDir {
+            path: "tests/fixtures".into(),
+            exclude: vec!["cljs".into(), "roots/test".into()],
+        };
+
+        let source_files = find_source_files(
+            &[source_dir],
+            NamespaceMapping::Clojure,
+            &mut report,
+        );
+
+        let namespaces: Vec<_> =
+            source_files.iter().map(|file| file.namespace()).collect();
+        assert!(namespaces.contains(&"roots.src.shipping.port"));
+        assert!(!namespaces.iter().any(|ns| ns.starts_with("cljs.")));
+        assert!(!namespaces.contains(&"roots.test.shipping.fixtures"));
+    }
+
+    #[test]
+    fn source_dirs_can_be_globs() {
+        let mut report = Report::new(DisplayOptions::default());
+
+        let source_files = find_source_files(
+            &["tests/fixtures/roots/*".into(), "modules/*/src".into()],
+            NamespaceMapping::Clojure,
+            &mut report,
+        );
+
+        let namespaces: Vec<_> =
+            source_files.iter().map(|file| file.namespace()).collect();
+        assert_eq!(
+            namespaces,
+            vec!["shipping.port", "shipping.ship", "shipping.fixtures"]
+        );
+        assert!(report
+            .to_string()
+            .contains("source directory modules/*/src matches no directories"));
+    }
+
+    #[test]
+    fn source_dirs_without_clojure_files_are_reported() {
+        let mut report = Report::new(DisplayOptions::default());
+
+        let source_files = find_source_files(
+            &["tests/fixtures".into(), "src".into()],
+            NamespaceMapping::Clojure,
+            &mut report,
+        );
+
+        assert!(!source_files.is_empty());
+        assert!(report
+            .to_string()
+            .contains("source directory src contains no Clojure source files"));
+        assert!(!report.to_string().contains("tests/fixtures contains"));
+    }
+
+    #[test]
+    fn namespace_is_derived_from_path() {
+        let path: PathBuf = ["com", "my_org", "core.clj"].iter().collect();
+
+        assert_eq!(
+            NamespaceMapping::Clojure.namespace_of(&path).unwrap(),
+            "com.my-org.core"
+        );
+    }
+
+    #[test]
+    fn namespace_ignores_all_but_the_last_extension() {
+        let path: PathBuf = ["com", "core.test.cljc"].iter().collect();
+
+        assert_eq!(
+            NamespaceMapping::Clojure.namespace_of(&path).unwrap(),
+            "com.core.test"
+        );
+    }
+
+    #[test]
+    fn clojurescript_names_are_demunged() {
+        let path: PathBuf = ["app", "default$", "valid_QMARK__test.cljs"]
+            .iter()
+            .collect();
+
+        assert_eq!(
+            NamespaceMapping::Clojurescript.namespace_of(&path).unwrap(),
+            "app.default.valid?-test"
+        );
+        assert_eq!(
+            NamespaceMapping::Clojure.namespace_of(&path).unwrap(),
+            "app.default$.valid-QMARK--test"
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn namespace_is_derived_from_path_with_mixed_separators() {
+        let path = Path::new(r"com/my_org\core.clj");
+
+        assert_eq!(
+            NamespaceMapping::Clojure.namespace_of(path).unwrap(),
+            "com.my-org.core"
+        );
+    }
+
+    #[test]
+    fn can_match_full_namespace() {
+        let matcher: NamespaceMatcher = "shipping.domain.ship".parse().unwrap();
+
+        assert!(matcher.matches("shipping.domain.ship"));
+        assert!(!matcher.matches("shipping.domain.port"));
+    }
+
+    #[test]
+    fn can_match_wildcard_within_namespace() {
+        let matcher: NamespaceMatcher = "shipping.dom*.ship".parse().unwrap();
+
+        assert!(matcher.matches("shipping.domain.ship"));
+        assert!(matcher.matches("shipping.domestic.ship"));
+        assert!(!matcher.matches("shipping.use-case.routing"));
+        assert!(!matcher.matches("shipping.domain.port"));
+    }
+
+    #[test]
+    fn can_match_wildcard_sub_namespace() {
+        let matcher: NamespaceMatcher = "shipping.use-case.*".parse().unwrap();
+
+        assert!(matcher.matches("shipping.use-case.routing"));
+        assert!(matcher.matches("shipping.use-case.contract-verification"));
+        assert!(matcher.matches("shipping.use-case.routing.route"));
+        assert!(!matcher.matches("shipping.use-case"));
+        assert!(!matcher.matches("shipping.domain.ship"));
+        assert!(!matcher.matches("flying.use-case.routing"));
+    }
+
+    #[test]
+    fn can_match_character_class_within_segment() {
+        let matcher: NamespaceMatcher = "shipping.v[0-9].api".parse().unwrap();
+
+        assert!(matcher.matches("shipping.v1.api"));
+        assert!(matcher.matches("shipping.v9.api"));
+        assert!(!matcher.matches("shipping.v10.api"));
+        assert!(!matcher.matches("shipping.vx.api"));
+    }
+
+    #[test]
+    fn character_class_may_repeat() {
+        let matcher: NamespaceMatcher = "shipping.v[0-9]+.*".parse().unwrap();
+
+        assert!(matcher.matches("shipping.v10.api"));
+        assert!(!matcher.matches("shipping.v.api"));
+    }
+
+    #[test]
+    fn other_regex_characters_match_themselves() {
+        let matcher: NamespaceMatcher = "shipping.ship?".parse().unwrap();
+
+        assert!(matcher.matches("shipping.ship?"));
+        assert!(!matcher.matches("shipping.shi"));
+    }
+
+    #[test]
+    fn patterns_subsume_the_namespaces_they_match() {
+        let subsumes = |a: &str, b: &str| {
+            let a: NamespaceMatcher = a.parse().unwrap();
+            a.subsumes(&b.parse().unwrap())
+        };
+
+        assert!(subsumes("com.acme.*", "com.acme.db"));
+        assert!(subsumes("com.acme.*", "com.acme.db.*"));
+        assert!(subsumes("com.*.db", "com.acme.db"));
+        assert!(subsumes("com.acme.*", "com.acme.*"));
+        assert!(!subsumes("com.acme.*", "com.acme"));
+        assert!(!subsumes("com.acme.db", "com.acme.db.*"));
+        assert!(!subsumes("com.acme.*", "com.*.db"));
+        assert!(!subsumes("com.acme.db.*", "com.acme.*"));
+    }
+
+    #[test]
+    fn warns_about_allowed_patterns_that_others_subsume() {
+        let rules = vec![Rule {
+            allow: vec![
+                "com.acme.db".parse().unwrap(),
+                "com.acme.*".parse().unwrap(),
+                "com.acme.util.*".parse().unwrap(),
+                "com.acme.*".parse().unwrap(),
+                "org.other".parse().unwrap(),
+            ],
+            ..rule("com.acme.app")
+        }];
+        let mut report = Report::new(DisplayOptions::default());
+
+        warn_redundant_allows(&rules, &mut report);
+
+        let messages: Vec<_> = report
+            .warnings()
+            .iter()
+            .map(|warning| warning.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "the rule for 'com.acme.app' allows 'com.acme.db', which \
+                 'com.acme.*' already allows",
+                "the rule for 'com.acme.app' allows 'com.acme.util.*', which \
+                 'com.acme.*' already allows",
+                "the rule for 'com.acme.app' allows 'com.acme.*', which \
+                 'com.acme.*' already allows",
+            ]
+        );
+        assert!(report
+            .warnings()
+            .iter()
+            .all(|warning| warning.code == WarningCode::RedundantAllow));
+    }
+
+    #[test]
+    fn reports_error_on_separator_at_either_end() {
+        assert_eq!(
+            "a.b.".parse::<NamespaceMatcher>().unwrap_err(),
+            "namespace patterns cannot start with or end with '.'"
+        );
+        assert!(".a.b".parse::<NamespaceMatcher>().is_err());
+    }
+
+    #[test]
+    fn reports_error_on_invalid_character_class() {
+        assert!("shipping.v[0-9.api".parse::<NamespaceMatcher>().is_err());
+        assert!("shipping.v0-9].api".parse::<NamespaceMatcher>().is_err());
+        assert!("shipping.v[].api".parse::<NamespaceMatcher>().is_err());
+        assert!("shipping.v[^0].api".parse::<NamespaceMatcher>().is_err());
+        assert!("shipping.v[[0]].api".parse::<NamespaceMatcher>().is_err());
+    }
+
+    #[test]
+    fn full_namespace_must_match_entire_namespace() {
+        let matcher: NamespaceMatcher = "shipping.domain.ship".parse().unwrap();
+
+        assert!(!matcher.matches("shipping.domain.ship.internal"));
+        assert!(!matcher.matches("old.shipping.domain.ship"));
+    }
+
+    #[test]
+    fn can_match_leading_wildcard_segment() {
+        let matcher: NamespaceMatcher = "*.infrastructure".parse().unwrap();
+
+        assert!(matcher.matches("billing.infrastructure"));
+        assert!(matcher.matches("shipping.infrastructure"));
+        assert!(!matcher.matches("billing.infrastructure.db"));
+        assert!(!matcher.matches("acme.billing.infrastructure"));
+        assert!(!matcher.matches("infrastructure"));
+    }
+
+    #[test]
+    fn can_match_leading_wildcard_with_wildcard_sub_namespace() {
+        let matcher: NamespaceMatcher = "*.domain.*".parse().unwrap();
+
+        assert!(matcher.matches("billing.domain.invoice"));
+        assert!(matcher.matches("shipping.domain.ship.hull"));
+        assert!(!matcher.matches("shipping.domain"));
+        assert!(!matcher.matches("acme.shipping.domain.ship"));
+    }
+
+    #[test]
+    fn reports_error_on_invalid_namespace() {
+        assert!("shipping.use case.routing"
+            .parse::<NamespaceMatcher>()
+            .is_err());
+        assert!("".parse::<NamespaceMatcher>().is_err());
+        assert!(".".parse::<NamespaceMatcher>().is_err());
+        assert!(".use-case".parse::<NamespaceMatcher>().is_err());
+        assert!("use-case.".parse::<NamespaceMatcher>().is_err());
+    }
+
+    #[test]
+    fn audit_reports_governing_rule_and_policy() {
+        let source_files = vec![
+            source_file("shipping.entity.ship", "src/shipping/entity/ship.clj"),
+            source_file("shipping.service.db", "src/shipping/service/db.clj"),
+        ];
+        let rule = rule("shipping.entity.*");
+        let rules = vec![rule.compile(&source_files)];
+
+        let audit = audit_rules(&rules, &source_files);
+
+        assert_eq!(audit[0]["rule"]["pattern"], "shipping.entity.*");
+        assert_eq!(
+            audit[0]["rule"]["forbidden"],
+            json!(["shipping.service.db"])
+        );
+        assert_eq!(audit[1]["rule"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn path_selector_matches_file_path() {
+        let selector = Selector::Path("src/shipping/*/db.clj".parse().unwrap());
+
+        assert!(selector.matches(&source_file(
+            "shipping.service.db",
+            "src/shipping/service/db.clj"
+        )));
+        assert!(!selector.matches(&source_file(
+            "shipping.service.pg.db",
+            "src/shipping/service/pg/db.clj"
+        )));
+    }
+
+    #[test]
+    fn path_selector_double_star_crosses_directories() {
+        let selector = Selector::Path("src/shipping/**".parse().unwrap());
+
+        assert!(selector.matches(&source_file(
+            "shipping.service.pg.db",
+            "src/shipping/service/pg/db.clj"
+        )));
+        assert!(!selector
+            .matches(&source_file("billing.core", "src/billing/core.clj")));
+    }
+
+    pub(crate) fn rule(pattern: &str) -> Rule {
+        Rule {
+            selector: Selector::Namespace(pattern.parse().unwrap()),
+            restricted: true,
+            allow: vec!["shipping.entity.*".parse().unwrap()],
+            except: vec![],
+            allow_paths: vec![],
+            except_paths: vec![],
+            allow_transitive: vec![],
+            deny: vec![],
+            deny_src_dirs: vec![],
+            when_path: None,
+            allow_self: true,
+            allow_same_root: false,
+            allow_parents: false,
+            allow_lower_versions: false,
+            no_external: false,
+            tagged: None,
+            deny_tagged: vec![],
+            must_reference: vec![],
+            severity: Severity::Error,
+            tags: vec![],
+            doc: None,
+        }
+    }
+
+    #[test]
+    fn disabled_rules_are_removed() {
+        let mut rules = vec![rule("shipping.entity.*"), rule("shipping.*")];
+        let mut report = Report::new(DisplayOptions::default());
+
+        disable_rules(
+            &mut rules,
+            &["shipping.entity.*".to_string(), "billing.*".to_string()],
+            &mut report,
+        );
+
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].selector.pattern(), "shipping.*");
+        assert!(report
+            .to_string()
+            .contains("no rule has the pattern 'billing.*'"));
+    }
+
+    #[test]
+    fn only_rules_with_a_selected_tag_are_run() {
+        let source_files = vec![source_file(
+            "com.acme.app",
+            "tests/fixtures/prefix_list.clj",
+        )];
+        let tagged = |pattern: &str, denied: &str, tag: &str| Rule {
+            allow: vec![],
+            deny: vec![denied.parse().unwrap()],
+            tags: vec![tag.into()],
+            ..rule(pattern)
+        };
+        let rules: Vec<_> = vec![
+            tagged("com.acme.app", "com.acme.db", "security"),
+            tagged("com.acme.*", "com.acme.util", "layering"),
+        ]
+        .into_iter()
+        .map(|rule| rule.compile(&source_files))
+        .collect();
+
+        let violations = |tags: &[&str]| {
+            let tags: Vec<String> =
+                tags.iter().map(|&tag| tag.into()).collect();
+            let mut report = Report::new(DisplayOptions::default());
+            report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+            let checks = Checks {
+                tags: &tags,
+                ..Checks::default()
+            };
+            apply_rules(&rules, &source_files, &checks, &mut report);
+            report.violation_count()
+        };
+
+        assert_eq!(violations(&[]), 1);
+        assert_eq!(violations(&["security"]), 1);
+        // The namespace isn't left to the next rule when its own isn't run
+        assert_eq!(violations(&["layering"]), 0);
+        assert_eq!(violations(&["layering", "security"]), 1);
+
+        let mut report = Report::new(DisplayOptions::default());
+        warn_unknown_tags(&rules, &["nightly".into()], &mut report);
+        assert!(report
+            .to_string()
+            .contains("no rule has the tag ':nightly'"));
+    }
+
+    #[test]
+    fn compliance_counts_allowed_and_forbidden_references() {
+        let source_files = vec![source_file(
+            "com.acme.app",
+            "tests/fixtures/prefix_list.clj",
+        )];
+        let rules = vec![Rule {
+            allow: vec![],
+            deny: vec!["com.acme.db".parse().unwrap()],
+            ..rule("com.acme.*")
+        }
+        .compile(&source_files)];
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Json,
+            compliance: true,
+            ..DisplayOptions::default()
+        });
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.to_string()).unwrap();
+        assert_eq!(json["compliance"]["references"], 6);
+        assert_eq!(json["compliance"]["allowed"], 5);
+        assert_eq!(json["compliance"]["forbidden"], 1);
+        assert!(report.summary().contains(
+            "83.3% compliant, 5 of 6 references allowed, 1 forbidden"
+        ));
+    }
+
+    #[test]
+    fn ungoverned_namespaces_fail_when_coverage_is_required() {
+        let source_files =
+            vec![source_file("billing.core", "src/billing/core.clj")];
+        let rules = vec![rule("shipping.*").compile(&source_files)];
+
+        let mut report = Report::new(DisplayOptions::default());
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+        assert_eq!(report.exit_status(), 0);
+
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Stable,
+            ..DisplayOptions::default()
+        });
+        apply_rules(
+            &rules,
+            &source_files,
+            &Checks {
+                require_coverage: true,
+                ..Checks::default()
+            },
+            &mut report,
+        );
+        assert_eq!(report.exit_status(), 1);
+        assert_eq!(
+            report.to_string(),
+            "UNGOVERNED src/billing/core.clj billing.core\n"
+        );
+    }
+
+    #[test]
+    fn denied_namespaces_are_forbidden_outside_the_source_tree() {
+        let source_files = vec![
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+        ];
+        let rule = Rule {
+            restricted: false,
+            allow: vec![],
+            deny: vec!["cheshire.*".parse().unwrap()],
+            ..rule("com.acme.app")
+        }
+        .compile(&source_files);
+
+        assert!(rule.why_forbidden("cheshire.core").is_some());
+        assert!(!rule.why_forbidden("com.acme.db").is_some());
+        assert!(!rule.why_forbidden("clojure.string").is_some());
+    }
+
+    #[test]
+    fn negations_alone_still_restrict_references() {
+        let source_files = vec![
+            source_file("a.core", "src/a/core.clj"),
+            source_file("a.db", "src/a/db.clj"),
+            source_file("a.internal", "src/a/internal.clj"),
+        ];
+        let rule = Rule {
+            allow: vec![],
+            except: vec!["a.internal".parse().unwrap()],
+            ..rule("a.core")
+        }
+        .compile(&source_files);
+
+        assert!(matches!(
+            rule.why_forbidden("a.db"),
+            Some(Forbidden::NotAllowed)
+        ));
+        assert!(matches!(
+            rule.why_forbidden("a.internal"),
+            Some(Forbidden::Excluded(_))
+        ));
+        assert!(rule.why_forbidden("a.core").is_none());
+    }
+
+    #[test]
+    fn allow_list_only_forbids_namespaces_in_the_source_tree() {
+        let source_files = vec![
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+        ];
+        let rule = Rule {
+            allow: vec!["cheshire.core".parse().unwrap()],
+            ..rule("com.acme.app")
+        }
+        .compile(&source_files);
+
+        assert!(rule.why_forbidden("com.acme.db").is_some());
+        assert!(!rule.why_forbidden("cheshire.core").is_some());
+        assert!(!rule.why_forbidden("clojure.string").is_some());
+    }
+
+    #[test]
+    fn invalid_utf8_is_skipped_unless_lossy() {
+        let file =
+            source_file("shipping.legacy.douane", "tests/fixtures/latin1.clj");
+        let mut report = Report::new(DisplayOptions::default());
+
+        assert_eq!(file.read(Encoding::Utf8, &mut report), None);
+
+        let code = file.read(Encoding::Lossy, &mut report).unwrap();
+        assert_eq!(
+            ns::read_references(&code).unwrap().unwrap()[0].namespace,
+            "shipping.entity.port"
+        );
+    }
+
+    #[test]
+    fn explains_why_references_are_forbidden() {
+        let source_files = vec![
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+            source_file("com.acme.internal", "src/com/acme/internal.clj"),
+        ];
+        let rule = Rule {
+            allow: vec!["com.acme.i*".parse().unwrap()],
+            except: vec!["com.acme.internal".parse().unwrap()],
+            deny: vec!["cheshire.*".parse().unwrap()],
+            ..rule("com.acme.app")
+        }
+        .compile(&source_files);
+
+        assert!(matches!(
+            rule.why_forbidden("cheshire.core"),
+            Some(Forbidden::Denied(_))
+        ));
+        assert!(matches!(
+            rule.why_forbidden("com.acme.internal"),
+            Some(Forbidden::Excluded(_))
+        ));
+        assert!(matches!(
+            rule.why_forbidden("com.acme.db"),
+            Some(Forbidden::NotAllowed)
+        ));
+        assert!(rule.why_forbidden("com.acme.app").is_none());
+    }
+
+    #[test]
+    fn production_code_may_not_reference_test_code() {
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Stable,
+            ..DisplayOptions::default()
+        });
+        let source_files = find_source_files(
+            &[
+                "tests/fixtures/roots/src".into(),
+                "tests/fixtures/roots/test".into(),
+            ],
+            NamespaceMapping::Clojure,
+            &mut report,
+        );
+        let rule = Rule {
+            selector: Selector::Path(
+                "tests/fixtures/roots/src/**".parse().unwrap(),
+            ),
+            allow: vec![],
+            deny_src_dirs: vec!["tests/fixtures/roots/test".into()],
+            ..rule("shipping.*")
+        };
+        let rules = vec![rule.compile(&source_files)];
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+
+        assert!(matches!(
+            rules[0].why_forbidden("shipping.fixtures"),
+            Some(Forbidden::InDeniedSrcDir("tests/fixtures/roots/test"))
+        ));
+        assert_eq!(
+            report.to_string(),
+            "VIOLATION tests/fixtures/roots/src/shipping/port.clj 3:14 \
+             shipping.port -> shipping.fixtures\n"
+        );
+    }
+
+    #[test]
+    fn references_match_namespaces_found_in_munged_paths() {
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Stable,
+            ..DisplayOptions::default()
+        });
+        let source_files = find_source_files(
+            &["tests/fixtures/munged".into()],
+            NamespaceMapping::Clojure,
+            &mut report,
+        );
+        let rule = Rule {
+            allow: vec!["com.acme.*".parse().unwrap()],
+            ..rule("com.acme.*")
+        };
+        let rules = vec![rule.compile(&source_files)];
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+
+        // com/my_org/core.clj is com.my-org.core, as it is referenced
+        assert_eq!(rules[0].forbidden, vec!["com.my-org.core"]);
+        assert_eq!(
+            report.to_string(),
+            "VIOLATION tests/fixtures/munged/com/acme/app.clj 2:14 \
+             com.acme.app -> com.my-org.core\n"
+        );
+    }
+
+    #[test]
+    fn describes_the_regexes_of_a_rule() {
+        let source_files = vec![
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+        ];
+        let rule = Rule {
+            restricted: false,
+            allow: vec![],
+            deny: vec!["cheshire.*".parse().unwrap()],
+            ..rule("com.acme.app")
+        }
+        .compile(&source_files);
+
+        assert_eq!(
+            rule.regexes(),
+            "com.acme.app  ^com\\.acme\\.app$\n  \
+             :deny cheshire.*  ^cheshire\\.[[[:alnum:]]\\.\\*\\+!\\-_\\?\\$%\\&=<>]+$\n  \
+             checker  ^(?:)$\n"
+        );
+    }
+
+    #[test]
+    fn discarded_requires_are_not_violations() {
+        let source_files = vec![source_file(
+            "shipping.ui.port",
+            "tests/fixtures/discarded_require.clj",
+        )];
+        let rule = Rule {
+            allow: vec![],
+            deny: vec!["com.acme.*".parse().unwrap()],
+            ..rule("shipping.ui.*")
+        };
+        let rules = vec![rule.compile(&source_files)];
+        let mut report = Report::new(DisplayOptions::default());
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+
+        assert_eq!(report.exit_status(), 0);
+    }
+
+    #[test]
+    fn path_conditions_narrow_the_files_a_rule_applies_to() {
+        let handler = source_file(
+            "app.web.orders-handler",
+            "src/app/web/orders_handler.clj",
+        );
+        let routes = source_file("app.web.routes", "src/app/web/routes.clj");
+        let rule = Rule {
+            allow: vec!["app.service.*".parse().unwrap()],
+            when_path: Some("**/*_handler.clj".parse().unwrap()),
+            ..rule("app.web.*")
+        }
+        .compile(&[]);
+
+        assert!(rule.matches(&handler));
+        assert!(!rule.matches(&routes));
+    }
+
+    #[test]
+    fn files_checked_in_parallel_are_reported_in_order() {
+        let mut report = Report::new(DisplayOptions::default());
+        let source_files = find_source_files(
+            &["example/src".into(), "tests/fixtures".into()],
+            NamespaceMapping::Clojure,
+            &mut report,
+        );
+        let rules = vec![
+            rule("shipping.entity.*").compile(&source_files),
+            rule("shipping.*").compile(&source_files),
+            rule("cljs.*").compile(&source_files),
+        ];
+        let report = |jobs| {
+            let mut report = Report::new(DisplayOptions {
+                format: Format::Stable,
+                ..DisplayOptions::default()
+            });
+            report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+            apply_rules(
+                &rules,
+                &source_files,
+                &Checks {
+                    require_coverage: true,
+                    jobs,
+                    ..Checks::default()
+                },
+                &mut report,
+            );
+            report.to_string()
+        };
+
+        let sequential = report(1);
+        assert!(sequential.contains("VIOLATION"));
+        assert!(sequential.contains("UNGOVERNED"));
+        for _ in 0..10 {
+            assert_eq!(report(4), sequential);
+        }
+    }
+
+    #[test]
+    fn later_namespaces_are_checked_against_their_own_rules() {
+        let source_files = vec![source_file(
+            "com.acme.scratch",
+            "tests/fixtures/declarations/ns_and_in_ns.clj",
+        )];
+        let denying = |pattern: &str, deny: &str| Rule {
+            allow: vec![],
+            deny: vec![deny.parse().unwrap()],
+            ..rule(pattern)
+        };
+        let rules = vec![
+            denying("com.acme.api", "com.acme.http").compile(&source_files),
+            denying("com.acme.*", "com.acme.other").compile(&source_files),
+        ];
+        let check = |multi_ns| {
+            let mut report = Report::new(DisplayOptions {
+                format: Format::Stable,
+                ..DisplayOptions::default()
+            });
+            report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+            apply_rules(
+                &rules,
+                &source_files,
+                &Checks {
+                    multi_ns,
+                    ..Checks::default()
+                },
+                &mut report,
+            );
+            report
+        };
+
+        let report = check(false);
+        assert_eq!(report.exit_status(), 0);
+        assert_eq!(report.warnings()[0].code, WarningCode::MultipleNs);
+
+        let report = check(true);
+        assert!(report.warnings().is_empty());
+        assert_eq!(
+            report.to_string(),
+            "VIOLATION tests/fixtures/declarations/ns_and_in_ns.clj 10:14 \
+             com.acme.api -> com.acme.http\n"
+        );
+    }
+
+    #[test]
+    fn deep_checks_find_qualified_symbols_in_the_source_tree() {
+        let source_files = vec![
+            source_file("deep.qualified", "tests/fixtures/deep/qualified.clj"),
+            source_file("shipping.entity.port", "src/shipping/entity/port.clj"),
+            source_file(
+                "shipping.service.database",
+                "src/shipping/service/database.clj",
+            ),
+        ];
+        let rule = rule("deep.*");
+        let rules = vec![rule.compile(&source_files)];
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Stable,
+            ..DisplayOptions::default()
+        });
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+        assert_eq!(report.exit_status(), 0);
+
+        apply_rules(
+            &rules,
+            &source_files,
+            &Checks {
+                deep: true,
+                ..Checks::default()
+            },
+            &mut report,
+        );
+        assert_eq!(
+            report.to_string(),
+            "VIOLATION tests/fixtures/deep/qualified.clj 5:34 deep.qualified \
+             -> shipping.service.database\n"
+        );
+    }
+
+    #[test]
+    fn files_with_the_skip_marker_are_skipped() {
+        let source_files = vec![source_file(
+            "shipping.ui.generated",
+            "tests/fixtures/generated.clj",
+        )];
+        let rule = Rule {
+            allow: vec![],
+            deny: vec!["shipping.service.*".parse().unwrap()],
+            ..rule("shipping.ui.*")
+        };
+        let rules = vec![rule.compile(&source_files)];
+        let check = |skip_marker| {
+            let mut report = Report::new(DisplayOptions::default());
+            report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+            apply_rules(
+                &rules,
+                &source_files,
+                &Checks {
+                    skip_marker,
+                    ..Checks::default()
+                },
+                &mut report,
+            );
+            report
+        };
+
+        assert_eq!(check(None).exit_status(), 1);
+
+        let report = check(Some(";; GENERATED"));
+        assert_eq!(report.exit_status(), 0);
+        assert!(report.to_string().contains("generated.clj is generated"));
+    }
+
+    #[test]
+    fn same_root_references_can_be_allowed() {
+        let source_files = vec![
+            source_file("billing.core", "src/billing/core.clj"),
+            source_file("billing.db", "src/billing/db.clj"),
+            source_file("shipping.db", "src/shipping/db.clj"),
+            source_file("user", "dev/user.clj"),
+        ];
+        let rule = Rule {
+            allow: vec![],
+            allow_same_root: true,
+            ..rule("billing.core")
+        }
+        .compile(&source_files);
+
+        assert!(rule
+            .why_forbidden_from("billing.core", "billing.db")
+            .is_none());
+        assert!(rule
+            .why_forbidden_from("billing.core", "shipping.db")
+            .is_some());
+        assert!(rule.why_forbidden_from("billing.core", "user").is_some());
+        assert!(rule.why_forbidden_from("billing", "billing.db").is_some());
+    }
+
+    #[test]
+    fn references_to_parents_can_be_allowed() {
+        let source_files = vec![
+            source_file("app.db", "src/app/db.clj"),
+            source_file("app.db.pool", "src/app/db/pool.clj"),
+            source_file("app.dbx", "src/app/dbx.clj"),
+            source_file("app.http", "src/app/http.clj"),
+        ];
+        let rule = Rule {
+            allow: vec!["app.http".parse().unwrap()],
+            allow_parents: true,
+            ..rule("app.db.pool")
+        }
+        .compile(&source_files);
+
+        assert!(rule.why_forbidden_from("app.db.pool", "app.db").is_none());
+        assert!(rule.why_forbidden_from("app.db.pool", "app.http").is_none());
+        assert!(rule.why_forbidden_from("app.db.pool", "app.dbx").is_some());
+    }
+
+    #[test]
+    fn external_references_can_be_forbidden() {
+        let source_files = vec![
+            source_file("app.domain.order", "src/app/domain/order.clj"),
+            source_file("app.domain.price", "src/app/domain/price.clj"),
+        ];
+        let rules = vec![Rule {
+            allow: vec!["clojure.*".parse().unwrap()],
+            except: vec!["clojure.java.*".parse().unwrap()],
+            no_external: true,
+            ..rule("app.domain.*")
+        }
+        .compile(&source_files)];
+
+        let violations = check_source(
+            "app.domain.order",
+            "(ns app.domain.order
+               (:require [app.domain.price :as price]
+                         [cheshire.core :as json]
+                         [clojure.java.io :as io]
+                         [clojure.string :as str]))",
+            &rules,
+        );
+
+        let references: Vec<_> = violations
+            .iter()
+            .map(|violation| violation.ref_ns.as_str())
+            .collect();
+        assert_eq!(references, vec!["cheshire.core", "clojure.java.io"]);
+        assert!(matches!(
+            rules[0].why_forbidden("cheshire.core"),
+            Some(Forbidden::External)
+        ));
+    }
+
+    #[test]
+    fn namespaces_can_be_allowed_by_path() {
+        let source_files = vec![
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+            source_file("lib.json", "shared/lib/json.clj"),
+            source_file("lib.secrets", "shared/internal/lib/secrets.clj"),
+            source_file("other.http", "src/other/http.clj"),
+        ];
+        let rule = Rule {
+            allow: vec!["com.acme.*".parse().unwrap()],
+            except: vec!["com.acme.db".parse().unwrap()],
+            allow_paths: vec!["shared/**".parse().unwrap()],
+            except_paths: vec!["**/internal/**".parse().unwrap()],
+            ..rule("com.acme.app")
+        }
+        .compile(&source_files);
+
+        assert_eq!(
+            rule.forbidden,
+            vec!["com.acme.db", "lib.secrets", "other.http"]
+        );
+        assert_eq!(
+            rule.restrict_to(),
+            vec![
+                "com.acme.*",
+                "\"shared/**\"",
+                "!com.acme.db",
+                "\"!**/internal/**\""
+            ]
+        );
+    }
+
+    #[test]
+    fn references_to_lower_versions_can_be_allowed() {
+        let source_files = vec![
+            source_file("api.v2.users", "src/api/v2/users.clj"),
+            source_file("api.v3.users", "src/api/v3/users.clj"),
+            source_file("api.v10.users", "src/api/v10/users.clj"),
+            source_file("web.v1.users", "src/web/v1/users.clj"),
+        ];
+        let rule = Rule {
+            allow: vec![],
+            allow_lower_versions: true,
+            ..rule("api.*.handlers")
+        }
+        .compile(&source_files);
+
+        assert!(rule
+            .why_forbidden_from("api.v3.handlers", "api.v2.users")
+            .is_none());
+        assert!(rule
+            .why_forbidden_from("api.v2.handlers", "api.v3.users")
+            .is_some());
+        assert!(rule
+            .why_forbidden_from("api.v3.handlers", "api.v10.users")
+            .is_some());
+        assert!(rule
+            .why_forbidden_from("api.v3.handlers", "web.v1.users")
+            .is_some());
+    }
+
+    #[test]
+    fn references_to_children_of_forbidden_namespaces_name_the_child() {
+        let source_files = vec![
+            source_file("com.acme", "src/com/acme.clj"),
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+        ];
+        let rules = vec![Rule {
+            allow: vec!["clojure.*".parse().unwrap()],
+            ..rule("com.acme.app")
+        }
+        .compile(&source_files)];
+        assert_eq!(
+            rules[0].checker.as_str(),
+            "^(?:com\\.acme|com\\.acme\\.db)$"
+        );
+
+        let violations = check_source(
+            "com.acme.app",
+            "(ns com.acme.app (:require com.acme.db com.acme))",
+            &rules,
+        );
+
+        let references: Vec<_> = violations
+            .iter()
+            .map(|violation| violation.ref_ns.as_str())
+            .collect();
+        assert_eq!(references, vec!["com.acme.db", "com.acme"]);
+        assert_eq!(violations[0].ref_location.len(), "com.acme.db".len());
+    }
+
+    #[test]
+    fn compiled_regex_does_not_depend_on_file_order() {
+        let mut source_files = vec![
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.http", "src/com/acme/http.clj"),
+            source_file("com.acme.http", "src/com/acme/http.cljs"),
+        ];
+        let rule = || Rule {
+            allow: vec!["com.acme.util".parse().unwrap()],
+            ..rule("com.acme.app")
+        };
+
+        let first = rule().compile(&source_files);
+        source_files.reverse();
+        let second = rule().compile(&source_files);
+
+        assert_eq!(first.checker.as_str(), second.checker.as_str());
+        assert_eq!(
+            first.checker.as_str(),
+            "^(?:com\\.acme\\.db|com\\.acme\\.http)$"
+        );
+    }
+
+    #[test]
+    fn rules_can_select_and_deny_namespaces_by_tag() {
+        let mut source_files = vec![
+            source_file(
+                "tagged.internal",
+                "tests/fixtures/tagged/internal.clj",
+            ),
+            source_file("tagged.public", "tests/fixtures/tagged/public.clj"),
+        ];
+        read_tags(&mut source_files, Encoding::Utf8);
+        let rule = Rule {
+            allow: vec![],
+            tagged: Some(TagCondition {
+                tag: "internal".into(),
+                present: false,
+            }),
+            deny_tagged: vec!["internal".into()],
+            ..rule("tagged.*")
+        };
+        assert!(rule.uses_tags());
+        let rules = vec![rule.compile(&source_files)];
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Stable,
+            ..DisplayOptions::default()
+        });
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+
+        assert!(!rules[0].matches(&source_files[0]));
+        assert!(rules[0].matches(&source_files[1]));
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+        assert_eq!(
+            report.to_string(),
+            "VIOLATION tests/fixtures/tagged/public.clj 2:14 tagged.public \
+             -> tagged.internal\n"
+        );
+    }
+
+    #[test]
+    fn snippets_exclude_the_line_ending() {
+        let code = "(ns a.b\r\n  (:require [c.d]))\r\n";
+
+        assert_eq!(snippet_bounds(code, 22, 25), (0, 28));
+        assert_eq!(snippet_bounds(&code.replace('\r', ""), 21, 24), (0, 27));
+    }
+
+    #[test]
+    fn crlf_line_endings_do_not_affect_positions() {
+        let source_files = vec![source_file("crlf", "tests/fixtures/crlf.clj")];
+        let rules = vec![Rule {
+            allow: vec![],
+            deny: vec!["crlf.other".parse().unwrap()],
+            ..rule("crlf")
+        }
+        .compile(&source_files)];
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Stable,
+            ..DisplayOptions::default()
+        });
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+        assert_eq!(
+            report.to_string(),
+            "VIOLATION tests/fixtures/crlf.clj 4:14 crlf -> crlf.other\n"
+        );
+    }
+
+    #[test]
+    fn byte_order_marks_are_skipped() {
+        let source_files = vec![source_file("bom", "tests/fixtures/bom.clj")];
+        let rules = vec![Rule {
+            allow: vec![],
+            deny: vec!["clojure.string".parse().unwrap()],
+            ..rule("bom")
+        }
+        .compile(&source_files)];
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Stable,
+            ..DisplayOptions::default()
+        });
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+
+        apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+        assert_eq!(
+            report.to_string(),
+            "VIOLATION tests/fixtures/bom.clj 1:20 bom -> clojure.string\n"
+        );
+    }
+
+    #[test]
+    fn source_in_memory_can_be_checked() {
+        let source_files = vec![
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+        ];
+        let rules = vec![Rule {
+            allow: vec![],
+            deny: vec!["com.acme.db".parse().unwrap()],
+            ..rule("com.acme.app")
+        }
+        .compile(&source_files)];
+
+        let violations = check_source(
+            "com.acme.app",
+            "(ns com.acme.app\n  (:require [com.acme.db :as db]))",
+            &rules,
+        );
+
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].ref_ns, "com.acme.db");
+        assert_eq!((violations[0].line, violations[0].column), (2, 14));
+        assert!(
+            check_source("com.acme.db", "(ns com.acme.db)", &rules).is_empty()
+        );
+    }
+
+    #[test]
+    fn computes_line_and_column_of_offset() {
+        let code = "(ns a.b\n  (:require [c.d]))";
+
+        assert_eq!(line_and_column(code, 0), (1, 1));
+        assert_eq!(line_and_column(code, 4), (1, 5));
+        assert_eq!(line_and_column(code, 21), (2, 14));
+    }
+
+    #[test]
+    fn self_references_can_be_forbidden() {
+        let source_files = vec![
+            source_file("com.acme.app", "src/com/acme/app.clj"),
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+        ];
+        let rule = |allow_self| {
+            Rule {
+                allow: vec!["clojure.*".parse().unwrap()],
+                allow_self,
+                ..rule("com.acme.*")
+            }
+            .compile(&source_files)
+        };
+
+        assert!(rule(true).forbidden.is_empty());
+
+        let strict = rule(false);
+        assert_eq!(strict.forbidden, vec!["com.acme.app", "com.acme.db"]);
+        let reason = strict
+            .why_forbidden_from("com.acme.app", "com.acme.db")
+            .unwrap();
+        assert_eq!(
+            strict.explain("com.acme.db", reason),
+            "the rule for 'com.acme.*' only allows references to \
+             ':restrict-to [clojure.*]', which doesn't include 'com.acme.db'"
+        );
+    }
+
+    #[test]
+    fn snippets_on_stdin_are_checked_as_the_assumed_namespace() {
+        let source_files = vec![
+            source_file("shipping.entity.ship", "src/shipping/entity/ship.clj"),
+            source_file("shipping.service.db", "src/shipping/service/db.clj"),
+        ];
+        let rules = vec![rule("shipping.entity.*").compile(&source_files)];
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Stable,
+            ..DisplayOptions::default()
+        });
+        report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+        let snippet = "(defn ship! [] (shipping.service.db/save!))";
+
+        assert!(stdin_file(snippet, None, &mut report).is_none());
+        let file =
+            stdin_file(snippet, Some("shipping.entity.ship"), &mut report)
+                .unwrap();
+        check_stdin(
+            &rules,
+            &source_files,
+            &file,
+            snippet,
+            &Checks::default(),
+            &mut report,
+        );
+        // A declared namespace takes precedence over the assumed one
+        let declared = stdin_file(
+            "(ns shipping.entity.port)",
+            Some("shipping.entity.ship"),
+            &mut report,
+        )
+        .unwrap();
+        assert_eq!(declared.namespace(), "shipping.entity.port");
+
+        assert_eq!(
+            report.to_string(),
+            "WARNING skipped-file <stdin> does not declare a namespace, pass \
+             --assume-namespace to check it\n\
+             WARNING assumed-namespace <stdin> declares shipping.entity.port, \
+             so --assume-namespace shipping.entity.ship is ignored\n\
+             VIOLATION <stdin> 1:17 shipping.entity.ship -> shipping.service.db\n"
+        );
+    }
+
+    #[test]
+    fn namespaces_missing_required_references_are_violations() {
+        let rules = vec![Rule {
+            allow: vec![],
+            must_reference: vec![
+                "com.acme.auth".parse().unwrap(),
+                "com.acme.middleware.*".parse().unwrap(),
+            ],
+            ..rule("com.acme.handlers.*")
+        }
+        .compile(&[])];
+
+        let complete = check_source(
+            "com.acme.handlers.orders",
+            "(ns com.acme.handlers.orders
+               (:require [com.acme.auth :as auth]
+                         [com.acme.middleware.cors :as cors]))",
+            &rules,
+        );
+        assert!(complete.is_empty());
+
+        let violations = check_source(
+            "com.acme.handlers.orders",
+            ";; Orders\n(ns ^:api com.acme.handlers.orders
+               (:require [com.acme.middleware.cors :as cors]))",
+            &rules,
+        );
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            violations[0].to_string(),
+            "'com.acme.handlers.orders' is missing required reference \
+             'com.acme.auth'"
+        );
+        assert!(violations[0].missing);
+        assert_eq!((violations[0].line, violations[0].column), (2, 11));
+        assert_eq!(violations[0].check_name(), "missing_required_reference");
+    }
+
+    #[test]
+    fn violations_are_passed_on_in_the_order_of_the_files() {
+        let source_files = vec![
+            source_file("com.acme.app", "tests/fixtures/prefix_list.clj"),
+            source_file("billing.core", "tests/fixtures/missing.clj"),
+            source_file(
+                "shipping.ui.port",
+                "tests/fixtures/discarded_require.clj",
+            ),
+        ];
+        let rules: Vec<_> = vec![
+            Rule {
+                allow: vec![],
+                deny: vec![
+                    "com.acme.db".parse().unwrap(),
+                    "clojure.set".parse().unwrap(),
+                ],
+                ..rule("com.acme.*")
+            },
+            Rule {
+                allow: vec![],
+                deny: vec!["shipping.entity.*".parse().unwrap()],
+                ..rule("shipping.*")
+            },
+        ]
+        .into_iter()
+        .map(|rule| rule.compile(&source_files))
+        .collect();
+
+        for jobs in [1, 4] {
+            let checks = Checks {
+                jobs,
+                ..Checks::default()
+            };
+            let mut violations = vec![];
+            let found =
+                check_files(&rules, &source_files, &checks, |violation| {
+                    violations.push(format!(
+                        "{} -> {}",
+                        violation.src_ns, violation.ref_ns
+                    ))
+                });
+
+            assert_eq!(found, 3);
+            assert_eq!(
+                violations,
+                vec![
+                    "com.acme.app -> com.acme.db",
+                    "com.acme.app -> clojure.set",
+                    "shipping.ui.port -> shipping.entity.port",
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn warned_violations_can_be_printed_to_a_stream_of_their_own() {
+        let source_files = vec![
+            source_file("com.acme.app", "tests/fixtures/prefix_list.clj"),
+            source_file(
+                "shipping.ui.port",
+                "tests/fixtures/discarded_require.clj",
+            ),
+        ];
+        let rules: Vec<_> = vec![
+            Rule {
+                allow: vec![],
+                deny: vec!["com.acme.db".parse().unwrap()],
+                ..rule("com.acme.*")
+            },
+            Rule {
+                allow: vec![],
+                deny: vec!["shipping.entity.*".parse().unwrap()],
+                severity: Severity::Warn,
+                ..rule("shipping.*")
+            },
+        ]
+        .into_iter()
+        .map(|rule| rule.compile(&source_files))
+        .collect();
+        let print = |split_severity| {
+            let mut report = Report::new(DisplayOptions {
+                format: Format::Stable,
+                ..DisplayOptions::default()
+            });
+            report.rules(rules.iter().map(|rule| rule.selector.to_string()));
+            apply_rules(&rules, &source_files, &Checks::default(), &mut report);
+            let (mut stdout, mut stderr) = (vec![], vec![]);
+            print_report(&mut report, split_severity, &mut stdout, &mut stderr)
+                .unwrap();
+            (
+                String::from_utf8(stdout).unwrap(),
+                String::from_utf8(stderr).unwrap(),
+                report.exit_status(),
+            )
+        };
+
+        let warned = "VIOLATION tests/fixtures/discarded_require.clj 3:14 \
+                      shipping.ui.port -> shipping.entity.port\n";
+        let failed = "VIOLATION tests/fixtures/prefix_list.clj 2:34 \
+                      com.acme.app -> com.acme.db\n";
+
+        let (stdout, stderr, status) = print(None);
+        assert_eq!(
+            (stdout, stderr),
+            (format!("{}{}", warned, failed), "".into())
+        );
+        assert_eq!(status, 1);
+
+        let (stdout, stderr, status) = print(Some(Stream::Stderr));
+        assert_eq!((stdout.as_str(), stderr.as_str()), (failed, warned));
+        assert_eq!(status, 1);
+
+        let (stdout, stderr, _) = print(Some(Stream::Stdout));
+        assert_eq!((stdout.as_str(), stderr.as_str()), (warned, failed));
+    }
+
+    #[test]
+    fn source_trees_are_checked_as_the_command_line_checks_them() {
+        let check = |encoding| {
+            let mut violations = vec![];
+            check_source_tree(
+                Path::new("tests/config/skip_marker/ns-rules.edn"),
+                None,
+                None,
+                encoding,
+                NonZeroUsize::new(2).unwrap(),
+                |violation| violations.push(violation.src_ns.clone()),
+            )
+            .unwrap();
+            violations
+        };
+
+        // The generated file is skipped, and the Latin-1 file only read lossily
+        assert_eq!(check(Encoding::Utf8), vec!["shipping.ui.port"]);
+        assert_eq!(
+            check(Encoding::Lossy),
+            vec!["shipping.ui.legacy", "shipping.ui.port"]
+        );
+    }
+
+    fn forbidden_namespaces(
+        allow: &[&str],
+        except: &[&str],
+        source_files: &[ClojureSourceFile],
+    ) -> Vec<String> {
+        let rule = Rule {
+            allow: allow.iter().map(|ns| ns.parse().unwrap()).collect(),
+            except: except.iter().map(|ns| ns.parse().unwrap()).collect(),
+            ..rule("com.acme.app")
+        };
+
+        rule.compile(source_files).forbidden
+    }
+
+    #[test]
+    fn negated_allow_entry_removes_namespaces_from_those_allowed() {
+        let source_files = vec![
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+            source_file("com.acme.internal", "src/com/acme/internal.clj"),
+            source_file("com.acme.internal.x", "src/com/acme/internal/x.clj"),
+        ];
+
+        assert_eq!(
+            forbidden_namespaces(
+                &["com.acme.*"],
+                &["com.acme.internal"],
+                &source_files
+            ),
+            vec!["com.acme.internal"]
+        );
+        assert_eq!(
+            forbidden_namespaces(
+                &["com.acme.*"],
+                &["com.acme.internal.*"],
+                &source_files
+            ),
+            vec!["com.acme.internal.x"]
+        );
+    }
+
+    #[test]
+    fn negated_allow_entry_does_not_allow_anything() {
+        let source_files = vec![
+            source_file("com.acme.db", "src/com/acme/db.clj"),
+            source_file("com.other.db", "src/com/other/db.clj"),
+        ];
+
+        assert_eq!(
+            forbidden_namespaces(
+                &["com.acme.*"],
+                &["com.other.db"],
+                &source_files
+            ),
+            vec!["com.other.db"]
+        );
+    }
+
+    #[test]
+    fn negated_allow_entry_does_not_forbid_self_references() {
+        let source_files =
+            vec![source_file("com.acme.app", "src/com/acme/app.clj")];
+
+        assert!(forbidden_namespaces(
+            &["com.acme.*"],
+            &["com.acme.app"],
+            &source_files
+        )
+        .is_empty());
+    }
+}
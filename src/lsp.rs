@@ -0,0 +1,325 @@
+//! A minimal language server, which publishes the violations in each open
+//! Clojure buffer as diagnostics, so that editors can show them inline.
+//!
+//! The configuration is read once, when the server starts. The rules are
+//! compiled again whenever a Clojure file is created or deleted, as they
+//! depend on the namespaces in the source tree.
+
+use crate::{
+    check_source_at, compile_rules, ns, CompiledRule, Severity, Violation,
+};
+use miette::DiagnosticResult;
+use std::{
+    collections::BTreeMap,
+    env,
+    path::{Path, PathBuf},
+    sync::{Mutex, RwLock},
+};
+use tower_lsp::{
+    jsonrpc,
+    lsp_types::{
+        Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams,
+        DidChangeWatchedFilesParams, DidChangeWatchedFilesRegistrationOptions,
+        DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+        DidSaveTextDocumentParams, FileChangeType, FileSystemWatcher,
+        GlobPattern, InitializeParams, InitializeResult, InitializedParams,
+        MessageType, NumberOrString, Position, Range, Registration,
+        SaveOptions, ServerCapabilities, ServerInfo,
+        TextDocumentSyncCapability, TextDocumentSyncKind,
+        TextDocumentSyncOptions, TextDocumentSyncSaveOptions, Url, WatchKind,
+    },
+    Client, LanguageServer, LspService, Server,
+};
+
+/// Where to find the configuration, as given on the command line.
+pub(crate) struct ConfigSource {
+    pub path: PathBuf,
+    pub key: Option<String>,
+    pub profile: Option<String>,
+}
+
+impl ConfigSource {
+    fn compile_rules(&self) -> DiagnosticResult<Vec<CompiledRule>> {
+        compile_rules(&self.path, self.key.as_deref(), self.profile.as_deref())
+    }
+}
+
+/// Serves the language server protocol over stdin and stdout until the client
+/// exits.
+pub(crate) fn serve(config: ConfigSource) -> DiagnosticResult<()> {
+    let rules = config.compile_rules()?;
+    let (service, socket) = LspService::new(|client| Backend {
+        client,
+        config,
+        rules: RwLock::new(rules),
+        documents: Mutex::new(BTreeMap::new()),
+    });
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("the runtime can be started");
+    runtime.block_on(
+        Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+            .serve(service),
+    );
+
+    Ok(())
+}
+
+struct Backend {
+    client: Client,
+    config: ConfigSource,
+    rules: RwLock<Vec<CompiledRule>>,
+    /// The text of each open Clojure buffer, so that the buffers can be
+    /// checked again when the rules change.
+    documents: Mutex<BTreeMap<Url, String>>,
+}
+
+impl Backend {
+    /// Records the `text` of the buffer at `uri` and publishes its
+    /// violations.
+    async fn update(&self, uri: Url, text: String) {
+        if !is_clojure(&uri) {
+            return;
+        }
+        let diagnostics = self.diagnostics(&uri, &text);
+        self.documents
+            .lock()
+            .expect("documents are not poisoned")
+            .insert(uri.clone(), text);
+        self.client
+            .publish_diagnostics(uri, diagnostics, None)
+            .await;
+    }
+
+    /// The violations in the `text` of the buffer at `uri` as diagnostics.
+    /// Text without an `ns` form has no references to check.
+    fn diagnostics(&self, uri: &Url, text: &str) -> Vec<Diagnostic> {
+        let namespace = match ns::read_namespace(text) {
+            Ok(Some(namespace)) => namespace,
+            Ok(None) | Err(_) => return vec![],
+        };
+        let path = relative_path(uri).unwrap_or_default();
+        let rules = self.rules.read().expect("rules are not poisoned");
+
+        check_source_at(&namespace, &path, text, &rules)
+            .iter()
+            .map(|violation| diagnostic(text, violation))
+            .collect()
+    }
+
+    /// Compiles the rules again, then checks every open buffer against them.
+    async fn reload(&self) {
+        match self.config.compile_rules() {
+            Ok(rules) => {
+                *self.rules.write().expect("rules are not poisoned") = rules;
+            }
+            Err(error) => {
+                let error = error.inner();
+                let message = match error.source() {
+                    Some(cause) => format!("{}: {}", error, cause),
+                    None => error.to_string(),
+                };
+                self.client.show_message(MessageType::ERROR, message).await;
+                return;
+            }
+        }
+
+        let documents = self
+            .documents
+            .lock()
+            .expect("documents are not poisoned")
+            .clone();
+        for (uri, text) in documents {
+            let diagnostics = self.diagnostics(&uri, &text);
+            self.client
+                .publish_diagnostics(uri, diagnostics, None)
+                .await;
+        }
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(
+        &self,
+        _: InitializeParams,
+    ) -> jsonrpc::Result<InitializeResult> {
+        let sync = TextDocumentSyncOptions {
+            open_close: Some(true),
+            change: Some(TextDocumentSyncKind::FULL),
+            save: Some(TextDocumentSyncSaveOptions::SaveOptions(SaveOptions {
+                include_text: Some(true),
+            })),
+            ..TextDocumentSyncOptions::default()
+        };
+
+        Ok(InitializeResult {
+            capabilities: ServerCapabilities {
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    sync,
+                )),
+                ..ServerCapabilities::default()
+            },
+            server_info: Some(ServerInfo {
+                name: env!("CARGO_PKG_NAME").into(),
+                version: Some(env!("CARGO_PKG_VERSION").into()),
+            }),
+        })
+    }
+
+    async fn initialized(&self, _: InitializedParams) {
+        let watchers = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: GlobPattern::String(
+                    "**/*.{clj,cljs,cljc}".into(),
+                ),
+                kind: Some(WatchKind::Create | WatchKind::Delete),
+            }],
+        };
+        let registration = Registration {
+            id: "ns-rules-source-files".into(),
+            method: "workspace/didChangeWatchedFiles".into(),
+            register_options: serde_json::to_value(watchers).ok(),
+        };
+
+        // Without file watching, the rules are only as current as the source
+        // tree when the server started
+        if self
+            .client
+            .register_capability(vec![registration])
+            .await
+            .is_err()
+        {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    "the client can't watch files, so new namespaces won't be \
+                     checked against the rules",
+                )
+                .await;
+        }
+    }
+
+    async fn shutdown(&self) -> jsonrpc::Result<()> {
+        Ok(())
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        self.update(params.text_document.uri, params.text_document.text)
+            .await;
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        // With full sync, the last change holds the whole text
+        if let Some(change) = params.content_changes.into_iter().last() {
+            self.update(params.text_document.uri, change.text).await;
+        }
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        if let Some(text) = params.text {
+            self.update(params.text_document.uri, text).await;
+        }
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let uri = params.text_document.uri;
+        self.documents
+            .lock()
+            .expect("documents are not poisoned")
+            .remove(&uri);
+        self.client.publish_diagnostics(uri, vec![], None).await;
+    }
+
+    async fn did_change_watched_files(
+        &self,
+        params: DidChangeWatchedFilesParams,
+    ) {
+        let namespaces_changed = params.changes.iter().any(|change| {
+            change.typ == FileChangeType::CREATED
+                || change.typ == FileChangeType::DELETED
+        });
+        if namespaces_changed {
+            self.reload().await;
+        }
+    }
+}
+
+/// Whether the buffer at `uri` holds Clojure, ClojureScript or cljc code.
+fn is_clojure(uri: &Url) -> bool {
+    let extension = Path::new(uri.path())
+        .extension()
+        .and_then(|extension| extension.to_str());
+
+    matches!(extension, Some("clj" | "cljs" | "cljc"))
+}
+
+/// The path of the file at `uri` relative to the working directory, as the
+/// paths of source files are when the rules are applied from the command
+/// line, or `None` if it isn't a file under the working directory.
+fn relative_path(uri: &Url) -> Option<String> {
+    let path = uri.to_file_path().ok()?;
+    let working_dir = env::current_dir().ok()?;
+    let path = path.strip_prefix(working_dir).ok()?;
+
+    path.to_str().map(str::to_owned)
+}
+
+/// `violation` of the rules in `text` as an LSP diagnostic, covering the
+/// forbidden reference.
+fn diagnostic(text: &str, violation: &Violation) -> Diagnostic {
+    let start = violation.ref_location.offset();
+    let end = start + violation.ref_location.len();
+
+    Diagnostic {
+        range: Range::new(position(text, start), position(text, end)),
+        severity: Some(match violation.severity {
+            Severity::Error => DiagnosticSeverity::ERROR,
+            Severity::Warn => DiagnosticSeverity::WARNING,
+        }),
+        code: Some(NumberOrString::String(violation.check_name().into())),
+        source: Some(env!("CARGO_PKG_NAME").into()),
+        message: violation.to_string(),
+        ..Diagnostic::default()
+    }
+}
+
+/// The LSP position of byte `offset` in `text`. Its character is counted in
+/// UTF-16 code units, as LSP requires, rather than in bytes or characters.
+fn position(text: &str, offset: usize) -> Position {
+    let preceding = &text[..offset];
+    let line_start = preceding.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = preceding.matches('\n').count();
+    let character = preceding[line_start..].encode_utf16().count();
+
+    Position::new(line as u32, character as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        let text = "(ns a.b)\n;; 𝛌 λ\n(c.d/e)";
+
+        assert_eq!(position(text, 0), Position::new(0, 0));
+        assert_eq!(position(text, 9), Position::new(1, 0));
+        assert_eq!(
+            position(text, text.find('λ').unwrap()),
+            Position::new(1, 6)
+        );
+        assert_eq!(
+            position(text, text.find("(c").unwrap()),
+            Position::new(2, 0)
+        );
+    }
+
+    #[test]
+    fn only_clojure_buffers_are_checked() {
+        assert!(is_clojure(&"file:///src/a/b.cljc".parse().unwrap()));
+        assert!(!is_clojure(&"file:///README.md".parse().unwrap()));
+    }
+}
@@ -1,37 +1,981 @@
+//! A minimal Clojure reader, capable of reading just enough of a source file
+//! to find its `ns` form and the namespaces that form references, or the fully
+//! qualified symbols in its code.
+
+use std::ops::Range;
+use thiserror::Error;
+
+/// A form read from Clojure source, along with its location in the source.
 #[derive(Debug)]
-enum Form<'s> {
-    Collection(Collection),
+pub(crate) struct Form<'s> {
+    kind: FormKind<'s>,
+    span: Range<usize>,
+}
+
+#[derive(Debug)]
+enum FormKind<'s> {
+    List(Vec<Form<'s>>),
+    Vector(Vec<Form<'s>>),
+    Map(Vec<Form<'s>>),
+    Set(Vec<Form<'s>>),
     Symbol(&'s str),
     Keyword(&'s str),
-    Other(&'s str),
+    Str(&'s str),
+    /// A form preceded by metadata, `^meta form`.
+    Meta {
+        meta: Box<Form<'s>>,
+        form: Box<Form<'s>>,
+    },
+    /// A form preceded by a reader macro, such as a quote or a tag.
+    Prefixed(Box<Form<'s>>),
+    /// A reader conditional `#?(...)`, or splicing reader conditional
+    /// `#?@(...)`.
+    ReaderConditional {
+        splicing: bool,
+        forms: Vec<Form<'s>>,
+    },
+    /// Numbers, characters and other forms with no bearing on references.
+    Atom,
 }
 
-#[derive(Debug)]
-enum CollectionType {
-    List, Vector, Map, // Set not needed
+impl<'s> Form<'s> {
+    fn as_list(&self) -> Option<&[Form<'s>]> {
+        match &self.kind {
+            FormKind::List(forms) => Some(forms),
+            _ => None,
+        }
+    }
+
+    fn as_symbol(&self) -> Option<&'s str> {
+        match self.kind {
+            FormKind::Symbol(symbol) => Some(symbol),
+            _ => None,
+        }
+    }
+
+    fn as_keyword(&self) -> Option<&'s str> {
+        match self.kind {
+            FormKind::Keyword(keyword) => Some(keyword),
+            _ => None,
+        }
+    }
+
+    /// The keywords set to true by the metadata attached to the form, whether
+    /// written `^:tag` or `^{:tag true}`, without their leading ':'.
+    fn tags(&self) -> Vec<&'s str> {
+        let (meta, form) = match &self.kind {
+            FormKind::Meta { meta, form } => (meta, form),
+            _ => return vec![],
+        };
+
+        let mut tags = match &meta.kind {
+            FormKind::Keyword(tag) => vec![&tag[1..]],
+            FormKind::Map(entries) => entries
+                .chunks_exact(2)
+                .filter_map(|entry| match entry {
+                    [key, value] if value.as_symbol() == Some("true") => {
+                        Some(&key.as_keyword()?[1..])
+                    }
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        tags.extend(form.tags());
+        tags
+    }
+
+    /// The symbol quoted by the form, as in `'com.acme.db` or
+    /// `(quote com.acme.db)`.
+    fn as_quoted_symbol(&self) -> Option<&'s str> {
+        match &self.kind {
+            FormKind::Prefixed(form) => form.as_symbol(),
+            FormKind::List(forms) => match &forms[..] {
+                [quote, symbol] if quote.as_symbol() == Some("quote") => {
+                    symbol.as_symbol()
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The form itself, without any metadata attached to it.
+    fn without_meta(&self) -> &Form<'s> {
+        match &self.kind {
+            FormKind::Meta { form, .. } => form.without_meta(),
+            _ => self,
+        }
+    }
 }
 
-#[derive(Debug)]
-struct Collection {
-    collection_type: CollectionType,
-    start: usize,
-    elements: Vec<Form>,
+#[derive(Debug, Error, PartialEq)]
+pub(crate) enum ReadError {
+    #[error("'{delimiter}' at byte {offset} is never closed")]
+    Unclosed { delimiter: char, offset: usize },
+    #[error("unexpected '{delimiter}' at byte {offset}")]
+    Unexpected { delimiter: char, offset: usize },
+    #[error("the string starting at byte {offset} is never closed")]
+    UnclosedString { offset: usize },
+    #[error("expected a form after the reader macro at byte {offset}")]
+    MissingForm { offset: usize },
+}
+
+/// Reads the top level forms of Clojure source, one at a time.
+pub(crate) struct Reader<'s> {
+    code: &'s str,
+    pos: usize,
+}
+
+impl<'s> Reader<'s> {
+    pub(crate) fn new(code: &'s str) -> Self {
+        Self { code, pos: 0 }
+    }
+
+    /// Reads the next top level form, or `None` at the end of the source.
+    pub(crate) fn next_form(&mut self) -> Result<Option<Form<'s>>, ReadError> {
+        match self.read()? {
+            Some(form) => Ok(Some(form)),
+            None => match self.peek() {
+                Some(delimiter) => Err(ReadError::Unexpected {
+                    delimiter: delimiter as char,
+                    offset: self.pos,
+                }),
+                None => Ok(None),
+            },
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.code.as_bytes().get(self.pos).copied()
+    }
+
+    fn peek_next(&self) -> Option<u8> {
+        self.code.as_bytes().get(self.pos + 1).copied()
+    }
+
+    /// Reads the next form, returning `None` at the end of the source or when
+    /// a closing delimiter is found.
+    fn read(&mut self) -> Result<Option<Form<'s>>, ReadError> {
+        loop {
+            self.skip_whitespace();
+            let start = self.pos;
+            let kind = match (self.peek(), self.peek_next()) {
+                (None, _) | (Some(b')' | b']' | b'}'), _) => return Ok(None),
+                (Some(b'('), _) => FormKind::List(self.read_collection()?),
+                (Some(b'['), _) => FormKind::Vector(self.read_collection()?),
+                (Some(b'{'), _) => FormKind::Map(self.read_collection()?),
+                (Some(b'"'), _) => FormKind::Str(self.read_string()?),
+                (Some(b'\\'), _) => self.read_character(),
+                (Some(b'^'), _) => {
+                    self.pos += 1;
+                    FormKind::Meta {
+                        meta: Box::new(self.read_required(start)?),
+                        form: Box::new(self.read_required(start)?),
+                    }
+                }
+                (Some(b'~'), Some(b'@')) => {
+                    self.pos += 2;
+                    FormKind::Prefixed(Box::new(self.read_required(start)?))
+                }
+                (Some(b'\'' | b'`' | b'~' | b'@'), _) => {
+                    self.pos += 1;
+                    FormKind::Prefixed(Box::new(self.read_required(start)?))
+                }
+                (Some(b'#'), Some(b'{')) => {
+                    self.pos += 1;
+                    FormKind::Set(self.read_collection()?)
+                }
+                (Some(b'#'), Some(b'"')) => {
+                    self.pos += 1;
+                    self.read_string()?;
+                    FormKind::Atom
+                }
+                (Some(b'#'), Some(b'_')) => {
+                    // Discarded forms are read, but are never seen
+                    self.pos += 2;
+                    self.read_required(start)?;
+                    continue;
+                }
+                (Some(b'#'), Some(b'!')) => {
+                    self.skip_line();
+                    continue;
+                }
+                (Some(b'#'), Some(b'?')) => {
+                    self.pos += 2;
+                    let splicing = self.peek() == Some(b'@');
+                    if splicing {
+                        self.pos += 1;
+                    }
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'(') {
+                        Err(ReadError::MissingForm { offset: start })?
+                    }
+                    let forms = self.read_collection()?;
+                    FormKind::ReaderConditional { splicing, forms }
+                }
+                (Some(b'#'), Some(b'(' | b'\'' | b'=')) => {
+                    self.pos += 1;
+                    if self.peek() != Some(b'(') {
+                        self.pos += 1;
+                    }
+                    FormKind::Prefixed(Box::new(self.read_required(start)?))
+                }
+                (Some(b'#'), Some(b'#')) => {
+                    self.pos += 2;
+                    self.read_token();
+                    FormKind::Atom
+                }
+                (Some(b'#'), Some(b':')) => {
+                    // Namespaced map, #:ns{...}
+                    self.pos += 1;
+                    self.read_token();
+                    self.skip_whitespace();
+                    FormKind::Prefixed(Box::new(self.read_required(start)?))
+                }
+                (Some(b'#'), _) => {
+                    // Tagged literal, #tag form
+                    self.pos += 1;
+                    self.read_token();
+                    FormKind::Prefixed(Box::new(self.read_required(start)?))
+                }
+                (Some(_), _) => {
+                    let token = self.read_token();
+                    if token.starts_with(':') {
+                        FormKind::Keyword(token)
+                    } else if is_number(token) {
+                        FormKind::Atom
+                    } else {
+                        FormKind::Symbol(token)
+                    }
+                }
+            };
+
+            return Ok(Some(Form {
+                kind,
+                span: start..self.pos,
+            }));
+        }
+    }
+
+    /// Reads a form that must be present, following a reader macro.
+    fn read_required(&mut self, start: usize) -> Result<Form<'s>, ReadError> {
+        self.read()?.ok_or(ReadError::MissingForm { offset: start })
+    }
+
+    fn read_collection(&mut self) -> Result<Vec<Form<'s>>, ReadError> {
+        let start = self.pos;
+        let open = self.code.as_bytes()[start];
+        let close = match open {
+            b'(' => b')',
+            b'[' => b']',
+            _ => b'}',
+        };
+        self.pos += 1;
+
+        let mut forms = Vec::new();
+        while let Some(form) = self.read()? {
+            forms.push(form);
+        }
+
+        match self.peek() {
+            Some(delimiter) if delimiter == close => {
+                self.pos += 1;
+                Ok(forms)
+            }
+            Some(delimiter) => Err(ReadError::Unexpected {
+                delimiter: delimiter as char,
+                offset: self.pos,
+            }),
+            None => Err(ReadError::Unclosed {
+                delimiter: open as char,
+                offset: start,
+            }),
+        }
+    }
+
+    fn read_string(&mut self) -> Result<&'s str, ReadError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut escaped = false;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => return Ok(&self.code[start + 1..self.pos - 1]),
+                _ => {}
+            }
+        }
+
+        Err(ReadError::UnclosedString { offset: start })
+    }
+
+    fn read_character(&mut self) -> FormKind<'s> {
+        self.pos += 1;
+        // The first character is always part of the literal, even if it is a
+        // delimiter, e.g. \( or \space.
+        if let Some(c) = self.code[self.pos..].chars().next() {
+            self.pos += c.len_utf8();
+        }
+        self.read_token();
+        FormKind::Atom
+    }
+
+    fn read_token(&mut self) -> &'s str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if is_whitespace(c) || is_terminating(c) {
+                break;
+            }
+            self.pos += 1;
+        }
+        &self.code[start..self.pos]
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                b';' => self.skip_line(),
+                c if is_whitespace(c) => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == b'\n' {
+                break;
+            }
+        }
+    }
+}
+
+fn is_whitespace(c: u8) -> bool {
+    c.is_ascii_whitespace() || c == b','
+}
+
+fn is_terminating(c: u8) -> bool {
+    matches!(
+        c,
+        b'"' | b';'
+            | b'@'
+            | b'^'
+            | b'`'
+            | b'~'
+            | b'\\'
+            | b'('
+            | b')'
+            | b'['
+            | b']'
+            | b'{'
+            | b'}'
+    )
+}
+
+fn is_number(token: &str) -> bool {
+    let digits = token.strip_prefix(&['+', '-'][..]).unwrap_or(token);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+}
+
+/// A reference to another namespace, made from an `ns` form.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Reference {
+    pub namespace: String,
+    pub span: Range<usize>,
+}
+
+/// A namespace declared part way through some code, by an `ns` or `in-ns`
+/// form, and the namespaces that its `ns` form references.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Segment {
+    pub namespace: String,
+    pub references: Vec<Reference>,
+}
+
+/// The first namespace declaration in some code.
+enum Declaration<'s> {
+    Ns(Form<'s>),
+    InNs,
+}
+
+/// Reads the first namespace declaration in `code`, or `None` if `code`
+/// declares no namespace.
+///
+/// The declaration is usually an `ns` form, but may be preceded by comments and
+/// other top level forms. Only top level forms are declarations, so an `ns`
+/// form inside another, such as a `(comment ...)` block of REPL experiments,
+/// is passed over with the form that contains it. An `in-ns` form also declares
+/// a namespace, but it references no other namespaces.
+fn read_declaration(code: &str) -> Result<Option<Declaration<'_>>, ReadError> {
+    let mut reader = Reader::new(code);
+    loop {
+        let form = match reader.next_form()? {
+            Some(form) => form,
+            None => return Ok(None),
+        };
+
+        match form.as_list() {
+            Some([head, name, ..])
+                if head.as_symbol() == Some("ns")
+                    && name.without_meta().as_symbol().is_some() =>
+            {
+                return Ok(Some(Declaration::Ns(form)));
+            }
+            Some([head, _]) if head.as_symbol() == Some("in-ns") => {
+                return Ok(Some(Declaration::InNs));
+            }
+            _ => continue,
+        }
+    }
+}
+
+/// Reads the first namespace declaration in `code` and returns the namespaces
+/// that it references, or `None` if `code` declares no namespace.
+pub(crate) fn read_references(
+    code: &str,
+) -> Result<Option<Vec<Reference>>, ReadError> {
+    let ns_form = match read_declaration(code)? {
+        Some(Declaration::Ns(ns_form)) => ns_form,
+        Some(Declaration::InNs) => return Ok(Some(vec![])),
+        None => return Ok(None),
+    };
+
+    Ok(Some(ns_references(&ns_form)))
+}
+
+/// Reads every top level `ns` and `in-ns` form in `code`, in order. Each
+/// declares the namespace of the code that follows it, up to the next.
+pub(crate) fn read_segments(code: &str) -> Result<Vec<Segment>, ReadError> {
+    let mut reader = Reader::new(code);
+    let mut segments = Vec::new();
+    while let Some(form) = reader.next_form()? {
+        match form.as_list() {
+            Some([head, name, ..]) if head.as_symbol() == Some("ns") => {
+                if let Some(namespace) = name.without_meta().as_symbol() {
+                    segments.push(Segment {
+                        namespace: namespace.to_owned(),
+                        references: ns_references(&form),
+                    });
+                }
+            }
+            Some([head, name]) if head.as_symbol() == Some("in-ns") => {
+                if let Some(namespace) = name.as_quoted_symbol() {
+                    segments.push(Segment {
+                        namespace: namespace.to_owned(),
+                        references: vec![],
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(segments)
+}
+
+/// The namespaces referenced by `ns_form`.
+fn ns_references(ns_form: &Form) -> Vec<Reference> {
+    let clauses = &ns_form.as_list().expect("ns form is a list")[2..];
+
+    let mut references = Vec::new();
+    // Only list clauses can reference namespaces, so a docstring or attribute
+    // map is skipped, whatever it contains.
+    for clause in expand(clauses) {
+        if let Some([kind, libspecs @ ..]) = clause.as_list() {
+            // :refer-clojure, :import, :gen-class etc. reference no namespaces
+            if let Some(
+                ":require" | ":use" | ":require-macros" | ":use-macros",
+            ) = kind.as_keyword()
+            {
+                for libspec in expand(libspecs) {
+                    read_libspec(libspec, &mut references);
+                }
+            }
+        }
+    }
+
+    references
+}
+
+/// Reads the tags in the metadata of the namespace name in the first `ns` form
+/// in `code`, such as `internal` in `(ns ^:internal com.acme.db)`.
+pub(crate) fn read_tags(code: &str) -> Result<Vec<String>, ReadError> {
+    let ns_form = match read_declaration(code)? {
+        Some(Declaration::Ns(ns_form)) => ns_form,
+        Some(Declaration::InNs) | None => return Ok(vec![]),
+    };
+    let name = &ns_form.as_list().expect("ns form is a list")[1];
+
+    Ok(name.tags().into_iter().map(str::to_owned).collect())
+}
+
+/// Reads the name of the namespace declared by the first `ns` form in `code`,
+/// or `None` if `code` has no `ns` form.
+pub(crate) fn read_namespace(code: &str) -> Result<Option<String>, ReadError> {
+    let ns_form = match read_declaration(code)? {
+        Some(Declaration::Ns(ns_form)) => ns_form,
+        Some(Declaration::InNs) | None => return Ok(None),
+    };
+    let name = &ns_form.as_list().expect("ns form is a list")[1];
+
+    Ok(name.without_meta().as_symbol().map(str::to_owned))
 }
 
-fn read(code: &str) -> Vec<Form> {
-    let stack = Vec::new();
-    let mut chars = code.chars_index();
+/// The span of the name in the first top level `ns` form in `code` that
+/// declares `namespace`, or `None` if there is no such form.
+pub(crate) fn read_ns_name(
+    code: &str,
+    namespace: &str,
+) -> Option<Range<usize>> {
+    let mut reader = Reader::new(code);
+    while let Some(form) = reader.next_form().ok()? {
+        if let Some([head, name, ..]) = form.as_list() {
+            let name = name.without_meta();
+            if head.as_symbol() == Some("ns")
+                && name.as_symbol() == Some(namespace)
+            {
+                return Some(name.span.clone());
+            }
+        }
+    }
+
+    None
+}
+
+/// The marker of a comment giving the reason for a reference.
+const REASON_MARKER: &str = "ns-rules-reason:";
+
+/// The reason given for the reference that ends at `end` in `code`, by a
+/// comment on the rest of its line, such as
+/// `[com.acme.db :as db] ; ns-rules-reason: needed for the migration`.
+pub(crate) fn read_reason(code: &str, end: usize) -> Option<&str> {
+    let rest = &code[end..];
+    let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
 
-    while let Some((i, c)) = chars.next() {
+    // A ';' in a string doesn't start a comment
+    let mut in_string = false;
+    let mut escaped = false;
+    let comment_start = line.char_indices().find_map(|(i, c)| {
         match c {
-            '(' => stack.push(Form::Collection(Collection {
-                collection_type: CollectionType::List,
-                start: i,
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ';' if !in_string => return Some(i),
+            _ => {}
+        }
+        None
+    })?;
+
+    let comment = line[comment_start..].trim_start_matches(';').trim();
+    let reason = comment.strip_prefix(REASON_MARKER)?.trim();
+    (!reason.is_empty()).then_some(reason)
+}
+
+fn read_libspec(libspec: &Form, references: &mut Vec<Reference>) {
+    match &libspec.kind {
+        // A libspec, [lib & options]
+        FormKind::Vector(forms) if is_libspec(forms) => references
+            .extend(forms.first().and_then(|lib| reference(None, lib))),
+        // A prefix list, (prefix lib [lib & options] ...), which may also be
+        // written as a vector
+        FormKind::List(forms) | FormKind::Vector(forms) => {
+            let (prefix, libspecs) = match forms.split_first() {
+                Some((prefix, libspecs)) => (prefix, libspecs),
+                None => return,
+            };
+            let prefix = match prefix.as_symbol() {
+                Some(prefix) => prefix,
+                None => return,
+            };
+            for libspec in expand(libspecs) {
+                let lib = match &libspec.kind {
+                    FormKind::Vector(forms) => forms.first(),
+                    _ => Some(libspec),
+                };
+                references
+                    .extend(lib.and_then(|lib| reference(Some(prefix), lib)));
+            }
+        }
+        _ => references.extend(reference(None, libspec)),
+    }
+}
+
+/// Whether a vector is a libspec rather than a prefix list, which is the case
+/// when the lib is followed by options, or nothing at all.
+fn is_libspec(forms: &[Form]) -> bool {
+    matches!(
+        forms.get(1).map(|form| &form.kind),
+        None | Some(FormKind::Keyword(_))
+    )
+}
+
+/// The reference made by a lib, which is either a symbol, or a string naming a
+/// JavaScript module in ClojureScript.
+fn reference(prefix: Option<&str>, lib: &Form) -> Option<Reference> {
+    let name = match lib.kind {
+        FormKind::Symbol(name) | FormKind::Str(name) => name,
+        _ => return None,
+    };
+
+    let namespace = match prefix {
+        Some(prefix) => format!("{}.{}", prefix, name),
+        None => name.to_string(),
+    };
+
+    Some(Reference {
+        namespace,
+        span: lib.span.clone(),
+    })
+}
+
+/// Reads the fully qualified symbols, `namespace/name`, anywhere in `code` as
+/// references to their namespaces, spanning just the namespace. Symbols in
+/// quoted forms and reader conditionals count, as `requiring-resolve` takes a
+/// quoted symbol, but those in metadata don't.
+pub(crate) fn read_qualified_symbols(
+    code: &str,
+) -> Result<Vec<Reference>, ReadError> {
+    let mut reader = Reader::new(code);
+    let mut references = Vec::new();
+    while let Some(form) = reader.next_form()? {
+        read_qualified(&form, &mut references);
+    }
+
+    Ok(references)
+}
+
+fn read_qualified(form: &Form, references: &mut Vec<Reference>) {
+    match &form.kind {
+        FormKind::Symbol(symbol) => match symbol.split_once('/') {
+            Some((namespace, name))
+                if !namespace.is_empty() && !name.is_empty() =>
+            {
+                let start = form.span.start;
+                references.push(Reference {
+                    namespace: namespace.to_owned(),
+                    span: start..start + namespace.len(),
+                });
+            }
+            _ => {}
+        },
+        FormKind::List(forms)
+        | FormKind::Vector(forms)
+        | FormKind::Map(forms)
+        | FormKind::Set(forms)
+        | FormKind::ReaderConditional { forms, .. } => {
+            for form in forms {
+                read_qualified(form, references);
+            }
+        }
+        FormKind::Meta { form, .. } | FormKind::Prefixed(form) => {
+            read_qualified(form, references)
+        }
+        FormKind::Keyword(_) | FormKind::Str(_) | FormKind::Atom => {}
+    }
+}
 
-            })),
-            '[' => stack.push(Vector(i)),
-            '{' => stack.push(Map(i)),
-            '0'..'9' =>
+/// Expands any reader conditionals in `forms`, including the forms for every
+/// platform, since a .cljc file's references apply to all of them.
+fn expand<'f, 's>(forms: &'f [Form<'s>]) -> Vec<&'f Form<'s>> {
+    let mut expanded = Vec::new();
+    for form in forms {
+        match &form.kind {
+            FormKind::ReaderConditional { splicing, forms } => {
+                // Reader conditionals alternate platform keywords and forms
+                for form in forms.iter().skip(1).step_by(2) {
+                    match &form.kind {
+                        FormKind::Vector(spliced) if *splicing => {
+                            expanded.extend(expand(spliced))
+                        }
+                        _ => {
+                            expanded.extend(expand(std::slice::from_ref(form)))
+                        }
+                    }
+                }
+            }
+            _ => expanded.push(form),
         }
     }
+
+    expanded
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn referenced_namespaces(code: &str) -> Vec<String> {
+        read_references(code)
+            .expect("valid code")
+            .expect("ns form")
+            .into_iter()
+            .map(|reference| reference.namespace)
+            .collect()
+    }
+
+    #[test]
+    fn reads_the_reason_given_beside_a_reference() {
+        let code = "(ns a.b\n  (:require [c.d :as d] ; ns-rules-reason: needed for now\n            [e.f :refer [\";\"]] ; just a comment\n            g.h))";
+        let reason = |namespace: &str| {
+            let end = code.find(namespace).unwrap() + namespace.len();
+            read_reason(code, end)
+        };
+
+        assert_eq!(reason("c.d"), Some("needed for now"));
+        assert_eq!(reason("e.f"), None);
+        assert_eq!(reason("g.h"), None);
+    }
+
+    #[test]
+    fn reads_the_declared_namespace() {
+        let code = ";; A comment\n(ns ^:internal com.acme.db (:require a.b))";
+
+        assert_eq!(read_namespace(code), Ok(Some("com.acme.db".into())));
+        assert_eq!(read_namespace("(in-ns 'com.acme.db)"), Ok(None));
+    }
+
+    #[test]
+    fn finds_required_namespaces() {
+        let code = "(ns shipping.entity.port
+                      (:require [shipping.entity.ship :as ship]
+                                shipping.entity.route)
+                      (:use [shipping.entity.contract :only [sign]]))";
+
+        assert_eq!(
+            referenced_namespaces(code),
+            vec![
+                "shipping.entity.ship",
+                "shipping.entity.route",
+                "shipping.entity.contract",
+            ]
+        );
+    }
+
+    #[test]
+    fn reference_spans_cover_the_namespace() {
+        let code = "(ns a.b (:require [c.d :as d]))";
+        let references = read_references(code).unwrap().unwrap();
+
+        assert_eq!(&code[references[0].span.clone()], "c.d");
+    }
+
+    #[test]
+    fn checks_require_macros_like_requires() {
+        let code = include_str!("../tests/fixtures/cljs/require_macros.cljs");
+
+        assert_eq!(
+            referenced_namespaces(code),
+            vec!["shipping.ui.macros", "shipping.entity.ship"]
+        );
+    }
+
+    #[test]
+    fn refer_clojure_is_not_a_reference() {
+        let code = include_str!("../tests/fixtures/cljs/refer_clojure.cljs");
+
+        assert_eq!(referenced_namespaces(code), vec!["shipping.entity.route"]);
+    }
+
+    #[test]
+    fn expands_prefix_lists() {
+        let code = include_str!("../tests/fixtures/prefix_list.clj");
+
+        assert_eq!(
+            referenced_namespaces(code),
+            vec![
+                "com.acme.core",
+                "com.acme.util",
+                "com.acme.db",
+                "clojure.string",
+                "clojure.set",
+                "com.acme.logging",
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_between_requires_are_skipped() {
+        let code = include_str!("../tests/fixtures/commented_require.clj");
+
+        assert_eq!(
+            referenced_namespaces(code),
+            vec![
+                "shipping.entity.voyage",
+                "shipping.entity.port",
+                "shipping.ui.table",
+                "shipping.ui.form",
+                "shipping.ui.format",
+            ]
+        );
+    }
+
+    #[test]
+    fn prefix_list_reference_spans_cover_the_suffix() {
+        let code = "(ns a.b (:require (com.acme [db :as d])))";
+        let references = read_references(code).unwrap().unwrap();
+
+        assert_eq!(references[0].namespace, "com.acme.db");
+        assert_eq!(&code[references[0].span.clone()], "db");
+    }
+
+    #[test]
+    fn string_requires_are_references() {
+        let code = include_str!("../tests/fixtures/cljs/string_requires.cljs");
+
+        assert_eq!(
+            referenced_namespaces(code),
+            vec!["react", "@js-joda/core", "shipping.ui.views"]
+        );
+    }
+
+    #[test]
+    fn includes_references_from_every_platform() {
+        let code = "(ns a.b
+                      (:require #?(:clj [c.jvm] :cljs [c.js])
+                                #?@(:clj [d.jvm [e.jvm]])))";
+
+        assert_eq!(
+            referenced_namespaces(code),
+            vec!["c.jvm", "c.js", "d.jvm", "e.jvm"]
+        );
+    }
+
+    #[test]
+    fn ns_metadata_docstrings_and_gen_class_are_not_references() {
+        let code = include_str!("../tests/fixtures/declarations/gen_class.clj");
+
+        assert_eq!(referenced_namespaces(code), vec!["shipping.entity.ship"]);
+    }
+
+    #[test]
+    fn discarded_requires_are_not_references() {
+        let code = include_str!("../tests/fixtures/discarded_require.clj");
+
+        assert_eq!(referenced_namespaces(code), vec!["shipping.entity.port"]);
+    }
+
+    #[test]
+    fn finds_qualified_symbols_in_code() {
+        let code = r#"(ns a.b (:require [c.d :as d]))
+                      (defn f [] (e.f/g #(h.i/j %) {:k 'l.m/n} ^{:o p.q/r} s))
+                      (def t [d/u / clojure.core// :v.w/x "y.z/a"])"#;
+
+        let references = read_qualified_symbols(code).unwrap();
+
+        let namespaces: Vec<_> = references
+            .iter()
+            .map(|reference| reference.namespace.as_str())
+            .collect();
+        assert_eq!(namespaces, vec!["e.f", "h.i", "l.m", "d", "clojure.core"]);
+        assert_eq!(&code[references[0].span.clone()], "e.f");
+    }
+
+    #[test]
+    fn reads_the_tags_of_the_namespace() {
+        let internal = include_str!("../tests/fixtures/tagged/internal.clj");
+        let public = include_str!("../tests/fixtures/tagged/public.clj");
+
+        assert_eq!(read_tags(internal).unwrap(), vec!["internal", "no-doc"]);
+        assert!(read_tags(public).unwrap().is_empty());
+        assert!(read_tags("(in-ns 'a.b)").unwrap().is_empty());
+    }
+
+    #[test]
+    fn finds_ns_form_after_other_forms() {
+        let code =
+            include_str!("../tests/fixtures/declarations/code_before_ns.clj");
+
+        assert_eq!(
+            referenced_namespaces(code),
+            vec!["com.acme.db", "clojure.string"]
+        );
+    }
+
+    #[test]
+    fn ns_forms_in_comments_are_not_declarations() {
+        let code =
+            include_str!("../tests/fixtures/declarations/license_header.clj");
+
+        assert_eq!(referenced_namespaces(code), vec!["clojure.string"]);
+        assert_eq!(
+            read_namespace(code).unwrap().as_deref(),
+            Some("com.acme.scripts.report")
+        );
+        assert_eq!(read_segments(code).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn in_ns_declares_a_namespace_without_references() {
+        let code = include_str!("../tests/fixtures/declarations/in_ns.clj");
+
+        assert_eq!(read_references(code).unwrap(), Some(vec![]));
+    }
+
+    #[test]
+    fn only_the_first_ns_form_is_read() {
+        let code =
+            include_str!("../tests/fixtures/declarations/multiple_ns.clj");
+
+        assert_eq!(referenced_namespaces(code), vec!["com.acme.db"]);
+    }
+
+    #[test]
+    fn every_declaration_starts_a_segment() {
+        let code =
+            include_str!("../tests/fixtures/declarations/ns_and_in_ns.clj");
+
+        let segments: Vec<_> = read_segments(code)
+            .unwrap()
+            .into_iter()
+            .map(|segment| {
+                let references: Vec<_> = segment
+                    .references
+                    .into_iter()
+                    .map(|reference| reference.namespace)
+                    .collect();
+                (segment.namespace, references)
+            })
+            .collect();
+        assert_eq!(
+            segments,
+            vec![
+                ("com.acme.scratch".to_string(), vec!["com.acme.db".into()]),
+                ("com.acme.user".to_string(), vec![]),
+                (
+                    "com.acme.api".to_string(),
+                    vec!["com.acme.db".into(), "com.acme.http".into()]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_without_ns_form_has_no_references() {
+        let code = include_str!("../tests/fixtures/declarations/no_ns.clj");
+
+        assert_eq!(read_references(code).unwrap(), None);
+        assert_eq!(read_references("(def x 1)").unwrap(), None);
+        assert_eq!(read_references("").unwrap(), None);
+    }
+
+    #[test]
+    fn reports_unbalanced_forms() {
+        assert_eq!(
+            read_references("(ns a.b (:require [c.d))").unwrap_err(),
+            ReadError::Unexpected {
+                delimiter: ')',
+                offset: 22
+            }
+        );
+        assert_eq!(
+            read_references("(ns a.b").unwrap_err(),
+            ReadError::Unclosed {
+                delimiter: '(',
+                offset: 0
+            }
+        );
+    }
 }
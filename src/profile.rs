@@ -0,0 +1,154 @@
+//! Measures the regexes that rules are compiled to, to show whether checking
+//! references against a single regex per rule is worth its cost.
+
+use crate::{ns, ClojureSourceFile, CompiledRule, Encoding, Report};
+use regex::Regex;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// The cost of the regex of a single rule.
+#[derive(Debug)]
+struct Row {
+    pattern: String,
+    /// The length, in bytes, of the regex.
+    length: usize,
+    compile: Duration,
+    /// The number of references matched against the regex.
+    references: usize,
+    matching: Duration,
+}
+
+#[derive(Debug)]
+pub(crate) struct RegexProfile {
+    rows: Vec<Row>,
+}
+
+impl RegexProfile {
+    /// Times the compilation of each rule's regex, and the matching of it
+    /// against the references of every source file the rule governs.
+    pub(crate) fn collect(
+        rules: &[CompiledRule],
+        source_files: &[ClojureSourceFile],
+        encoding: Encoding,
+    ) -> Self {
+        let mut rows: Vec<_> = rules
+            .iter()
+            .map(|rule| {
+                let start = Instant::now();
+                Regex::new(rule.checker.as_str()).expect("valid regex");
+                Row {
+                    pattern: rule.selector.to_string(),
+                    length: rule.checker.as_str().len(),
+                    compile: start.elapsed(),
+                    references: 0,
+                    matching: Duration::ZERO,
+                }
+            })
+            .collect();
+
+        // Files that can't be read have no references to match, and are
+        // reported by the check itself.
+        let mut ignored = Report::new(Default::default());
+        for file in source_files {
+            let i = match rules.iter().position(|rule| rule.matches(file)) {
+                Some(i) => i,
+                None => continue,
+            };
+            let references = match file
+                .read(encoding, &mut ignored)
+                .and_then(|code| ns::read_references(&code).ok().flatten())
+            {
+                Some(references) => references,
+                None => continue,
+            };
+
+            let start = Instant::now();
+            for reference in references.iter() {
+                rules[i].checker.is_match(&reference.namespace);
+            }
+            rows[i].matching += start.elapsed();
+            rows[i].references += references.len();
+        }
+
+        Self { rows }
+    }
+}
+
+impl fmt::Display for RegexProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .rows
+            .iter()
+            .map(|row| row.pattern.len())
+            .chain(Some("rule".len()))
+            .max()
+            .unwrap_or_default();
+
+        writeln!(
+            f,
+            "{:width$}  {:>8}  {:>10}  {:>10}  {:>10}",
+            "rule",
+            "length",
+            "compile",
+            "references",
+            "matching",
+            width = width
+        )?;
+        for row in self.rows.iter() {
+            writeln!(
+                f,
+                "{:width$}  {:>8}  {:>10}  {:>10}  {:>10}",
+                row.pattern,
+                row.length,
+                format!("{:.2?}", row.compile),
+                row.references,
+                format!("{:.2?}", row.matching),
+                width = width
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test::rule, Rule};
+
+    #[test]
+    fn references_are_counted_against_the_rule_that_governs_them() {
+        let files = [ClojureSourceFile {
+            entry: "shipping.ui.porttests/fixtures/discarded_require.clj"
+                .into(),
+            path_start: "shipping.ui.port".len(),
+            tags: vec![],
+        }];
+        let compiled = |pattern: &str| {
+            Rule {
+                allow: vec![],
+                ..rule(pattern)
+            }
+            .compile(&files)
+        };
+        let rules = vec![compiled("billing.*"), compiled("shipping.*")];
+
+        let profile = RegexProfile::collect(&rules, &files, Encoding::Utf8);
+
+        let counts: Vec<_> = profile
+            .rows
+            .iter()
+            .map(|row| (row.pattern.as_str(), row.length, row.references))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![
+                ("billing.*", rules[0].checker.as_str().len(), 0),
+                ("shipping.*", rules[1].checker.as_str().len(), 1),
+            ]
+        );
+        assert!(profile.to_string().starts_with("rule          length"));
+    }
+}
@@ -0,0 +1,1835 @@
+//! Collects the outcome of a run and prints it in the format chosen by the
+//! user.
+
+use crate::{compare::Edge, ClojureSourceFile, Severity};
+use clap::ArgEnum;
+use miette::{
+    Diagnostic, DiagnosticReportPrinter, DiagnosticSnippet,
+    GraphicalReportPrinter, GraphicalTheme, NamedSource, SourceSpan,
+    ThemeStyles,
+};
+use owo_colors::{OwoColorize, Style};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    env, error, fmt, fs,
+    io::{self, IsTerminal, Write},
+    iter, mem,
+    path::Path,
+};
+
+mod codeclimate;
+mod junit;
+mod tap;
+#[cfg(feature = "tui")]
+pub(crate) mod tui;
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Format {
+    /// Violations are shown in context, with colour and box drawing.
+    Graphical,
+    /// One line per violation, sorted by file and position, for comparing
+    /// against a known good output.
+    Stable,
+    /// A single JSON document, for consumption by other tools.
+    Json,
+    /// JUnit XML, in which each namespace governed by a rule is a test case.
+    Junit,
+    /// The Test Anything Protocol, in which each namespace governed by a rule
+    /// is a test point.
+    Tap,
+    /// One JSON object per line for each violation, printed as soon as it is
+    /// found, with the totals on stderr.
+    Ndjson,
+    /// A list of Code Climate issues, which GitLab shows as a code quality
+    /// report.
+    Codeclimate,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ColorChoice {
+    /// Colour is used when printing to a terminal, unless `NO_COLOR` is set.
+    Auto,
+    Always,
+    Never,
+}
+
+/// The colours used to draw violations.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Theme {
+    /// True colour, for terminals with a dark background.
+    Default,
+    /// True colour, for terminals with a light background.
+    Light,
+    /// The terminal's own 16 colour palette.
+    Ansi,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum GroupBy {
+    /// Violations are printed in the order they were found.
+    None,
+    /// Violations are printed under the file that contains them.
+    File,
+}
+
+/// An output stream, to which part of the report can be printed.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn other(self) -> Self {
+        match self {
+            Stream::Stdout => Stream::Stderr,
+            Stream::Stderr => Stream::Stdout,
+        }
+    }
+}
+
+/// The kinds of warning, by which they can be allowed with --allow-warning.
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum WarningCode {
+    /// A file was skipped, as it couldn't be read or declares no namespace.
+    SkippedFile,
+    /// A rule neither restricts nor denies anything.
+    NoEffectRule,
+    /// A rule was defined more than once, and the definitions were merged.
+    DuplicateRule,
+    /// A rule to be disabled doesn't exist.
+    UnknownRule,
+    /// A source directory is an invalid glob, or contains no source files.
+    EmptySrcDir,
+    /// A file that isn't valid UTF-8 was read with replacement characters.
+    InvalidUtf8,
+    /// The qualified symbols of a file couldn't be read by --deep.
+    UnreadableSymbols,
+    /// A file declares more than one namespace, and only the first is checked.
+    MultipleNs,
+    /// The namespace given by --assume-namespace was ignored, as the code on
+    /// stdin declares another.
+    AssumedNamespace,
+    /// A namespace pattern allowed by a rule is already allowed by another.
+    RedundantAllow,
+}
+
+impl WarningCode {
+    fn as_str(self) -> &'static str {
+        match self {
+            WarningCode::SkippedFile => "skipped-file",
+            WarningCode::NoEffectRule => "no-effect-rule",
+            WarningCode::DuplicateRule => "duplicate-rule",
+            WarningCode::UnknownRule => "unknown-rule",
+            WarningCode::EmptySrcDir => "empty-src-dir",
+            WarningCode::InvalidUtf8 => "invalid-utf8",
+            WarningCode::UnreadableSymbols => "unreadable-symbols",
+            WarningCode::MultipleNs => "multiple-ns",
+            WarningCode::AssumedNamespace => "assumed-namespace",
+            WarningCode::RedundantAllow => "redundant-allow",
+        }
+    }
+}
+
+impl fmt::Display for WarningCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A problem that doesn't fail the run, but may mean that it checked less than
+/// was intended.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Warning {
+    pub code: WarningCode,
+    pub message: String,
+    /// The file that the warning is about, if any.
+    pub path: Option<String>,
+}
+
+impl Warning {
+    pub(crate) fn new(code: WarningCode, message: String) -> Self {
+        Self {
+            code,
+            message,
+            path: None,
+        }
+    }
+
+    /// A warning that the file at `path` was skipped.
+    pub(crate) fn skipped(path: &str, message: String) -> Self {
+        Self::new(WarningCode::SkippedFile, message).at(path)
+    }
+
+    pub(crate) fn at(mut self, path: &str) -> Self {
+        self.path = Some(path.to_owned());
+        self
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "code": self.code.as_str(),
+            "message": self.message,
+            "path": self.path,
+        })
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.code)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Verbosity {
+    /// Only violations are printed.
+    Quiet,
+    Normal,
+    /// Violations include the documentation of the rule they break.
+    Verbose,
+}
+
+/// Controls how a `Report` is printed.
+#[derive(Clone, Debug)]
+pub(crate) struct DisplayOptions {
+    pub format: Format,
+    pub color: ColorChoice,
+    pub theme: Theme,
+    pub group_by: GroupBy,
+    pub verbosity: Verbosity,
+    pub summary: bool,
+    /// The number of top offenders listed in the summary.
+    pub top: usize,
+    pub absolute_paths: bool,
+    /// Whether violations explain why they break their rule.
+    pub why: bool,
+    /// Whether failures are printed as soon as they are found, rather than
+    /// with the rest of the report, which only the ndjson format supports.
+    pub stream: bool,
+    /// The kinds of warning that are left out of the report.
+    pub allowed_warnings: Vec<WarningCode>,
+    /// The kinds of warning that fail the run.
+    pub denied_warnings: Vec<WarningCode>,
+    /// Whether the share of references that are allowed is reported.
+    pub compliance: bool,
+    /// The stream to which the violations of rules at `:severity :warn` are
+    /// printed, with the rest of the report on the other, if they are split.
+    pub split_severity: Option<Stream>,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            format: Format::Graphical,
+            color: ColorChoice::Auto,
+            theme: Theme::Default,
+            group_by: GroupBy::None,
+            verbosity: Verbosity::Normal,
+            summary: true,
+            top: 5,
+            absolute_paths: false,
+            why: false,
+            stream: false,
+            allowed_warnings: vec![],
+            denied_warnings: vec![],
+            compliance: false,
+            split_severity: None,
+        }
+    }
+}
+
+impl DisplayOptions {
+    /// The stream to which failures of `severity` are printed, which is stdout
+    /// unless they are split by severity.
+    fn stream_for(&self, severity: Severity) -> Stream {
+        match (self.split_severity, severity) {
+            (None, _) => Stream::Stdout,
+            (Some(warned), Severity::Warn) => warned,
+            (Some(warned), Severity::Error) => warned.other(),
+        }
+    }
+
+    fn use_color(&self) -> bool {
+        match self.color {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                io::stdout().is_terminal()
+                    && !matches!(env::var("NO_COLOR"), Ok(no_color) if no_color != "0")
+            }
+        }
+    }
+
+    /// `style`, or no style at all if colour is not to be used.
+    pub(crate) fn paint(&self, style: Style) -> Style {
+        if self.use_color() {
+            style
+        } else {
+            Style::new()
+        }
+    }
+
+    /// How `path` is shown in the report, relative to the working directory
+    /// unless absolute paths were asked for.
+    pub(crate) fn display_path(&self, path: &str) -> String {
+        let working_dir = match env::current_dir() {
+            Ok(working_dir) => working_dir,
+            Err(_) => return path.to_owned(),
+        };
+
+        let path = Path::new(path);
+        let shown = if self.absolute_paths {
+            working_dir.join(path)
+        } else {
+            path.strip_prefix(&working_dir).unwrap_or(path).to_owned()
+        };
+
+        shown.to_string_lossy().into_owned()
+    }
+
+    /// The style of the source namespace shown above each snippet.
+    pub(crate) fn ns_style(&self) -> Style {
+        let style = match self.theme {
+            Theme::Default => Style::new().truecolor(255, 135, 162),
+            Theme::Light => Style::new().truecolor(135, 0, 95),
+            Theme::Ansi => Style::new().magenta(),
+        };
+
+        self.paint(style)
+    }
+
+    fn theme(&self) -> GraphicalTheme {
+        let mut theme = match self.color {
+            ColorChoice::Auto => GraphicalTheme::default(),
+            ColorChoice::Always => GraphicalTheme::unicode(),
+            ColorChoice::Never => GraphicalTheme::unicode_nocolor(),
+        };
+        theme.styles = if self.use_color() {
+            match self.theme {
+                Theme::Default => ThemeStyles::rgb(),
+                Theme::Light => ThemeStyles {
+                    error: Style::new().truecolor(175, 0, 0),
+                    warning: Style::new().truecolor(135, 95, 0),
+                    advice: Style::new().truecolor(0, 95, 135),
+                    code: Style::new().truecolor(95, 0, 135),
+                    help: Style::new().truecolor(0, 95, 0),
+                    filename: Style::new()
+                        .truecolor(0, 95, 135)
+                        .underline()
+                        .bold(),
+                    highlights: vec![
+                        Style::new().truecolor(175, 0, 0).bold(),
+                        Style::new().truecolor(0, 95, 135).bold(),
+                    ],
+                },
+                Theme::Ansi => ThemeStyles::ansi(),
+            }
+        } else {
+            ThemeStyles::none()
+        };
+
+        theme
+    }
+}
+
+/// What is known of the terminal, and a violation drawn as it would be in the
+/// report, to show whether and how colour will be used.
+#[derive(Debug)]
+pub(crate) struct ColorTest<'o>(pub &'o DisplayOptions);
+
+impl fmt::Display for ColorTest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options = self.0;
+        let variable = |name| match env::var(name) {
+            Ok(value) => format!("{:?}", value),
+            Err(_) => "not set".to_owned(),
+        };
+
+        writeln!(f, "stdout is a terminal: {}", io::stdout().is_terminal())?;
+        writeln!(f, "NO_COLOR: {}", variable("NO_COLOR"))?;
+        writeln!(f, "TERM: {}", variable("TERM"))?;
+        writeln!(
+            f,
+            "--color: {}",
+            format!("{:?}", options.color).to_lowercase()
+        )?;
+        writeln!(
+            f,
+            "--theme: {}",
+            format!("{:?}", options.theme).to_lowercase()
+        )?;
+        writeln!(f, "colour used: {}\n", options.use_color())?;
+
+        let code = "(ns shipping.entity.ship\n  (:require [shipping.service.database :as db]))";
+        let sample = Violation {
+            src: NamedSource::new(
+                "src/shipping/entity/ship.clj",
+                code.to_owned(),
+            ),
+            path: "src/shipping/entity/ship.clj".into(),
+            line: 2,
+            column: 14,
+            src_ns: "shipping.entity.ship".into(),
+            ref_ns: "shipping.service.database".into(),
+            missing: false,
+            rule: 0,
+            severity: Severity::Error,
+            help: Some("this is a sample violation".into()),
+            reason: None,
+            ns_style: options.ns_style(),
+            snippet: (0, code.len()).into(),
+            ref_location: (38, 25).into(),
+        };
+        GraphicalReportPrinter::new_themed(options.theme())
+            .debug(&sample, f)?;
+        f.write_str("\n")
+    }
+}
+
+/// The work done by a single rule.
+#[derive(Clone, Debug)]
+struct RuleSummary {
+    pattern: String,
+    /// The namespaces governed by the rule, and their paths.
+    namespaces: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Report {
+    options: DisplayOptions,
+    rules: Vec<RuleSummary>,
+    violations: Vec<Violation>,
+    /// The namespaces, and their paths, that no rule governs, when every
+    /// namespace is required to be governed.
+    ungoverned: Vec<(String, String)>,
+    /// The path of each file with a forbidden transitive dependency, and the
+    /// chain of references leading to it.
+    transitive: Vec<(String, Vec<String>)>,
+    warnings: Vec<Warning>,
+    /// Violations that have been accepted, by their referencing and referenced
+    /// namespaces, which are left out of the report.
+    accepted: BTreeSet<Edge>,
+    /// The number of violations allowed before the run fails, if any.
+    budget: Option<usize>,
+    files_checked: usize,
+    rules_matched: usize,
+    files_skipped: usize,
+    /// The number of references checked against a rule, whether allowed or
+    /// forbidden.
+    references_checked: usize,
+}
+
+impl Report {
+    pub(crate) fn new(options: DisplayOptions) -> Self {
+        Self {
+            options,
+            rules: vec![],
+            violations: vec![],
+            ungoverned: vec![],
+            transitive: vec![],
+            warnings: vec![],
+            accepted: BTreeSet::new(),
+            budget: None,
+            files_checked: 0,
+            rules_matched: 0,
+            files_skipped: 0,
+            references_checked: 0,
+        }
+    }
+
+    pub(crate) fn options(&self) -> &DisplayOptions {
+        &self.options
+    }
+
+    /// An empty report in which to collect the results of checking a single
+    /// file, away from the rest, until they can be added with `append`.
+    pub(crate) fn buffer(options: &DisplayOptions) -> Self {
+        Self::new(DisplayOptions {
+            stream: false,
+            ..options.clone()
+        })
+    }
+
+    /// Adds the violations and warnings collected in `buffer`, streaming them
+    /// as if they had been found by this report.
+    pub(crate) fn append(&mut self, buffer: Self) {
+        for violation in buffer.violations {
+            self.violation(violation);
+        }
+        self.warnings.extend(buffer.warnings);
+        self.files_skipped += buffer.files_skipped;
+        self.references_checked += buffer.references_checked;
+    }
+
+    /// The violations found, in the order in which they were found.
+    pub(crate) fn into_violations(self) -> Vec<Violation> {
+        self.violations
+    }
+
+    /// Moves the violations of rules at `:severity :warn` into a report of
+    /// their own, to be printed apart from the rest. The counts of the files
+    /// and references checked are left to this report.
+    pub(crate) fn split_warned(&mut self) -> Self {
+        let (warned, failed) = mem::take(&mut self.violations)
+            .into_iter()
+            .partition(|violation| violation.severity == Severity::Warn);
+        self.violations = failed;
+
+        Self {
+            rules: self.rules.clone(),
+            violations: warned,
+            ..Self::new(DisplayOptions {
+                summary: false,
+                compliance: false,
+                ..self.options.clone()
+            })
+        }
+    }
+
+    pub(crate) fn candidate_files(&mut self, files: &[ClojureSourceFile]) {
+        self.files_checked = files.len();
+    }
+
+    /// Writes each JUnit test suite to a file of its own in `dir`, which is
+    /// created if need be, returning the number of files written.
+    pub(crate) fn write_junit_files(&self, dir: &Path) -> io::Result<usize> {
+        fs::create_dir_all(dir)?;
+        let files = junit::files(self);
+        for (name, xml) in files.iter() {
+            fs::write(dir.join(name), xml)?;
+        }
+
+        Ok(files.len())
+    }
+
+    /// Counts the references of a namespace that were checked against its
+    /// rule.
+    pub(crate) fn references_checked(&mut self, references: usize) {
+        self.references_checked += references;
+    }
+
+    /// Counts a skipped file, with the warning that says why.
+    pub(crate) fn file_skipped(&mut self, warning: Warning) {
+        self.warn(warning);
+        self.files_skipped += 1;
+    }
+
+    pub(crate) fn violation(&mut self, violation: Violation) {
+        let edge = (violation.src_ns.clone(), violation.ref_ns.clone());
+        if self.accepted.contains(&edge) {
+            return;
+        }
+        self.stream(violation.severity, || {
+            let fingerprint = self.fingerprint(&violation);
+            ndjson("violation", violation_json(&violation, fingerprint))
+        });
+        self.violations.push(violation);
+    }
+
+    /// Records the patterns of the rules to be applied, in order, so that
+    /// matches and violations can be attributed to them by index.
+    pub(crate) fn rules(&mut self, patterns: impl Iterator<Item = String>) {
+        self.rules = patterns
+            .map(|pattern| RuleSummary {
+                pattern,
+                namespaces: vec![],
+            })
+            .collect();
+    }
+
+    pub(crate) fn rule_matched(
+        &mut self,
+        rule: usize,
+        file: &ClojureSourceFile,
+    ) {
+        let path = self.options.display_path(file.path());
+        self.rules_matched += 1;
+        self.rules[rule]
+            .namespaces
+            .push((file.namespace().to_owned(), path));
+    }
+
+    /// Records `warning`, unless its kind has been allowed.
+    pub(crate) fn warn(&mut self, warning: Warning) {
+        if !self.options.allowed_warnings.contains(&warning.code) {
+            self.warnings.push(warning);
+        }
+    }
+
+    pub(crate) fn ungoverned(&mut self, file: &ClojureSourceFile) {
+        let path = self.options.display_path(file.path());
+        let namespace = file.namespace().to_owned();
+        self.stream(Severity::Error, || {
+            ndjson("ungoverned", ungoverned_json(&namespace, &path))
+        });
+        self.ungoverned.push((namespace, path));
+    }
+
+    pub(crate) fn transitive_violation(
+        &mut self,
+        file: &ClojureSourceFile,
+        chain: Vec<String>,
+    ) {
+        let path = self.options.display_path(file.path());
+        self.stream(Severity::Error, || {
+            ndjson("transitive", transitive_json(&path, &chain))
+        });
+        self.transitive.push((path, chain));
+    }
+
+    /// The fingerprint of `violation`, from the pattern of the rule it breaks.
+    fn fingerprint(&self, violation: &Violation) -> String {
+        violation.fingerprint(&self.rules[violation.rule].pattern)
+    }
+
+    /// Prints the line made by `line`, for a failure of `severity`, straight
+    /// away, if failures are streamed, so that they can be consumed before the
+    /// run has finished.
+    fn stream(
+        &self,
+        severity: Severity,
+        line: impl FnOnce() -> serde_json::Value,
+    ) {
+        if self.options.stream {
+            let mut out: Box<dyn Write> =
+                match self.options.stream_for(severity) {
+                    Stream::Stdout => Box::new(io::stdout().lock()),
+                    Stream::Stderr => Box::new(io::stderr().lock()),
+                };
+            // A consumer that has stopped reading is no reason to stop the run
+            let _ = writeln!(out, "{}", line()).and_then(|_| out.flush());
+        }
+    }
+
+    /// Allows up to `budget` violations, including transitive violations,
+    /// before the run fails, so that they can be fixed gradually.
+    pub(crate) fn set_budget(&mut self, budget: usize) {
+        self.budget = Some(budget);
+    }
+
+    pub(crate) fn exit_status(&self) -> i32 {
+        if !self.failed() || self.within_budget() {
+            0
+        } else {
+            1
+        }
+    }
+
+    /// Whether the only failures are violations, and there are no more than
+    /// the budget allows. Ungoverned namespaces and denied warnings always
+    /// fail.
+    fn within_budget(&self) -> bool {
+        self.ungoverned.is_empty()
+            && self.denied_warnings() == 0
+            && self
+                .budget
+                .is_some_and(|budget| self.failing_count() <= budget)
+    }
+
+    /// The number of violations against the budget, if there is one.
+    fn budget_line(&self) -> Option<String> {
+        let budget = self.budget?;
+        let verdict = if self.failing_count() <= budget {
+            "within"
+        } else {
+            "over"
+        };
+        Some(format!(
+            "{} violation{}, {} the budget of {}",
+            self.failing_count(),
+            self.failing_count().pluralise(),
+            verdict,
+            budget
+        ))
+    }
+
+    /// The number of references checked that are allowed, and the number that
+    /// are forbidden. Accepted violations count as allowed.
+    fn compliance(&self) -> (usize, usize) {
+        // Missing references were never made, so aren't among those checked
+        let forbidden = self.violations.iter().filter(|v| !v.missing).count();
+        (self.references_checked.saturating_sub(forbidden), forbidden)
+    }
+
+    /// The percentage of the references checked that are allowed, which is
+    /// 100 when there were none.
+    fn compliance_percentage(&self) -> f64 {
+        let (allowed, _) = self.compliance();
+        if self.references_checked == 0 {
+            100.0
+        } else {
+            allowed as f64 * 100.0 / self.references_checked as f64
+        }
+    }
+
+    fn compliance_line(&self) -> Option<String> {
+        if !self.options.compliance {
+            return None;
+        }
+        let (allowed, forbidden) = self.compliance();
+        Some(format!(
+            "{:.1}% compliant, {} of {} reference{} allowed, {} forbidden",
+            self.compliance_percentage(),
+            allowed,
+            self.references_checked,
+            self.references_checked.pluralise(),
+            forbidden
+        ))
+    }
+
+    /// Whether there are failures other than the violations of rules at
+    /// `:severity :warn`, which are reported without failing the run.
+    fn failed(&self) -> bool {
+        self.failing_count() > 0
+            || !self.ungoverned.is_empty()
+            || self.denied_warnings() > 0
+    }
+
+    /// The number of violations, including transitive violations, that fail
+    /// the run.
+    fn failing_count(&self) -> usize {
+        self.violations.len() - self.warned_count() + self.transitive.len()
+    }
+
+    /// The number of violations of rules at `:severity :warn`, which are
+    /// reported without failing the run.
+    fn warned_count(&self) -> usize {
+        self.violations
+            .iter()
+            .filter(|violation| violation.severity == Severity::Warn)
+            .count()
+    }
+
+    /// The number of warnings of the kinds that fail the run.
+    fn denied_warnings(&self) -> usize {
+        self.warnings
+            .iter()
+            .filter(|warning| {
+                self.options.denied_warnings.contains(&warning.code)
+            })
+            .count()
+    }
+
+    /// A line describing the namespaces found and the rules to be applied.
+    pub(crate) fn banner(&self) -> String {
+        format!(
+            "Checking {} namespace{} against {} rule{}",
+            self.files_checked,
+            self.files_checked.pluralise(),
+            self.rules.len(),
+            self.rules.len().pluralise()
+        )
+    }
+
+    /// The number of violations, including transitive violations.
+    pub(crate) fn violation_count(&self) -> usize {
+        self.violations.len() + self.transitive.len()
+    }
+
+    pub(crate) fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// The referencing and referenced namespaces of each violation.
+    pub(crate) fn violation_edges(
+        &self,
+    ) -> impl Iterator<Item = (String, String)> + '_ {
+        self.violations.iter().map(|violation| {
+            (violation.src_ns.clone(), violation.ref_ns.clone())
+        })
+    }
+
+    /// Removes the violations whose referencing and referenced namespaces are
+    /// in `accepted`, now and as they are found.
+    pub(crate) fn accept(&mut self, accepted: &BTreeSet<Edge>) {
+        self.accepted.extend(accepted.iter().cloned());
+        self.violations.retain(|violation| {
+            !accepted
+                .contains(&(violation.src_ns.clone(), violation.ref_ns.clone()))
+        });
+    }
+
+    /// The violations, ordered by file and position.
+    fn sorted_violations(&self) -> Vec<&Violation> {
+        let mut violations: Vec<&Violation> = self.violations.iter().collect();
+        violations.sort_by(|a, b| {
+            (&a.path, a.ref_location.offset())
+                .cmp(&(&b.path, b.ref_location.offset()))
+        });
+
+        violations
+    }
+
+    /// The namespaces governed by, and violations of, each rule.
+    fn rule_summaries(&self) -> impl Iterator<Item = (&RuleSummary, usize)> {
+        self.rules.iter().enumerate().map(move |(i, rule)| {
+            let violations =
+                self.violations.iter().filter(|v| v.rule == i).count();
+            (rule, violations)
+        })
+    }
+
+    /// The rules with the most violations and the namespaces with the most
+    /// forbidden references, most first, limited to the top offenders.
+    fn top_offenders(&self) -> (Vec<Offender<'_>>, Vec<Offender<'_>>) {
+        let rules = self
+            .rule_summaries()
+            .map(|(rule, violations)| (rule.pattern.as_str(), violations))
+            .collect();
+        let mut namespaces = BTreeMap::new();
+        for violation in self.violations.iter() {
+            *namespaces.entry(violation.src_ns.as_str()).or_default() += 1;
+        }
+
+        let top = self.options.top;
+        (
+            most_violations(rules, top),
+            most_violations(namespaces.into_iter().collect(), top),
+        )
+    }
+
+    fn show_warnings(&self) -> bool {
+        self.options.verbosity != Verbosity::Quiet
+    }
+}
+
+impl Report {
+    fn fmt_stable(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.show_warnings() {
+            for warning in self.warnings.iter() {
+                writeln!(f, "WARNING {} {}", warning.code, warning.message)?;
+            }
+        }
+
+        for (namespace, path) in self.ungoverned.iter() {
+            writeln!(f, "UNGOVERNED {} {}", path, namespace)?;
+        }
+
+        for violation in self.sorted_violations() {
+            writeln!(
+                f,
+                "{} {} {}:{} {} -> {}",
+                if violation.missing {
+                    "MISSING"
+                } else {
+                    "VIOLATION"
+                },
+                violation.path,
+                violation.line,
+                violation.column,
+                violation.src_ns,
+                violation.ref_ns
+            )?;
+        }
+
+        for (path, chain) in self.transitive.iter() {
+            writeln!(f, "TRANSITIVE {} {}", path, chain.join(" -> "))?;
+        }
+
+        Ok(())
+    }
+
+    fn fmt_json(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let violations: Vec<_> = self
+            .violations
+            .iter()
+            .map(|violation| {
+                violation_json(violation, self.fingerprint(violation))
+            })
+            .collect();
+        let rules: Vec<_> = self
+            .rule_summaries()
+            .map(|(rule, violations)| {
+                json!({
+                    "pattern": rule.pattern,
+                    "namespaces": rule.namespaces.len(),
+                    "violations": violations,
+                })
+            })
+            .collect();
+        let (top_rules, top_namespaces) = self.top_offenders();
+        let mut report = json!({
+            "violations": violations,
+            "rules": rules,
+            "top-offenders": {
+                "rules": top_rules
+                    .iter()
+                    .map(|(pattern, violations)| {
+                        json!({ "pattern": pattern, "violations": violations })
+                    })
+                    .collect::<Vec<_>>(),
+                "namespaces": top_namespaces
+                    .iter()
+                    .map(|(namespace, violations)| {
+                        json!({
+                            "namespace": namespace,
+                            "violations": violations,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            },
+            "ungoverned": self
+                .ungoverned
+                .iter()
+                .map(|(namespace, path)| ungoverned_json(namespace, path))
+                .collect::<Vec<_>>(),
+            "transitive": self
+                .transitive
+                .iter()
+                .map(|(path, chain)| transitive_json(path, chain))
+                .collect::<Vec<_>>(),
+            "warnings": self
+                .warnings
+                .iter()
+                .map(Warning::to_json)
+                .collect::<Vec<_>>(),
+            "files-checked": self.files_checked,
+            "namespaces-matched": self.rules_matched,
+            "files-skipped": self.files_skipped,
+            "budget": self.budget,
+        });
+        if self.options.compliance {
+            let (allowed, forbidden) = self.compliance();
+            report["compliance"] = json!({
+                "references": self.references_checked,
+                "allowed": allowed,
+                "forbidden": forbidden,
+                "percentage": self.compliance_percentage(),
+            });
+        }
+
+        writeln!(f, "{:#}", report)
+    }
+
+    /// Prints each failure as a line of JSON, unless they were streamed as
+    /// they were found.
+    fn fmt_ndjson(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.options.stream {
+            return Ok(());
+        }
+
+        for violation in self.violations.iter() {
+            let json = violation_json(violation, self.fingerprint(violation));
+            writeln!(f, "{}", ndjson("violation", json))?;
+        }
+        for (namespace, path) in self.ungoverned.iter() {
+            let json = ungoverned_json(namespace, path);
+            writeln!(f, "{}", ndjson("ungoverned", json))?;
+        }
+        for (path, chain) in self.transitive.iter() {
+            let json = transitive_json(path, chain);
+            writeln!(f, "{}", ndjson("transitive", json))?;
+        }
+
+        Ok(())
+    }
+
+    /// The warnings and totals, which the ndjson format prints to stderr, so
+    /// as to keep stdout to one failure per line.
+    pub(crate) fn summary(&self) -> String {
+        let mut summary = String::new();
+        if self.show_warnings() {
+            for warning in self.warnings.iter() {
+                summary.push_str(&format!("warning: {}\n", warning));
+            }
+        }
+        if self.options.summary && self.show_warnings() {
+            summary.push_str(&format!(
+                "{} violation{}, {} file{} checked, {} file{} skipped\n",
+                self.violation_count(),
+                self.violation_count().pluralise(),
+                self.files_checked,
+                self.files_checked.pluralise(),
+                self.files_skipped,
+                self.files_skipped.pluralise(),
+            ));
+        }
+        if let Some(budget) = self.budget_line() {
+            summary.push_str(&format!("{}\n", budget));
+        }
+        if let Some(compliance) = self.compliance_line() {
+            summary.push_str(&format!("{}\n", compliance));
+        }
+
+        summary
+    }
+
+    fn fmt_graphical(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.show_warnings() && !self.warnings.is_empty() {
+            f.write_str("Warnings:\n")?;
+            for warning in self.warnings.iter() {
+                writeln!(f, "  {}", warning)?;
+            }
+            f.write_str("\n")?;
+        }
+
+        let printer = GraphicalReportPrinter::new_themed(self.options.theme());
+        match self.options.group_by {
+            GroupBy::None => {
+                for violation in self.violations.iter() {
+                    printer.debug(violation, f)?;
+                    f.write_str("\n\n")?;
+                }
+            }
+            GroupBy::File => {
+                let mut current_path = None;
+                for violation in self.sorted_violations() {
+                    if current_path != Some(&violation.path) {
+                        let style = self.options.paint(Style::new().bold());
+                        writeln!(f, "{}\n", violation.path.style(style))?;
+                        current_path = Some(&violation.path);
+                    }
+                    printer.debug(violation, f)?;
+                    f.write_str("\n\n")?;
+                }
+            }
+        }
+
+        if !self.ungoverned.is_empty() {
+            f.write_str("Namespaces not governed by any rule:\n")?;
+            for (namespace, path) in self.ungoverned.iter() {
+                writeln!(f, "  {} ({})", namespace, path)?;
+            }
+            f.write_str("\n")?;
+        }
+
+        if !self.transitive.is_empty() {
+            f.write_str("Forbidden transitive dependencies:\n")?;
+            for (path, chain) in self.transitive.iter() {
+                writeln!(f, "  {} ({})", chain.join(" -> "), path)?;
+            }
+            f.write_str("\n")?;
+        }
+
+        let warned = self.warned_count();
+        let warned_line =
+            format!("{} warned rule violation{}", warned, warned.pluralise());
+        if !self.failed() {
+            let style = self.options.paint(Style::new().green());
+            let passed = if warned > 0 {
+                format!("All checks passed, with {}", warned_line)
+            } else {
+                "All checks passed".to_string()
+            };
+            writeln!(f, "{}", passed.style(style))?;
+        } else {
+            let style = self.options.paint(Style::new().red());
+            let mut failures = vec![];
+            let failing = self.violations.len() - warned;
+            if failing > 0 {
+                failures.push(format!(
+                    "{} rule violation{}",
+                    failing,
+                    failing.pluralise()
+                ));
+            }
+            if !self.ungoverned.is_empty() {
+                failures.push(format!(
+                    "{} ungoverned namespace{}",
+                    self.ungoverned.len(),
+                    self.ungoverned.len().pluralise()
+                ));
+            }
+            if !self.transitive.is_empty() {
+                failures.push(format!(
+                    "{} transitive violation{}",
+                    self.transitive.len(),
+                    self.transitive.len().pluralise()
+                ));
+            }
+            let denied_warnings = self.denied_warnings();
+            if denied_warnings > 0 {
+                failures.push(format!(
+                    "{} denied warning{}",
+                    denied_warnings,
+                    denied_warnings.pluralise()
+                ));
+            }
+            if warned > 0 {
+                failures.push(warned_line);
+            }
+            writeln!(
+                f,
+                "{}",
+                format!("Found {}", failures.join(" and ")).style(style)
+            )?;
+        }
+        if let Some(budget) = self.budget_line() {
+            let colour = if self.within_budget() {
+                Style::new().green()
+            } else {
+                Style::new().red()
+            };
+            writeln!(f, "{}", budget.style(self.options.paint(colour)))?;
+        }
+        if let Some(compliance) = self.compliance_line() {
+            writeln!(f, "{}", compliance)?;
+        }
+        if self.options.summary && self.options.verbosity != Verbosity::Quiet {
+            writeln!(
+                f,
+                "{:3} file{} checked\n\
+                 {:3} namespace{} matched a rule\n\
+                 {:3} warning{}\n\
+                 {:3} file{} skipped\n",
+                self.files_checked,
+                self.files_checked.pluralise(),
+                self.rules_matched,
+                self.rules_matched.pluralise(),
+                self.warnings.len(),
+                self.warnings.len().pluralise(),
+                self.files_skipped,
+                self.files_skipped.pluralise(),
+            )?;
+
+            if !self.rules.is_empty() {
+                writeln!(f, "namespaces  violations  rule")?;
+                for (rule, violations) in self.rule_summaries() {
+                    writeln!(
+                        f,
+                        "{:10}  {:10}  {}",
+                        rule.namespaces.len(),
+                        violations,
+                        rule.pattern
+                    )?;
+                }
+                f.write_str("\n")?;
+            }
+
+            let (top_rules, top_namespaces) = self.top_offenders();
+            let sections = [
+                ("Rules with the most violations:", top_rules),
+                ("Namespaces with the most violations:", top_namespaces),
+            ];
+            for (heading, offenders) in sections {
+                if offenders.is_empty() {
+                    continue;
+                }
+                writeln!(f, "{}", heading)?;
+                for (name, violations) in offenders {
+                    writeln!(f, "{:10}  {}", violations, name)?;
+                }
+                f.write_str("\n")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.options.format {
+            Format::Graphical => self.fmt_graphical(f),
+            Format::Stable => self.fmt_stable(f),
+            Format::Json => self.fmt_json(f),
+            Format::Junit => junit::fmt(self, f),
+            Format::Tap => tap::fmt(self, f),
+            Format::Ndjson => self.fmt_ndjson(f),
+            Format::Codeclimate => codeclimate::fmt(self, f),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Violation {
+    pub src: NamedSource,
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub src_ns: String,
+    /// The namespace referenced or, when `missing`, the pattern of the
+    /// namespaces of which one must be.
+    pub ref_ns: String,
+    /// Whether the rule requires a reference that the namespace doesn't make,
+    /// with `ref_location` at the name in its `ns` form, rather than forbidding
+    /// one that it does.
+    pub missing: bool,
+    /// The index of the rule that was broken.
+    pub rule: usize,
+    /// The severity of the rule, by which the violation may not fail the run.
+    pub severity: Severity,
+    pub help: Option<String>,
+    /// The reason given for the reference in a comment beside it, as it may
+    /// be a known exception.
+    pub reason: Option<String>,
+    /// The style of the source namespace, shown above the snippet.
+    pub ns_style: Style,
+    pub snippet: SourceSpan,
+    pub ref_location: SourceSpan,
+}
+
+impl Violation {
+    /// An identifier for the violation that stays the same from one run to
+    /// the next, made from the namespaces and the `pattern` of the rule that
+    /// was broken. The position of the reference is left out, so that editing
+    /// the code around it doesn't change the fingerprint.
+    pub(crate) fn fingerprint(&self, pattern: &str) -> String {
+        fingerprint(&[&self.src_ns, &self.ref_ns, pattern])
+    }
+
+    /// The name of the kind of violation, as used by the formats that tell
+    /// kinds of failure apart.
+    pub(crate) fn check_name(&self) -> &'static str {
+        if self.missing {
+            "missing_required_reference"
+        } else {
+            "namespace_rule_violation"
+        }
+    }
+
+    /// The help for the violation, preceded by the reason given for the
+    /// reference, if there is one.
+    pub(crate) fn help_with_reason(&self) -> Option<String> {
+        match (&self.reason, &self.help) {
+            (Some(reason), Some(help)) => {
+                Some(format!("reason given: {}\n{}", reason, help))
+            }
+            (Some(reason), None) => Some(format!("reason given: {}", reason)),
+            (None, help) => help.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.missing {
+            write!(
+                f,
+                "'{}' is missing required reference '{}'",
+                self.src_ns, self.ref_ns
+            )
+        } else {
+            write!(
+                f,
+                "'{}' is not allowed to reference '{}'",
+                self.src_ns, self.ref_ns
+            )
+        }
+    }
+}
+
+impl error::Error for Violation {}
+
+impl Diagnostic for Violation {
+    fn code<'a>(&'a self) -> Box<dyn fmt::Display + 'a> {
+        Box::new(self.check_name())
+    }
+
+    fn severity(&self) -> Option<miette::Severity> {
+        Some(match self.severity {
+            Severity::Error => miette::Severity::Error,
+            Severity::Warn => miette::Severity::Warning,
+        })
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.help_with_reason()
+            .map(|help| Box::new(help) as Box<dyn fmt::Display>)
+    }
+
+    fn snippets<'a>(
+        &'a self,
+    ) -> Option<Box<dyn Iterator<Item = DiagnosticSnippet<'a>> + 'a>> {
+        let label = if self.missing {
+            format!("this namespace must reference '{}'", self.ref_ns)
+        } else {
+            "this reference is not allowed".to_string()
+        };
+        let snippet = DiagnosticSnippet {
+            message: Some(self.src_ns.style(self.ns_style).to_string()),
+            source: &self.src,
+            context: self.snippet.clone(),
+            highlights: Some(vec![(Some(label), self.ref_location.clone())]),
+        };
+
+        Some(Box::new(iter::once(snippet)))
+    }
+}
+
+fn violation_json(
+    violation: &Violation,
+    fingerprint: String,
+) -> serde_json::Value {
+    let mut json = json!({
+        "path": violation.path,
+        "line": violation.line,
+        "column": violation.column,
+        "namespace": violation.src_ns,
+        "reference": violation.ref_ns,
+        "fingerprint": fingerprint,
+    });
+    if violation.missing {
+        json["missing"] = true.into();
+    }
+    if violation.severity == Severity::Warn {
+        json["severity"] = violation.severity.to_string().into();
+    }
+    if let Some(reason) = &violation.reason {
+        json["reason"] = reason.as_str().into();
+    }
+
+    json
+}
+
+fn ungoverned_json(namespace: &str, path: &str) -> serde_json::Value {
+    json!({ "namespace": namespace, "path": path })
+}
+
+fn transitive_json(path: &str, chain: &[String]) -> serde_json::Value {
+    json!({ "path": path, "chain": chain })
+}
+
+/// `json` with its `type`, so that the kinds of failure can be told apart
+/// when they are printed one per line.
+fn ndjson(kind: &str, mut json: serde_json::Value) -> serde_json::Value {
+    json["type"] = kind.into();
+    json
+}
+
+/// A rule pattern or namespace, and the number of violations attributed to it.
+type Offender<'a> = (&'a str, usize);
+
+/// The `top` offenders with the most violations, most first, leaving out those
+/// without any.
+fn most_violations(
+    mut counts: Vec<Offender<'_>>,
+    top: usize,
+) -> Vec<Offender<'_>> {
+    counts.retain(|(_, count)| *count > 0);
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    counts.truncate(top);
+    counts
+}
+
+/// A 64-bit FNV-1a hash of `parts`, in hex. Unlike the hashers of the standard
+/// library, it is the same in every build, so a fingerprint doesn't change
+/// when ns-rules is upgraded.
+pub(crate) fn fingerprint(parts: &[&str]) -> String {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    // Parts are separated by a byte that no namespace or path contains, so
+    // that moving text from one part to the next changes the fingerprint.
+    let bytes = parts.iter().enumerate().flat_map(|(i, part)| {
+        (i > 0).then_some(0).into_iter().chain(part.bytes())
+    });
+    let hash = bytes.fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    });
+
+    format!("{:016x}", hash)
+}
+
+pub(crate) trait Pluralise {
+    fn pluralise(&self) -> &str;
+}
+
+impl Pluralise for usize {
+    fn pluralise(&self) -> &str {
+        if *self == 1 {
+            ""
+        } else {
+            "s"
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn source_file(namespace: &str, path: &str) -> ClojureSourceFile {
+        ClojureSourceFile {
+            entry: format!("{}{}", namespace, path),
+            path_start: namespace.len(),
+            tags: vec![],
+        }
+    }
+
+    pub(super) fn violation(rule: usize) -> Violation {
+        Violation {
+            src: NamedSource::new(
+                "src/a/b.clj",
+                "(ns a.b (:require c.d))".to_string(),
+            ),
+            path: "src/a/b.clj".into(),
+            line: 1,
+            column: 19,
+            src_ns: "a.b".into(),
+            ref_ns: "c.d".into(),
+            missing: false,
+            rule,
+            severity: Severity::Error,
+            help: None,
+            reason: None,
+            ns_style: Style::new(),
+            snippet: (0, 23).into(),
+            ref_location: (18, 3).into(),
+        }
+    }
+
+    #[test]
+    fn color_test_draws_a_sample_violation() {
+        let options = DisplayOptions {
+            color: ColorChoice::Never,
+            ..DisplayOptions::default()
+        };
+
+        let shown = ColorTest(&options).to_string();
+
+        assert!(shown.contains("--color: never\n"));
+        assert!(shown.contains("colour used: false\n"));
+        assert!(shown.contains("[shipping.service.database :as db]"));
+        assert!(!shown.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn matches_and_violations_are_attributed_to_rules() {
+        let mut report = Report::new(DisplayOptions::default());
+        report.rules(vec!["a.*".into(), "b.*".into()].into_iter());
+        report.rule_matched(0, &source_file("a.b", "src/a/b.clj"));
+        report.rule_matched(0, &source_file("a.c", "src/a/c.clj"));
+        report.violation(violation(0));
+
+        let summaries: Vec<_> = report
+            .rule_summaries()
+            .map(|(rule, violations)| {
+                (rule.pattern.as_str(), rule.namespaces.len(), violations)
+            })
+            .collect();
+
+        assert_eq!(summaries, vec![("a.*", 2, 1), ("b.*", 0, 0)]);
+    }
+
+    #[test]
+    fn banner_counts_namespaces_and_rules() {
+        let mut report = Report::new(DisplayOptions::default());
+        report.candidate_files(&[source_file("a.b", "src/a/b.clj")]);
+        report.rules(vec!["a.*".into(), "b.*".into()].into_iter());
+
+        assert_eq!(report.banner(), "Checking 1 namespace against 2 rules");
+    }
+
+    #[test]
+    fn violations_are_counted_with_transitive_violations() {
+        let mut report = Report::new(DisplayOptions::default());
+        report.rules(vec!["a.*".into()].into_iter());
+        report.violation(violation(0));
+        report.transitive_violation(
+            &source_file("a.b", "src/a/b.clj"),
+            vec!["a.b".into(), "c.d".into(), "e.f".into()],
+        );
+        report.ungoverned(&source_file("g.h", "src/g/h.clj"));
+
+        assert_eq!(report.violation_count(), 2);
+    }
+
+    #[test]
+    fn violations_within_the_budget_pass() {
+        let mut report = Report::new(DisplayOptions {
+            color: ColorChoice::Never,
+            ..DisplayOptions::default()
+        });
+        report.rules(vec!["a.*".into()].into_iter());
+        report.set_budget(2);
+        report.violation(violation(0));
+        report.violation(violation(0));
+
+        assert_eq!(report.exit_status(), 0);
+        assert!(report
+            .to_string()
+            .contains("2 violations, within the budget of 2"));
+
+        report.violation(violation(0));
+
+        assert_eq!(report.exit_status(), 1);
+        assert!(report
+            .to_string()
+            .contains("3 violations, over the budget of 2"));
+    }
+
+    #[test]
+    fn warned_violations_do_not_fail() {
+        let mut report = Report::new(DisplayOptions {
+            color: ColorChoice::Never,
+            ..DisplayOptions::default()
+        });
+        report.rules(vec!["a.*".into()].into_iter());
+        report.violation(Violation {
+            severity: Severity::Warn,
+            ..violation(0)
+        });
+
+        assert_eq!(report.exit_status(), 0);
+        assert!(report
+            .to_string()
+            .contains("All checks passed, with 1 warned rule violation\n"));
+
+        report.violation(violation(0));
+
+        assert_eq!(report.exit_status(), 1);
+        assert!(report
+            .to_string()
+            .contains("Found 1 rule violation and 1 warned rule violation\n"));
+        let warned = report.split_warned();
+        assert_eq!(warned.violations.len(), 1);
+        assert_eq!(warned.violations[0].severity, Severity::Warn);
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].severity, Severity::Error);
+    }
+
+    #[test]
+    fn ungoverned_namespaces_fail_within_the_budget() {
+        let mut report = Report::new(DisplayOptions::default());
+        report.set_budget(5);
+        report.ungoverned(&source_file("g.h", "src/g/h.clj"));
+
+        assert_eq!(report.exit_status(), 1);
+    }
+
+    #[test]
+    fn warnings_of_allowed_kinds_are_left_out() {
+        let mut report = Report::new(DisplayOptions {
+            allowed_warnings: vec![WarningCode::SkippedFile],
+            ..DisplayOptions::default()
+        });
+        report.file_skipped(Warning::skipped(
+            "src/a/b.clj",
+            "src/a/b.clj is generated, skipping".into(),
+        ));
+        report.warn(Warning::new(
+            WarningCode::NoEffectRule,
+            "the rule for 'a.*' has no effect".into(),
+        ));
+
+        assert_eq!(report.warnings().len(), 1);
+        assert_eq!(report.warnings()[0].code, WarningCode::NoEffectRule);
+        assert_eq!(report.files_skipped, 1);
+    }
+
+    #[test]
+    fn warnings_of_denied_kinds_fail() {
+        let mut report = Report::new(DisplayOptions {
+            color: ColorChoice::Never,
+            denied_warnings: vec![WarningCode::NoEffectRule],
+            ..DisplayOptions::default()
+        });
+        report.file_skipped(Warning::skipped(
+            "src/a/b.clj",
+            "src/a/b.clj is generated, skipping".into(),
+        ));
+
+        assert_eq!(report.exit_status(), 0);
+
+        report.warn(Warning::new(
+            WarningCode::NoEffectRule,
+            "the rule for 'a.*' has no effect".into(),
+        ));
+
+        assert_eq!(report.exit_status(), 1);
+        assert!(report.to_string().contains("Found 1 denied warning"));
+    }
+
+    #[test]
+    fn json_warnings_have_a_code_and_path() {
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Json,
+            ..DisplayOptions::default()
+        });
+        report.file_skipped(Warning::skipped(
+            "src/a/b.clj",
+            "src/a/b.clj is generated, skipping".into(),
+        ));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.to_string()).unwrap();
+
+        assert_eq!(
+            json["warnings"],
+            json!([{
+                "code": "skipped-file",
+                "message": "src/a/b.clj is generated, skipping",
+                "path": "src/a/b.clj",
+            }])
+        );
+    }
+
+    #[test]
+    fn top_offenders_are_ranked_by_violations() {
+        let mut report = Report::new(DisplayOptions {
+            top: 1,
+            ..DisplayOptions::default()
+        });
+        report
+            .rules(vec!["a.*".into(), "b.*".into(), "c.*".into()].into_iter());
+        report.violation(violation(1));
+        report.violation(violation(0));
+        report.violation(Violation {
+            src_ns: "a.c".into(),
+            ..violation(0)
+        });
+
+        let (rules, namespaces) = report.top_offenders();
+
+        assert_eq!(rules, vec![("a.*", 2)]);
+        assert_eq!(namespaces, vec![("a.b", 2)]);
+    }
+
+    #[test]
+    fn junit_has_a_test_case_per_governed_namespace() {
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Junit,
+            ..DisplayOptions::default()
+        });
+        report.rules(vec!["a.*".into()].into_iter());
+        report.rule_matched(0, &source_file("a.b", "src/a/b.clj"));
+        report.rule_matched(0, &source_file("a.c", "src/a/c.clj"));
+        report.violation(violation(0));
+
+        let xml = report.to_string();
+
+        assert!(
+            xml.contains(r#"<testsuite name="a.*" tests="2" failures="1">"#)
+        );
+        assert!(xml.contains(
+            r#"<failure message="&apos;a.b&apos; is not allowed to reference &apos;c.d&apos;""#
+        ));
+        assert!(xml.contains(
+            r#"<testcase name="a.c" classname="a.*" file="src/a/c.clj"/>"#
+        ));
+    }
+
+    #[test]
+    fn reasons_are_reported_with_violations() {
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Json,
+            ..DisplayOptions::default()
+        });
+        report.rules(vec!["a.*".into()].into_iter());
+        report.violation(Violation {
+            reason: Some("needed for the migration".into()),
+            ..violation(0)
+        });
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.to_string()).unwrap();
+        assert_eq!(json["violations"][0]["reason"], "needed for the migration");
+        assert_eq!(
+            report.violations[0].help_with_reason().unwrap(),
+            "reason given: needed for the migration"
+        );
+    }
+
+    #[test]
+    fn ndjson_has_a_line_per_failure() {
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Ndjson,
+            ..DisplayOptions::default()
+        });
+        report.rules(vec!["a.*".into()].into_iter());
+        report.violation(violation(0));
+        report.ungoverned(&source_file("d.e", "src/d/e.clj"));
+
+        let lines: Vec<serde_json::Value> = report
+            .to_string()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                json!({
+                    "type": "violation",
+                    "path": "src/a/b.clj",
+                    "line": 1,
+                    "column": 19,
+                    "namespace": "a.b",
+                    "reference": "c.d",
+                    "fingerprint": "f22dd10410c0952c",
+                }),
+                json!({
+                    "type": "ungoverned",
+                    "namespace": "d.e",
+                    "path": "src/d/e.clj",
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn accepted_violations_are_not_reported_when_found() {
+        let mut report = Report::new(DisplayOptions::default());
+        report.rules(vec!["a.*".into()].into_iter());
+        report.accept(&BTreeSet::from([("a.b".into(), "c.d".into())]));
+
+        report.violation(violation(0));
+
+        assert_eq!(report.violation_count(), 0);
+    }
+
+    #[test]
+    fn accepted_violations_are_removed() {
+        let mut report = Report::new(DisplayOptions::default());
+        report.rules(vec!["a.*".into()].into_iter());
+        report.violation(violation(0));
+
+        report.accept(&BTreeSet::from([("a.b".into(), "c.d".into())]));
+
+        assert_eq!(report.violation_count(), 0);
+        assert_eq!(report.exit_status(), 0);
+    }
+
+    #[test]
+    fn tap_has_a_test_point_per_governed_namespace() {
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Tap,
+            ..DisplayOptions::default()
+        });
+        report.rules(vec!["a.*".into()].into_iter());
+        report.rule_matched(0, &source_file("a.b", "src/a/b.clj"));
+        report.rule_matched(0, &source_file("a.c", "src/a/c.clj"));
+        report.violation(violation(0));
+        report.ungoverned(&source_file("d.e", "src/d/e.clj"));
+
+        assert_eq!(
+            report.to_string(),
+            "TAP version 13\n\
+             1..3\n\
+             not ok 1 - a.b (a.*)\n\
+             \x20 ---\n\
+             \x20 file: \"src/a/b.clj\"\n\
+             \x20 violations:\n\
+             \x20   - reference: \"c.d\"\n\
+             \x20     at: \"src/a/b.clj:1:19\"\n\
+             \x20     message: \"'a.b' is not allowed to reference 'c.d'\"\n\
+             \x20 ...\n\
+             ok 2 - a.c (a.*)\n\
+             not ok 3 - d.e (coverage)\n\
+             \x20 ---\n\
+             \x20 file: \"src/d/e.clj\"\n\
+             \x20 message: \"not governed by any rule\"\n\
+             \x20 ...\n"
+        );
+    }
+
+    #[test]
+    fn fingerprints_ignore_the_position_of_the_reference() {
+        let moved = Violation {
+            src: NamedSource::new(
+                "src/a/b.clj",
+                "(ns a.b\n  (:require [c.d :as d]))".to_string(),
+            ),
+            line: 2,
+            column: 14,
+            snippet: (0, 32).into(),
+            ref_location: (20, 3).into(),
+            ..violation(0)
+        };
+
+        assert_eq!(moved.fingerprint("a.*"), violation(0).fingerprint("a.*"));
+        assert_ne!(
+            violation(0).fingerprint("a.*"),
+            violation(0).fingerprint("*")
+        );
+        assert_ne!(
+            Violation {
+                ref_ns: "c.e".into(),
+                ..violation(0)
+            }
+            .fingerprint("a.*"),
+            violation(0).fingerprint("a.*")
+        );
+    }
+
+    #[test]
+    fn codeclimate_issues_have_stable_fingerprints() {
+        let mut report = Report::new(DisplayOptions {
+            format: Format::Codeclimate,
+            ..DisplayOptions::default()
+        });
+        report.rules(vec!["a.*".into()].into_iter());
+        report.violation(violation(0));
+        report.violation(Violation {
+            line: 2,
+            ..violation(0)
+        });
+        report.ungoverned(&source_file("d.e", "src/d/e.clj"));
+
+        let issues: serde_json::Value =
+            serde_json::from_str(&report.to_string()).unwrap();
+
+        assert_eq!(
+            issues[0],
+            json!({
+                "type": "issue",
+                "check_name": "namespace_rule_violation",
+                "description": "'a.b' is not allowed to reference 'c.d'",
+                "categories": ["Style"],
+                "severity": "major",
+                "fingerprint": "f22dd10410c0952c",
+                "location": { "path": "src/a/b.clj", "lines": { "begin": 1 } },
+            })
+        );
+        assert_eq!(issues[1]["fingerprint"], issues[0]["fingerprint"]);
+        assert_eq!(issues[1]["location"]["lines"]["begin"], 2);
+        assert_eq!(issues[2]["check_name"], "ungoverned_namespace");
+        assert_ne!(issues[2]["fingerprint"], issues[0]["fingerprint"]);
+    }
+
+    #[test]
+    fn themes_are_not_used_without_colour() {
+        for theme in [Theme::Default, Theme::Light, Theme::Ansi] {
+            let options = DisplayOptions {
+                color: ColorChoice::Never,
+                theme,
+                ..DisplayOptions::default()
+            };
+
+            assert_eq!("a.b".style(options.ns_style()).to_string(), "a.b");
+        }
+    }
+
+    #[test]
+    fn themes_colour_the_namespace() {
+        let options = DisplayOptions {
+            color: ColorChoice::Always,
+            theme: Theme::Ansi,
+            ..DisplayOptions::default()
+        };
+
+        assert_eq!(
+            "a.b".style(options.ns_style()).to_string(),
+            "a.b".magenta().to_string()
+        );
+    }
+
+    #[test]
+    fn paths_are_shown_relative_to_the_working_directory() {
+        let working_dir = env::current_dir().unwrap();
+        let absolute = working_dir.join("src").join("core.clj");
+        let options = DisplayOptions::default();
+
+        assert_eq!(
+            options.display_path(absolute.to_str().unwrap()),
+            Path::new("src").join("core.clj").to_str().unwrap()
+        );
+        assert_eq!(options.display_path("src/core.clj"), "src/core.clj");
+    }
+
+    #[test]
+    fn absolute_paths_can_be_shown() {
+        let working_dir = env::current_dir().unwrap();
+        let options = DisplayOptions {
+            absolute_paths: true,
+            ..DisplayOptions::default()
+        };
+
+        assert_eq!(
+            options.display_path("core.clj"),
+            working_dir.join("core.clj").to_str().unwrap()
+        );
+    }
+}
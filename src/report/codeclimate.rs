@@ -0,0 +1,78 @@
+//! Prints a `Report` as a list of Code Climate issues, which GitLab shows as a
+//! code quality report on merge requests.
+//!
+//! Each violation is an issue, with the fingerprint of the violation, so that
+//! GitLab recognises the issue across runs while the code around it changes.
+//! Forbidden transitive dependencies and namespaces that no rule governs are
+//! issues too, fingerprinted by their namespaces and path.
+
+use super::{fingerprint, Report};
+use crate::Severity;
+use serde_json::json;
+use std::fmt;
+
+pub(super) fn fmt(report: &Report, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let violations = report.violations.iter().map(|violation| {
+        let mut issue = issue(
+            violation.check_name(),
+            &violation.to_string(),
+            report.fingerprint(violation),
+            &violation.path,
+            violation.line,
+        );
+        // Violations that don't fail the run are of less concern
+        if violation.severity == Severity::Warn {
+            issue["severity"] = "minor".into();
+        }
+        issue
+    });
+    let transitive = report.transitive.iter().map(|(path, chain)| {
+        let description =
+            format!("forbidden transitive dependency {}", chain.join(" -> "));
+        let mut parts: Vec<&str> = chain.iter().map(String::as_str).collect();
+        parts.push(path);
+        issue(
+            "forbidden_transitive_dependency",
+            &description,
+            fingerprint(&parts),
+            path,
+            1,
+        )
+    });
+    let ungoverned = report.ungoverned.iter().map(|(namespace, path)| {
+        let description =
+            format!("'{}' is not governed by any rule", namespace);
+        issue(
+            "ungoverned_namespace",
+            &description,
+            fingerprint(&[namespace, path]),
+            path,
+            1,
+        )
+    });
+
+    let issues: Vec<_> =
+        violations.chain(transitive).chain(ungoverned).collect();
+    writeln!(f, "{:#}", serde_json::Value::Array(issues))
+}
+
+fn issue(
+    check_name: &str,
+    description: &str,
+    fingerprint: String,
+    path: &str,
+    line: usize,
+) -> serde_json::Value {
+    json!({
+        "type": "issue",
+        "check_name": check_name,
+        "description": description,
+        "categories": ["Style"],
+        "severity": "major",
+        "fingerprint": fingerprint,
+        "location": {
+            "path": path,
+            "lines": { "begin": line },
+        },
+    })
+}
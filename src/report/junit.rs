@@ -0,0 +1,245 @@
+//! Prints a `Report` as JUnit XML, so that the checks are shown alongside the
+//! results of unit tests by CI systems.
+//!
+//! Each rule is a test suite, and each namespace that the rule governs is a
+//! test case, which fails if the namespace breaks the rule. Namespaces that no
+//! rule governs are a further test suite when coverage is required.
+//!
+//! The suites are either printed as a single document, or written one to a
+//! file, for CI systems that expect a file per suite.
+
+use super::{Report, Violation};
+use std::{collections::BTreeSet, fmt};
+
+/// A test case: the namespace, its path and its failures as XML.
+type TestCase<'r> = (&'r str, &'r str, String);
+
+/// A test suite: its name and its test cases.
+type TestSuite<'r> = (&'r str, Vec<TestCase<'r>>);
+
+pub(super) fn fmt(report: &Report, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    document(f, &suites(report))
+}
+
+/// Each test suite as a document of its own, along with the name of the file
+/// to write it to.
+pub(super) fn files(report: &Report) -> Vec<(String, String)> {
+    let mut taken = BTreeSet::new();
+    suites(report)
+        .into_iter()
+        .map(|suite| {
+            let name = file_name(suite.0, &mut taken);
+            let mut xml = String::new();
+            document(&mut xml, &[suite]).expect("strings can be written to");
+            (name, xml)
+        })
+        .collect()
+}
+
+/// The name of the file for the suite called `suite`, which is `TEST-`
+/// followed by the name, with any character that isn't safe in a file name
+/// replaced by `_`. Names that are `taken`, ignoring case, are numbered.
+fn file_name(suite: &str, taken: &mut BTreeSet<String>) -> String {
+    let sanitised: String = suite
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect();
+
+    let mut name = format!("TEST-{}.xml", sanitised);
+    let mut number = 1;
+    while !taken.insert(name.to_lowercase()) {
+        number += 1;
+        name = format!("TEST-{}-{}.xml", sanitised, number);
+    }
+
+    name
+}
+
+fn suites(report: &Report) -> Vec<TestSuite<'_>> {
+    let mut suites: Vec<TestSuite> = report
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(i, rule)| {
+            let cases = rule
+                .namespaces
+                .iter()
+                .map(|(namespace, path)| {
+                    let failures = report
+                        .violations
+                        .iter()
+                        .filter(|v| v.rule == i && v.src_ns == *namespace)
+                        .map(failure)
+                        .chain(
+                            report
+                                .transitive
+                                .iter()
+                                .filter(|(_, chain)| chain[0] == *namespace)
+                                .map(|(_, chain)| transitive_failure(chain)),
+                        )
+                        .collect();
+                    (namespace.as_str(), path.as_str(), failures)
+                })
+                .collect();
+            (rule.pattern.as_str(), cases)
+        })
+        .collect();
+
+    if !report.ungoverned.is_empty() {
+        let cases = report
+            .ungoverned
+            .iter()
+            .map(|(namespace, path)| {
+                let failure = format!(
+                    "      <failure message=\"{} is not governed by any \
+                     rule\"/>\n",
+                    escape(namespace)
+                );
+                (namespace.as_str(), path.as_str(), failure)
+            })
+            .collect();
+        suites.push(("coverage", cases));
+    }
+
+    suites
+}
+
+fn document(f: &mut impl fmt::Write, suites: &[TestSuite]) -> fmt::Result {
+    let failed = |cases: &[TestCase]| {
+        cases
+            .iter()
+            .filter(|(_, _, failures)| !failures.is_empty())
+            .count()
+    };
+    let tests: usize = suites.iter().map(|(_, cases)| cases.len()).sum();
+    let failures: usize = suites.iter().map(|(_, cases)| failed(cases)).sum();
+
+    writeln!(f, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        f,
+        r#"<testsuites name="ns-rules" tests="{}" failures="{}">"#,
+        tests, failures
+    )?;
+    for (name, cases) in suites.iter() {
+        writeln!(
+            f,
+            r#"  <testsuite name="{}" tests="{}" failures="{}">"#,
+            escape(name),
+            cases.len(),
+            failed(cases)
+        )?;
+        for (namespace, path, failures) in cases.iter() {
+            test_case(f, namespace, name, path, failures)?;
+        }
+        writeln!(f, "  </testsuite>")?;
+    }
+
+    writeln!(f, "</testsuites>")
+}
+
+fn test_case(
+    f: &mut impl fmt::Write,
+    namespace: &str,
+    class_name: &str,
+    path: &str,
+    failures: &str,
+) -> fmt::Result {
+    let attributes = format!(
+        r#"name="{}" classname="{}" file="{}""#,
+        escape(namespace),
+        escape(class_name),
+        escape(path)
+    );
+    if failures.is_empty() {
+        writeln!(f, "    <testcase {}/>", attributes)
+    } else {
+        writeln!(f, "    <testcase {}>", attributes)?;
+        f.write_str(failures)?;
+        writeln!(f, "    </testcase>")
+    }
+}
+
+fn failure(violation: &Violation) -> String {
+    let mut details = format!(
+        "{}:{}:{} {} -> {}",
+        violation.path,
+        violation.line,
+        violation.column,
+        violation.src_ns,
+        violation.ref_ns
+    );
+    if let Some(help) = violation.help_with_reason() {
+        details.push('\n');
+        details.push_str(&help);
+    }
+
+    format!(
+        "      <failure message=\"{}\" type=\"{}\">{}</failure>\n",
+        escape(&violation.to_string()),
+        violation.check_name(),
+        escape(&details)
+    )
+}
+
+fn transitive_failure(chain: &[String]) -> String {
+    let chain = chain.join(" -> ");
+    format!(
+        "      <failure message=\"forbidden transitive dependency {}\" \
+         type=\"transitive_violation\">{}</failure>\n",
+        escape(&chain),
+        escape(&chain)
+    )
+}
+
+/// `text` with the characters that are special in XML replaced by entities.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn file_names_are_safe_and_distinct() {
+        let mut taken = BTreeSet::new();
+
+        let names: Vec<_> = ["app.*", "src/**/api", "APP.*", "coverage"]
+            .iter()
+            .map(|suite| file_name(suite, &mut taken))
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "TEST-app._.xml",
+                "TEST-src____api.xml",
+                "TEST-APP._-2.xml",
+                "TEST-coverage.xml"
+            ]
+        );
+    }
+
+    #[test]
+    fn special_characters_are_escaped() {
+        assert_eq!(
+            escape(r#"'a<b>' & "c""#),
+            "&apos;a&lt;b&gt;&apos; &amp; &quot;c&quot;"
+        );
+    }
+}
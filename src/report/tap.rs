@@ -0,0 +1,106 @@
+//! Prints a `Report` in the Test Anything Protocol, for CI harnesses that
+//! consume TAP.
+//!
+//! Each namespace governed by a rule is a test point, which fails if the
+//! namespace breaks the rule, with the details of its violations in a YAML
+//! block. Namespaces that no rule governs fail when coverage is required.
+
+use super::Report;
+use std::fmt;
+
+pub(super) fn fmt(report: &Report, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let tests = report
+        .rules
+        .iter()
+        .map(|rule| rule.namespaces.len())
+        .sum::<usize>()
+        + report.ungoverned.len();
+
+    writeln!(f, "TAP version 13")?;
+    writeln!(f, "1..{}", tests)?;
+
+    let mut number = 0;
+    for (i, rule) in report.rules.iter().enumerate() {
+        for (namespace, path) in rule.namespaces.iter() {
+            number += 1;
+            let violations: Vec<_> = report
+                .violations
+                .iter()
+                .filter(|v| v.rule == i && v.src_ns == *namespace)
+                .collect();
+            let transitive: Vec<_> = report
+                .transitive
+                .iter()
+                .filter(|(_, chain)| chain[0] == *namespace)
+                .map(|(_, chain)| chain.join(" -> "))
+                .collect();
+
+            if violations.is_empty() && transitive.is_empty() {
+                writeln!(
+                    f,
+                    "ok {} - {} ({})",
+                    number, namespace, rule.pattern
+                )?;
+                continue;
+            }
+
+            writeln!(
+                f,
+                "not ok {} - {} ({})",
+                number, namespace, rule.pattern
+            )?;
+            writeln!(f, "  ---")?;
+            writeln!(f, "  file: {}", quote(path))?;
+            if !violations.is_empty() {
+                writeln!(f, "  violations:")?;
+                for violation in violations {
+                    writeln!(
+                        f,
+                        "    - reference: {}",
+                        quote(&violation.ref_ns)
+                    )?;
+                    writeln!(
+                        f,
+                        "      at: {}",
+                        quote(&format!(
+                            "{}:{}:{}",
+                            violation.path, violation.line, violation.column
+                        ))
+                    )?;
+                    writeln!(
+                        f,
+                        "      message: {}",
+                        quote(&violation.to_string())
+                    )?;
+                    if let Some(help) = violation.help_with_reason() {
+                        writeln!(f, "      help: {}", quote(&help))?;
+                    }
+                }
+            }
+            if !transitive.is_empty() {
+                writeln!(f, "  transitive:")?;
+                for chain in transitive {
+                    writeln!(f, "    - {}", quote(&chain))?;
+                }
+            }
+            writeln!(f, "  ...")?;
+        }
+    }
+
+    for (namespace, path) in report.ungoverned.iter() {
+        number += 1;
+        writeln!(f, "not ok {} - {} (coverage)", number, namespace)?;
+        writeln!(f, "  ---")?;
+        writeln!(f, "  file: {}", quote(path))?;
+        writeln!(f, "  message: \"not governed by any rule\"")?;
+        writeln!(f, "  ...")?;
+    }
+
+    Ok(())
+}
+
+/// `text` as a double quoted YAML scalar, which has the same escapes as a JSON
+/// string.
+fn quote(text: &str) -> String {
+    serde_json::Value::from(text).to_string()
+}
@@ -0,0 +1,309 @@
+//! Browses the violations of a `Report` in the terminal, for exploring a large
+//! set of them.
+//!
+//! The violations are listed by rule and then by namespace, and the one that
+//! is selected is drawn below the list as it would be in the report. The
+//! arrow keys, or `j` and `k`, move the selection and `q` quits.
+
+use super::{Report, Violation};
+use ansi_to_tui::IntoText;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use miette::{Diagnostic, DiagnosticReportPrinter, GraphicalReportPrinter};
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error, Diagnostic)]
+pub(crate) enum Error {
+    #[diagnostic(
+        code(tui_error),
+        help("run it in a terminal, or use --format to print the report")
+    )]
+    #[error("the violations can only be browsed in a terminal")]
+    NotATerminal,
+    #[diagnostic(code(tui_error))]
+    #[error("the terminal could not be controlled")]
+    Terminal {
+        #[from]
+        source: io::Error,
+    },
+}
+
+/// A line of the list of violations.
+#[derive(Debug)]
+enum Row<'r> {
+    Rule(&'r str),
+    Namespace(&'r str),
+    Violation(&'r Violation),
+}
+
+/// Lets the violations in `report` be browsed until the user quits.
+pub(crate) fn browse(report: &Report) -> Result<(), Error> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(Error::NotATerminal);
+    }
+    let rows = rows(report);
+    let selectable: Vec<usize> = rows
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| matches!(row, Row::Violation(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if selectable.is_empty() {
+        println!("There are no violations to browse");
+        return Ok(());
+    }
+
+    // Raw mode and the alternate screen, which leaves the shell as it was once
+    // the user quits, and is restored on a panic too
+    let mut terminal = ratatui::try_init()?;
+    let (mut selected, mut offset) = (0, 0);
+    let outcome = loop {
+        let height = match terminal.size() {
+            Ok(size) => usize::from(size.height),
+            Err(error) => break Err(error),
+        };
+        let list_height = list_height(height);
+        offset = scroll(offset, rows_of(&selectable, selected), list_height);
+        if let Err(error) = terminal.draw(|frame| {
+            draw(frame, report, &rows, selectable[selected], offset)
+        }) {
+            break Err(error);
+        }
+
+        // Escape sequences, such as those of the arrow keys, are read whole
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            Ok(_) => continue,
+            Err(error) => break Err(error),
+        };
+        let last = selectable.len() - 1;
+        selected = match key.code {
+            KeyCode::Char('c')
+                if key.modifiers.contains(KeyModifiers::CONTROL) =>
+            {
+                break Ok(())
+            }
+            KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+            KeyCode::Char('k') | KeyCode::Up => selected.saturating_sub(1),
+            KeyCode::Char('j') | KeyCode::Down => (selected + 1).min(last),
+            KeyCode::PageUp => selected.saturating_sub(list_height),
+            KeyCode::PageDown => (selected + list_height).min(last),
+            KeyCode::Char('g') | KeyCode::Home => 0,
+            KeyCode::Char('G') | KeyCode::End => last,
+            _ => selected,
+        };
+    };
+
+    ratatui::try_restore()?;
+    Ok(outcome?)
+}
+
+/// The violations in `report`, under the pattern of the rule that they break
+/// and the namespace that they are in, ordered by where they are in the file.
+fn rows(report: &Report) -> Vec<Row<'_>> {
+    let mut violations: Vec<&Violation> = report.violations.iter().collect();
+    violations.sort_by(|a, b| {
+        (a.rule, &a.src_ns, &a.path, a.ref_location.offset()).cmp(&(
+            b.rule,
+            &b.src_ns,
+            &b.path,
+            b.ref_location.offset(),
+        ))
+    });
+
+    let mut rows = Vec::new();
+    let (mut rule, mut namespace) = (None, None);
+    for violation in violations {
+        if rule != Some(violation.rule) {
+            rows.push(Row::Rule(&report.rules[violation.rule].pattern));
+            rule = Some(violation.rule);
+            namespace = None;
+        }
+        if namespace != Some(&violation.src_ns) {
+            rows.push(Row::Namespace(&violation.src_ns));
+            namespace = Some(&violation.src_ns);
+        }
+        rows.push(Row::Violation(violation));
+    }
+
+    rows
+}
+
+/// The rows from the first of the headings above the `selected` violation, if
+/// it is the first of its namespace, to the row of the violation itself.
+fn rows_of(selectable: &[usize], selected: usize) -> (usize, usize) {
+    let row = selectable[selected];
+    let first = selected.checked_sub(1).map_or(0, |i| selectable[i] + 1);
+    (first, row)
+}
+
+/// The first row to show, in a list `height` rows high, having moved as little
+/// as possible from `offset` to show `row`, and the headings above it from
+/// `first` when there is room.
+fn scroll(offset: usize, (first, row): (usize, usize), height: usize) -> usize {
+    let offset = if row >= offset + height {
+        row + 1 - height
+    } else {
+        offset
+    };
+    offset.min(first.max((row + 1).saturating_sub(height)))
+}
+
+/// The number of rows of the list, in a terminal `height` rows high.
+fn list_height(height: usize) -> usize {
+    (height / 2).max(1)
+}
+
+/// The whole screen: the list, from row `offset`, with the `selected` row
+/// highlighted, and the selected violation drawn beneath it. Lines too long
+/// for the terminal are cut off rather than wrapped.
+fn draw(
+    frame: &mut Frame,
+    report: &Report,
+    rows: &[Row],
+    selected: usize,
+    offset: usize,
+) {
+    let height = list_height(usize::from(frame.area().height));
+    let [list, violation, help] = Layout::vertical([
+        Constraint::Length(height as u16),
+        Constraint::Fill(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let lines: Vec<Line> = rows
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(height)
+        .map(|(i, row)| {
+            let line = Line::raw(match row {
+                Row::Rule(pattern) => pattern.to_string(),
+                Row::Namespace(namespace) => format!("  {}", namespace),
+                Row::Violation(violation) => format!(
+                    "    -> {} ({}:{})",
+                    violation.ref_ns, violation.path, violation.line
+                ),
+            });
+            if i == selected {
+                line.style(Style::new().reversed())
+            } else {
+                line
+            }
+        })
+        .collect();
+    frame.render_widget(Paragraph::new(lines), list);
+
+    if let Row::Violation(selected) = rows[selected] {
+        let drawn = Drawn(report, selected).to_string();
+        let text = drawn.into_text().unwrap_or_else(|_| Text::raw(drawn));
+        frame.render_widget(
+            Paragraph::new(text).block(Block::new().borders(Borders::TOP)),
+            violation,
+        );
+    }
+    frame.render_widget(
+        Line::raw("↑/↓ or j/k to move, PgUp/PgDn to page, q to quit"),
+        help,
+    );
+}
+
+/// A violation, drawn as it is in the graphical report.
+struct Drawn<'r>(&'r Report, &'r Violation);
+
+impl fmt::Display for Drawn<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        GraphicalReportPrinter::new_themed(self.0.options.theme())
+            .debug(self.1, f)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::report::{test::violation, DisplayOptions};
+    use ratatui::{backend::TestBackend, Terminal};
+
+    #[test]
+    fn violations_are_listed_by_rule_and_namespace() {
+        let mut report = Report::new(DisplayOptions::default());
+        report.rules(vec!["a.*".into(), "e.*".into()].into_iter());
+        report.violation(Violation {
+            src_ns: "e.f".into(),
+            ..violation(1)
+        });
+        report.violation(Violation {
+            ref_ns: "c.e".into(),
+            line: 2,
+            ref_location: (30, 3).into(),
+            ..violation(0)
+        });
+        report.violation(violation(0));
+
+        let rows: Vec<String> = rows(&report)
+            .iter()
+            .map(|row| match row {
+                Row::Rule(pattern) => pattern.to_string(),
+                Row::Namespace(namespace) => format!("  {}", namespace),
+                Row::Violation(violation) => {
+                    format!("    {}", violation.ref_ns)
+                }
+            })
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                "a.*", "  a.b", "    c.d", "    c.e", "e.*", "  e.f", "    c.d"
+            ]
+        );
+    }
+
+    #[test]
+    fn scrolling_shows_the_headings_of_the_first_violation() {
+        // Rule, namespace, violation, violation, namespace, violation
+        let selectable = [2, 3, 5];
+
+        assert_eq!(scroll(0, rows_of(&selectable, 0), 3), 0);
+        assert_eq!(scroll(0, rows_of(&selectable, 1), 3), 1);
+        assert_eq!(scroll(1, rows_of(&selectable, 2), 3), 3);
+        assert_eq!(scroll(3, rows_of(&selectable, 0), 3), 0);
+        // Without room for the headings, the violation itself is shown
+        assert_eq!(scroll(0, rows_of(&selectable, 0), 1), 2);
+    }
+
+    #[test]
+    fn long_lines_are_cut_off_at_the_edge_of_the_terminal() {
+        let mut report = Report::new(DisplayOptions::default());
+        report.rules(vec!["a.*".into()].into_iter());
+        report.violation(violation(0));
+        let rows = rows(&report);
+        let mut terminal = Terminal::new(TestBackend::new(12, 8)).unwrap();
+
+        terminal
+            .draw(|frame| draw(frame, &report, &rows, 2, 0))
+            .unwrap();
+
+        let screen: Vec<String> = terminal
+            .backend()
+            .buffer()
+            .content
+            .chunks(12)
+            .map(|line| line.iter().map(|cell| cell.symbol()).collect())
+            .collect();
+        assert_eq!(screen[0], "a.*         ");
+        assert_eq!(screen[2], "    -> c.d (");
+        assert_eq!(screen[7], "↑/↓ or j/k t");
+    }
+}
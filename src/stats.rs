@@ -0,0 +1,199 @@
+//! Aggregates the references between namespaces, independently of any rules,
+//! to help find namespaces that depend on, or are depended on by, too much.
+
+use crate::{ns, report::Warning, ClojureSourceFile, Encoding, Report};
+use serde_json::json;
+use std::{collections::BTreeMap, fmt};
+
+/// The number of namespaces listed for each of fan-in and fan-out.
+const TOP_N: usize = 10;
+
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    /// The number of references made by each namespace.
+    fan_out: BTreeMap<String, usize>,
+    /// The number of references made to each namespace.
+    fan_in: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    /// Counts the references made by every source file.
+    pub(crate) fn collect(
+        source_files: &[ClojureSourceFile],
+        encoding: Encoding,
+        report: &mut Report,
+    ) -> Self {
+        let mut stats = Self::default();
+        for file in source_files {
+            let code = match file.read(encoding, report) {
+                Some(code) => code,
+                None => continue,
+            };
+
+            let references = match ns::read_references(&code) {
+                Ok(Some(references)) => references,
+                Ok(None) => {
+                    report.file_skipped(Warning::skipped(
+                        file.path(),
+                        format!(
+                            "{} does not declare a namespace, skipping",
+                            file.path()
+                        ),
+                    ));
+                    continue;
+                }
+                Err(error) => {
+                    report.file_skipped(Warning::skipped(
+                        file.path(),
+                        format!(
+                            "failed to read the ns form of {}: {}",
+                            file.path(),
+                            error
+                        ),
+                    ));
+                    continue;
+                }
+            };
+
+            stats.add(file.namespace(), references);
+        }
+
+        stats
+    }
+
+    fn add(&mut self, namespace: &str, references: Vec<ns::Reference>) {
+        *self.fan_out.entry(namespace.to_owned()).or_default() +=
+            references.len();
+        for reference in references {
+            *self.fan_in.entry(reference.namespace).or_default() += 1;
+        }
+    }
+
+    /// The number of namespaces making each number of references.
+    fn histogram(&self) -> BTreeMap<usize, usize> {
+        let mut histogram = BTreeMap::new();
+        for count in self.fan_out.values() {
+            *histogram.entry(*count).or_default() += 1;
+        }
+
+        histogram
+    }
+
+    /// The `TOP_N` namespaces with the highest counts, ties broken by name.
+    fn top(counts: &BTreeMap<String, usize>) -> Vec<(&str, usize)> {
+        let mut top: Vec<_> = counts
+            .iter()
+            .map(|(namespace, count)| (namespace.as_str(), *count))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        top.truncate(TOP_N);
+
+        top
+    }
+
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let ranking = |counts| {
+            Self::top(counts)
+                .into_iter()
+                .map(|(namespace, count)| {
+                    json!({ "namespace": namespace, "references": count })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        json!({
+            "references": self.fan_out,
+            "histogram": self
+                .histogram()
+                .into_iter()
+                .map(|(references, namespaces)| {
+                    json!({ "references": references, "namespaces": namespaces })
+                })
+                .collect::<Vec<_>>(),
+            "fan-in": ranking(&self.fan_in),
+            "fan-out": ranking(&self.fan_out),
+        })
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Outgoing references per namespace:\n")?;
+        for (references, namespaces) in self.histogram() {
+            writeln!(
+                f,
+                "{:5} │ {} {}",
+                references,
+                "█".repeat(namespaces),
+                namespaces
+            )?;
+        }
+
+        writeln!(f, "\nMost referenced namespaces (fan-in):")?;
+        for (namespace, count) in Self::top(&self.fan_in) {
+            writeln!(f, "{:5}  {}", count, namespace)?;
+        }
+
+        writeln!(f, "\nNamespaces with the most references (fan-out):")?;
+        for (namespace, count) in Self::top(&self.fan_out) {
+            writeln!(f, "{:5}  {}", count, namespace)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn references(namespaces: &[&str]) -> Vec<ns::Reference> {
+        namespaces
+            .iter()
+            .map(|namespace| ns::Reference {
+                namespace: namespace.to_string(),
+                span: 0..0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn discarded_requires_are_not_counted() {
+        let file = ClojureSourceFile {
+            entry: "shipping.ui.porttests/fixtures/discarded_require.clj"
+                .into(),
+            path_start: "shipping.ui.port".len(),
+            tags: vec![],
+        };
+        let mut report = Report::new(Default::default());
+
+        let stats = Stats::collect(&[file], Encoding::Utf8, &mut report);
+
+        assert_eq!(stats.fan_out["shipping.ui.port"], 1);
+        assert_eq!(
+            stats.fan_in.keys().collect::<Vec<_>>(),
+            vec!["shipping.entity.port"]
+        );
+    }
+
+    #[test]
+    fn counts_fan_in_and_fan_out() {
+        let mut stats = Stats::default();
+        stats.add("app.core", references(&["app.db", "app.util"]));
+        stats.add("app.db", references(&["app.util"]));
+        stats.add("app.util", references(&[]));
+
+        assert_eq!(
+            Stats::top(&stats.fan_in),
+            vec![("app.util", 2), ("app.db", 1)]
+        );
+        assert_eq!(
+            Stats::top(&stats.fan_out),
+            vec![("app.core", 2), ("app.db", 1), ("app.util", 0)]
+        );
+        assert_eq!(
+            stats.histogram().into_iter().collect::<Vec<_>>(),
+            vec![(0, 1), (1, 1), (2, 1)]
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Suggests the additions to each rule's allow list that would make every
+//! current reference legal, to help adopt ns-rules in an existing codebase.
+
+use crate::{
+    ns, report::Warning, ClojureSourceFile, CompiledRule, Encoding, Forbidden,
+    Report, Severity,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+#[derive(Debug)]
+pub(crate) struct Suggestions<'r> {
+    rules: &'r [CompiledRule],
+    /// The namespaces to allow, by the index of the rule to allow them in.
+    additions: BTreeMap<usize, BTreeSet<String>>,
+    /// References that `:restrict-to` can't allow, as the rule forbids them by
+    /// other means.
+    unfixable: BTreeSet<(usize, String)>,
+}
+
+impl<'r> Suggestions<'r> {
+    /// Finds the namespaces that each rule would need to allow for every
+    /// source file to pass.
+    pub(crate) fn collect(
+        rules: &'r [CompiledRule],
+        source_files: &[ClojureSourceFile],
+        encoding: Encoding,
+        report: &mut Report,
+    ) -> Self {
+        let mut suggestions = Self {
+            rules,
+            additions: BTreeMap::new(),
+            unfixable: BTreeSet::new(),
+        };
+
+        for file in source_files {
+            let (index, rule) = match rules
+                .iter()
+                .enumerate()
+                .find(|(_, rule)| rule.matches(file))
+            {
+                Some(rule) => rule,
+                None => continue,
+            };
+
+            let code = match file.read(encoding, report) {
+                Some(code) => code,
+                None => continue,
+            };
+            let references = match ns::read_references(&code) {
+                Ok(references) => references.unwrap_or_default(),
+                Err(error) => {
+                    report.file_skipped(Warning::skipped(
+                        file.path(),
+                        format!(
+                            "failed to read the ns form of {}: {}",
+                            file.path(),
+                            error
+                        ),
+                    ));
+                    continue;
+                }
+            };
+
+            for reference in references {
+                match rule
+                    .why_forbidden_from(file.namespace(), &reference.namespace)
+                {
+                    Some(Forbidden::NotAllowed | Forbidden::External) => {
+                        suggestions
+                            .additions
+                            .entry(index)
+                            .or_default()
+                            .insert(reference.namespace);
+                    }
+                    Some(
+                        Forbidden::Denied(_)
+                        | Forbidden::InDeniedSrcDir(_)
+                        | Forbidden::DeniedTag(_)
+                        | Forbidden::Excluded(_),
+                    ) => {
+                        suggestions
+                            .unfixable
+                            .insert((index, reference.namespace));
+                    }
+                    None => {}
+                }
+            }
+        }
+
+        suggestions
+    }
+}
+
+impl fmt::Display for Suggestions<'_> {
+    /// Prints the suggestions as EDN, in the form of the `:rules` of the
+    /// configuration, so that they can be pasted over the existing rules.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, namespace) in self.unfixable.iter() {
+            writeln!(
+                f,
+                ";; '{}' is denied or excluded by the rule for '{}', so can't \
+                 be allowed by :restrict-to",
+                namespace, self.rules[*index].selector
+            )?;
+        }
+
+        f.write_str("{:rules [")?;
+        for (i, (index, additions)) in self.additions.iter().enumerate() {
+            let rule = &self.rules[*index];
+            let allowed = rule
+                .restrict_to()
+                .into_iter()
+                .chain(additions.iter().cloned())
+                .intersperse(" ".to_string())
+                .collect::<String>();
+
+            if i > 0 {
+                f.write_str("\n         ")?;
+            }
+            write!(f, "{} {{", rule.selector)?;
+            if let Some(when_path) = &rule.when_path {
+                write!(f, ":when-path {} ", when_path)?;
+            }
+            if let Some(tagged) = &rule.tagged {
+                write!(f, ":tagged {} ", tagged)?;
+            }
+            write!(f, ":restrict-to [{}]", allowed)?;
+            if !rule.deny.is_empty() {
+                let denied = rule
+                    .deny
+                    .iter()
+                    .map(ToString::to_string)
+                    .intersperse(" ".to_string())
+                    .collect::<String>();
+                write!(f, " :deny [{}]", denied)?;
+            }
+            if !rule.deny_src_dirs.is_empty() {
+                write!(
+                    f,
+                    " :deny-src-dirs [{}]",
+                    crate::quoted(&rule.deny_src_dirs)
+                )?;
+            }
+            if !rule.deny_tagged.is_empty() {
+                write!(
+                    f,
+                    " :deny-tagged [{}]",
+                    crate::keywords(&rule.deny_tagged)
+                )?;
+            }
+            if !rule.must_reference.is_empty() {
+                let required = rule
+                    .must_reference
+                    .iter()
+                    .map(ToString::to_string)
+                    .intersperse(" ".to_string())
+                    .collect::<String>();
+                write!(f, " :must-reference [{}]", required)?;
+            }
+            if rule.severity != Severity::Error {
+                write!(f, " :severity :{}", rule.severity)?;
+            }
+            if !rule.allow_self {
+                f.write_str(" :allow-self false")?;
+            }
+            if rule.allow_same_root {
+                f.write_str(" :allow-same-root true")?;
+            }
+            if rule.allow_parents {
+                f.write_str(" :allow-parents true")?;
+            }
+            if rule.no_external {
+                f.write_str(" :no-external true")?;
+            }
+            if !rule.tags.is_empty() {
+                write!(f, " :tags [{}]", crate::keywords(&rule.tags))?;
+            }
+            if let Some(doc) = &rule.doc {
+                write!(f, " :doc {:?}", doc)?;
+            }
+            f.write_str("}")?;
+        }
+        f.write_str("]}\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test::rule, Rule};
+
+    #[test]
+    fn suggestions_are_valid_edn() {
+        let rules = vec![Rule {
+            allow: vec!["com.acme.db".parse().unwrap()],
+            doc: Some("keep \"app\" thin".into()),
+            ..rule("com.acme.app")
+        }
+        .compile(&[])];
+        let mut suggestions = Suggestions {
+            rules: &rules,
+            additions: BTreeMap::new(),
+            unfixable: BTreeSet::new(),
+        };
+        suggestions.additions.insert(
+            0,
+            vec!["com.acme.util".to_string(), "com.acme.http".to_string()]
+                .into_iter()
+                .collect(),
+        );
+
+        let edn: edn_rs::Edn = suggestions.to_string().parse().unwrap();
+
+        assert_eq!(
+            edn,
+            r#"{:rules [com.acme.app {:restrict-to [com.acme.db
+                                                   com.acme.http
+                                                   com.acme.util]
+                                     :doc "keep \"app\" thin"}]}"#
+                .parse()
+                .unwrap()
+        );
+    }
+}
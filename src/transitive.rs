@@ -0,0 +1,148 @@
+//! Finds the namespaces that a namespace depends on through other namespaces,
+//! when its rule forbids it from depending on them at all.
+//!
+//! A transitive dependency breaks a rule when the rule would forbid a direct
+//! reference to it. The namespaces in between need not be allowed by the rule,
+//! as it is only the namespace at the end of the chain that is checked, and a
+//! namespace that is referenced directly is left to the direct check.
+
+use crate::{ns, ClojureSourceFile, CompiledRule, Encoding, Report};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// The namespaces referenced by each namespace in the source tree.
+type Graph = BTreeMap<String, BTreeSet<String>>;
+
+/// Checks every source file against the rule that governs it, if the rule has
+/// one of `tags` or no tags were given, reporting each forbidden transitive
+/// dependency along with the chain of references that leads to it.
+pub(crate) fn check(
+    rules: &[CompiledRule],
+    source_files: &[ClojureSourceFile],
+    encoding: Encoding,
+    tags: &[String],
+    report: &mut Report,
+) {
+    let graph = build_graph(source_files, encoding);
+
+    for file in source_files {
+        let rule = match rules.iter().find(|rule| rule.matches(file)) {
+            Some(rule) if rule.has_any_tag(tags) => rule,
+            _ => continue,
+        };
+
+        for chain in forbidden_chains(rule, file.namespace(), &graph) {
+            report.transitive_violation(file, chain);
+        }
+    }
+}
+
+/// Reads the references of every source file. Files that can't be read are
+/// left out of the graph, having already been reported by the direct check.
+fn build_graph(
+    source_files: &[ClojureSourceFile],
+    encoding: Encoding,
+) -> Graph {
+    let mut ignored = Report::new(Default::default());
+    source_files
+        .iter()
+        .filter_map(|file| {
+            let code = file.read(encoding, &mut ignored)?;
+            let references = ns::read_references(&code).ok()??;
+            let references = references
+                .into_iter()
+                .map(|reference| reference.namespace)
+                .collect();
+            Some((file.namespace().to_owned(), references))
+        })
+        .collect()
+}
+
+/// The shortest chain of references, starting at `namespace`, to each
+/// namespace that `rule` forbids and that `namespace` only depends on through
+/// other namespaces.
+fn forbidden_chains(
+    rule: &CompiledRule,
+    namespace: &str,
+    graph: &Graph,
+) -> Vec<Vec<String>> {
+    // The namespace from which each namespace was first reached, so that the
+    // chain can be followed back to the start.
+    let mut reached_from: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut queue = VecDeque::from([(namespace, 0)]);
+    let mut chains = Vec::new();
+
+    while let Some((current, depth)) = queue.pop_front() {
+        for next in graph.get(current).into_iter().flatten() {
+            if next == namespace || reached_from.contains_key(next.as_str()) {
+                continue;
+            }
+            reached_from.insert(next, current);
+            queue.push_back((next, depth + 1));
+
+            if depth > 0 && rule.why_forbidden_from(namespace, next).is_some() {
+                let mut chain = vec![next.clone()];
+                let mut link = current;
+                while link != namespace {
+                    chain.push(link.to_owned());
+                    link = reached_from[link];
+                }
+                chain.push(namespace.to_owned());
+                chain.reverse();
+                chains.push(chain);
+            }
+        }
+    }
+
+    chains
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test::rule, Rule};
+
+    fn graph(edges: &[(&str, &[&str])]) -> Graph {
+        edges
+            .iter()
+            .map(|(from, to)| {
+                (
+                    from.to_string(),
+                    to.iter().map(ToString::to_string).collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn api_rule() -> CompiledRule {
+        Rule {
+            allow: vec!["app.service".parse().unwrap()],
+            deny: vec!["java.sql".parse().unwrap()],
+            ..rule("app.api")
+        }
+        .compile(&[])
+    }
+
+    #[test]
+    fn reports_the_chain_to_forbidden_dependencies() {
+        let graph = graph(&[
+            ("app.api", &["app.service"]),
+            ("app.service", &["app.db"]),
+            ("app.db", &["java.sql"]),
+        ]);
+
+        assert_eq!(
+            forbidden_chains(&api_rule(), "app.api", &graph),
+            vec![vec!["app.api", "app.service", "app.db", "java.sql"]]
+        );
+    }
+
+    #[test]
+    fn direct_references_are_left_to_the_direct_check() {
+        let graph = graph(&[
+            ("app.api", &["app.service", "java.sql"]),
+            ("app.service", &["java.sql"]),
+        ]);
+
+        assert!(forbidden_chains(&api_rule(), "app.api", &graph).is_empty());
+    }
+}
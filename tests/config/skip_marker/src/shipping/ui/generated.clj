@@ -0,0 +1,3 @@
+;; GENERATED - DO NOT EDIT
+(ns shipping.ui.generated
+  (:require [shipping.service.database :as db]))
@@ -0,0 +1,3 @@
+(ns shipping.ui.legacy
+  "Entrept"
+  (:require [shipping.service.stock :as stock]))
@@ -0,0 +1,2 @@
+(ns shipping.ui.port
+  (:require [shipping.service.ports :as ports]))
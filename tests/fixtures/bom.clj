@@ -0,0 +1,4 @@
+﻿(ns bom (:require [clojure.string :as str]))
+
+(defn shout [s]
+  (str/upper-case s))
@@ -0,0 +1,17 @@
+(ns shipping.ui.voyage
+  (:require ;; The entities come first
+            [shipping.entity.voyage :as voyage] ; the aggregate root
+            ;; [shipping.entity.legacy :as legacy]
+            [shipping.entity.port ; ports are looked up by code
+             :as port]
+            ; (shipping.infra [db :as db])
+            (shipping.ui
+             ;; Shared widgets
+             [table :as table]
+             ;; forms
+             form)
+            shipping.ui.format ;; [shipping.ui.theme]
+            ))
+
+(defn render [v]
+  (table/render (voyage/legs v) (port/name (voyage/origin v))))
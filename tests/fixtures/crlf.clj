@@ -0,0 +1,7 @@
+;; This file has Windows line endings.
+(ns crlf
+  (:require [clojure.string :as str]
+            [crlf.other]))
+
+(defn shout [s]
+  (str/upper-case s))
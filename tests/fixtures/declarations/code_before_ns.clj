@@ -0,0 +1,11 @@
+#!/usr/bin/env bb
+;; A script that sets up its classpath before declaring its namespace.
+
+(require '[babashka.deps :as deps])
+(deps/add-deps '{:deps {com.acme/db {:local/root "../db"}}})
+
+(ns com.acme.scripts.migrate
+  (:require [com.acme.db :as db]
+            [clojure.string :as str]))
+
+(db/migrate!)
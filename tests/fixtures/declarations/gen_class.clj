@@ -0,0 +1,16 @@
+(ns ^{:author "Nick Ogden"
+      :see-also [shipping.entity.cargo]}
+  shipping.entity.port
+  "Ports, and the ships that dock at them. Unlike shipping.entity.berth, a
+  port can be referenced from (:require shipping.entity.dock)."
+  {:added "1.0"
+   :superseded-by shipping.entity.harbour}
+  (:gen-class
+   :name shipping.entity.Port
+   :extends shipping.entity.Location
+   :implements [shipping.entity.Dockable])
+  (:refer-clojure :exclude [shipping.entity.route])
+  (:require [shipping.entity.ship :as ship]))
+
+(defn dock [port ship]
+  (ship/moor ship port))
@@ -0,0 +1,4 @@
+(in-ns 'com.acme.app)
+
+(defn helper []
+  (com.acme.db/connect))
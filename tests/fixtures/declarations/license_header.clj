@@ -0,0 +1,17 @@
+;; Copyright (c) Acme Corp. All rights reserved.
+;;
+;; Licensed under the Eclipse Public License 2.0. By using this software in any
+;; fashion, you are agreeing to be bound by the terms of this license.
+;; (ns com.acme.licensed)
+
+(comment
+  ;; Evaluate in a REPL to try the report out against a scratch database
+  (ns com.acme.scratch
+    (:require [com.acme.db :as db]))
+  (db/connect! "jdbc:h2:mem:scratch"))
+
+(ns com.acme.scripts.report
+  (:require [clojure.string :as str]))
+
+(defn -main [& args]
+  (println (str/join " " args)))
@@ -0,0 +1,9 @@
+(ns com.acme.first
+  (:require [com.acme.db :as db]))
+
+(def conn (db/connect))
+
+(ns com.acme.second
+  (:require [com.acme.http :as http]))
+
+(def server (http/start))
@@ -0,0 +1,3 @@
+;; Generated by the build, has no namespace of its own.
+
+(def version "1.2.3")
@@ -0,0 +1,12 @@
+(ns com.acme.scratch
+  (:require [com.acme.db :as db]))
+
+(def conn (db/connect))
+
+(in-ns 'com.acme.user)
+
+(ns com.acme.api
+  (:require [com.acme.db :as db]
+            [com.acme.http :as http]))
+
+(def server (http/start conn))
@@ -0,0 +1,8 @@
+(ns deep.qualified
+  (:require [shipping.entity.port :as port]))
+
+(defn handle [request]
+  (let [save (requiring-resolve 'shipping.service.database/save!)]
+    (save (port/name (:port request)))
+    (shipping.service.database/flush!)
+    (java.lang.Math/abs -1)))
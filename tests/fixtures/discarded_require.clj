@@ -0,0 +1,8 @@
+(ns shipping.ui.port
+  (:require #_[com.acme.old :as old]
+            [shipping.entity.port :as port]
+            #_#_com.acme.legacy com.acme.legacy-util
+            #_(com.acme [older :as older])))
+
+(defn render [p]
+  (port/name p))
@@ -0,0 +1,4 @@
+;; GENERATED - DO NOT EDIT
+;; Generated from shipping.proto
+(ns shipping.ui.generated
+  (:require [shipping.service.database :as db]))
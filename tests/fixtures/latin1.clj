@@ -0,0 +1,3 @@
+;; Cr par l'quipe logistique.
+(ns shipping.legacy.douane
+  (:require [shipping.entity.port :as port]))
@@ -0,0 +1,4 @@
+(ns com.acme.app
+  (:require [com.my-org.core :as core]))
+
+(defn -main [] (core/greet))
@@ -0,0 +1,3 @@
+(ns com.my-org.core)
+
+(defn greet [] "hello")
@@ -0,0 +1,8 @@
+(ns com.acme.app
+  (:require (com.acme core util [db :as d])
+            [clojure string [set :as set]]
+            [com.acme.logging :as log]))
+
+(defn start []
+  (log/info "starting")
+  (d/connect (util/config)))
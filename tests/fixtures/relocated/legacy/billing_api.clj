@@ -0,0 +1,5 @@
+(ns billing.api
+  (:require [billing.db :as db]))
+
+(defn invoice [id]
+  (db/fetch id))
@@ -0,0 +1,3 @@
+;; Scratch code, with no ns form
+
+(println "hello")
@@ -0,0 +1,5 @@
+(ns shipping.port
+  (:require [shipping.ship :as ship]
+            [shipping.fixtures :as fixtures]))
+
+(def default-port (fixtures/port-with (ship/ship)))
@@ -0,0 +1,3 @@
+(ns shipping.ship)
+
+(defn ship [] {:name "Clipper"})
@@ -0,0 +1,4 @@
+(ns shipping.fixtures
+  (:require [shipping.ship :as ship]))
+
+(defn port-with [ship] {:ships [ship]})
@@ -0,0 +1,4 @@
+(ns ^:internal ^{:no-doc true :added "1.2"} tagged.internal
+  "Implementation details, not to be used outside of tagged.*")
+
+(defn secret [] 42)
@@ -0,0 +1,4 @@
+(ns ^{:internal false} tagged.public
+  (:require [tagged.internal :as internal]))
+
+(defn answer [] (internal/secret))